@@ -0,0 +1,88 @@
+#[cfg(target_os = "windows")]
+use winapi::um::wbemcli::{
+    CIMTYPE, CIM_BOOLEAN, CIM_CHAR16, CIM_DATETIME, CIM_EMPTY, CIM_FLAG_ARRAY, CIM_OBJECT,
+    CIM_REAL32, CIM_REAL64, CIM_REFERENCE, CIM_SINT16, CIM_SINT32, CIM_SINT64, CIM_SINT8,
+    CIM_STRING, CIM_UINT16, CIM_UINT32, CIM_UINT64, CIM_UINT8,
+};
+
+/// The declared (CIM) type of a property.
+///
+/// This can differ from the type of the returned `Variant`: for example, `uint32` properties
+/// are returned as `VT_I4`, and `uint64` and `datetime` properties are returned as strings.
+///
+/// See: https://docs.microsoft.com/en-us/windows/win32/api/wbemcli/ne-wbemcli-tag_cimtype_enumeration
+///
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum CimType {
+    Empty,
+    SInt8,
+    UInt8,
+    SInt16,
+    UInt16,
+    SInt32,
+    UInt32,
+    SInt64,
+    UInt64,
+    Real32,
+    Real64,
+    Boolean,
+    String,
+    DateTime,
+    Reference,
+    Char16,
+    Object,
+    /// An array of elements of the given type.
+    Array(Box<CimType>),
+    /// A type which is not known to this crate (the raw `CIMTYPE`).
+    Unknown(i32),
+}
+
+#[cfg(target_os = "windows")]
+impl CimType {
+    pub fn from_raw(raw: CIMTYPE) -> Self {
+        let raw_u32 = raw as u32;
+
+        if raw_u32 & CIM_FLAG_ARRAY == CIM_FLAG_ARRAY {
+            let item_type = (raw_u32 & !CIM_FLAG_ARRAY) as CIMTYPE;
+
+            return CimType::Array(Box::new(CimType::from_raw(item_type)));
+        }
+
+        match raw_u32 {
+            CIM_EMPTY => CimType::Empty,
+            CIM_SINT8 => CimType::SInt8,
+            CIM_UINT8 => CimType::UInt8,
+            CIM_SINT16 => CimType::SInt16,
+            CIM_UINT16 => CimType::UInt16,
+            CIM_SINT32 => CimType::SInt32,
+            CIM_UINT32 => CimType::UInt32,
+            CIM_SINT64 => CimType::SInt64,
+            CIM_UINT64 => CimType::UInt64,
+            CIM_REAL32 => CimType::Real32,
+            CIM_REAL64 => CimType::Real64,
+            CIM_BOOLEAN => CimType::Boolean,
+            CIM_STRING => CimType::String,
+            CIM_DATETIME => CimType::DateTime,
+            CIM_REFERENCE => CimType::Reference,
+            CIM_CHAR16 => CimType::Char16,
+            CIM_OBJECT => CimType::Object,
+            _ => CimType::Unknown(raw),
+        }
+    }
+}
+
+#[cfg(all(test, target_os = "windows"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_converts_raw_cim_types() {
+        assert_eq!(CimType::from_raw(CIM_UINT64 as CIMTYPE), CimType::UInt64);
+        assert_eq!(CimType::from_raw(CIM_STRING as CIMTYPE), CimType::String);
+        assert_eq!(
+            CimType::from_raw((CIM_FLAG_ARRAY | CIM_UINT16) as CIMTYPE),
+            CimType::Array(Box::new(CimType::UInt16))
+        );
+        assert_eq!(CimType::from_raw(0x42), CimType::Unknown(0x42));
+    }
+}
@@ -0,0 +1,89 @@
+//! Explicit WMI class metadata for a Rust type.
+//!
+//! The regular query methods infer the class and property names from the type's
+//! `serde` implementation. Instead, a type can implement [`WMIClass`], usually using
+//! `#[derive(WMIClass)]` (which requires the `derive` feature), and be queried with
+//! [`WMIConnection::query_wmi_class`](crate::WMIConnection::query_wmi_class).
+//!
+//! The derive uses the same names as `#[derive(Deserialize)]`: the class name is taken from
+//! `#[wmi(class = "...")]` or `#[serde(rename = "...")]` (or the type's name), and the property
+//! names from the fields (with `#[serde(rename = "...")]` and `#[serde(rename_all = "PascalCase")]`).
+//! Unsupported `serde` attributes (like `flatten`) are a compile error.
+//!
+//! ```edition2018,ignore
+//! use serde::Deserialize;
+//! use wmi::WMIClass;
+//!
+//! #[derive(Deserialize, WMIClass)]
+//! #[serde(rename = "Win32_Process")]
+//! #[serde(rename_all = "PascalCase")]
+//! struct Process {
+//!     process_id: u32,
+//!     #[serde(rename = "Name")]
+//!     executable: String,
+//! }
+//!
+//! assert_eq!(Process::wmi_class_name(), "Win32_Process");
+//! assert_eq!(Process::wmi_property_names(), ["ProcessId", "Name"]);
+//! ```
+//!
+
+/// The WMI class and property names of a type.
+///
+pub trait WMIClass {
+    /// The name of the class (like `Win32_Process`).
+    fn wmi_class_name() -> &'static str;
+
+    /// The names of the properties, in the order of the type's fields.
+    fn wmi_property_names() -> &'static [&'static str];
+}
+
+#[cfg(all(test, feature = "derive"))]
+mod tests {
+    use crate::tests::fixtures::*;
+    use crate::WMIClass;
+    use serde::Deserialize;
+
+    #[derive(Deserialize, WMIClass, Debug)]
+    #[serde(rename = "Win32_Process")]
+    #[serde(rename_all = "PascalCase")]
+    struct Process {
+        process_id: u32,
+        #[serde(rename = "Name")]
+        executable: String,
+        #[serde(rename = "WorkingSetSize")]
+        working_set: u64,
+    }
+
+    #[derive(Deserialize, WMIClass, Debug)]
+    #[wmi(class = "Win32_OperatingSystem")]
+    #[allow(non_snake_case)]
+    struct OS {
+        Caption: String,
+    }
+
+    #[test]
+    fn it_derives_class_metadata() {
+        assert_eq!(Process::wmi_class_name(), "Win32_Process");
+        assert_eq!(
+            Process::wmi_property_names(),
+            ["ProcessId", "Name", "WorkingSetSize"]
+        );
+
+        assert_eq!(OS::wmi_class_name(), "Win32_OperatingSystem");
+        assert_eq!(OS::wmi_property_names(), ["Caption"]);
+    }
+
+    #[test]
+    fn it_queries_a_derived_class() {
+        let wmi_con = wmi_con();
+
+        let processes: Vec<Process> = wmi_con.query_wmi_class().unwrap();
+
+        assert!(processes.iter().any(|p| p.executable == "System"));
+
+        let os: Vec<OS> = wmi_con.query_wmi_class().unwrap();
+
+        assert_eq!(os[0].Caption, "Microsoft Windows 10 Pro");
+    }
+}
@@ -0,0 +1,226 @@
+//! Ready-made structs for some of the most commonly queried classes.
+//!
+//! Only available with the `common-classes` feature.
+//!
+//! Each struct has the commonly used properties of its class (not all of them),
+//! with the types used by WMI: `uint64` properties are `u64`s, `datetime`s are [`WMIDateTime`]s,
+//! and properties which can be null are `Option`s.
+//! For other properties, copy a struct and add the missing fields.
+//!
+//! ```edition2018
+//! # use wmi::*;
+//! # let con = WMIConnection::new(COMLibrary::new().unwrap().into()).unwrap();
+//! use wmi::classes::{OperatingSystem, Process};
+//!
+//! let os: OperatingSystem = con.get().unwrap();
+//! let processes: Vec<Process> = con.query().unwrap();
+//! ```
+//!
+use crate::datetime::WMIDateTime;
+use serde::Deserialize;
+
+/// [`Win32_OperatingSystem`](https://docs.microsoft.com/en-us/windows/win32/cimwin32prov/win32-operatingsystem)
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename = "Win32_OperatingSystem")]
+#[serde(rename_all = "PascalCase")]
+pub struct OperatingSystem {
+    pub caption: String,
+    pub version: String,
+    pub build_number: String,
+    #[serde(rename = "OSArchitecture")]
+    pub os_architecture: Option<String>,
+    #[serde(rename = "CSName")]
+    pub cs_name: String,
+    pub serial_number: Option<String>,
+    pub install_date: WMIDateTime,
+    pub last_boot_up_time: WMIDateTime,
+    pub local_date_time: WMIDateTime,
+    /// The offset from UTC, in minutes.
+    pub current_time_zone: i16,
+    /// In kilobytes.
+    pub total_visible_memory_size: u64,
+    /// In kilobytes.
+    pub free_physical_memory: u64,
+    pub number_of_processes: u32,
+    pub number_of_users: u32,
+    #[serde(rename = "OSType")]
+    pub os_type: u16,
+    pub product_type: u32,
+    pub system_directory: String,
+    pub windows_directory: String,
+}
+
+/// [`Win32_Process`](https://docs.microsoft.com/en-us/windows/win32/cimwin32prov/win32-process)
+///
+/// Some properties (like the `ExecutablePath` and `CommandLine`) are null
+/// for processes which the current user can't access.
+///
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename = "Win32_Process")]
+#[serde(rename_all = "PascalCase")]
+pub struct Process {
+    pub name: String,
+    pub process_id: u32,
+    pub parent_process_id: u32,
+    pub executable_path: Option<String>,
+    pub command_line: Option<String>,
+    pub creation_date: Option<WMIDateTime>,
+    pub session_id: u32,
+    pub priority: u32,
+    pub thread_count: u32,
+    pub handle_count: u32,
+    /// In bytes.
+    pub working_set_size: u64,
+    /// In bytes.
+    pub virtual_size: u64,
+    /// In 100 nanosecond units.
+    pub kernel_mode_time: u64,
+    /// In 100 nanosecond units.
+    pub user_mode_time: u64,
+}
+
+/// [`Win32_LogicalDisk`](https://docs.microsoft.com/en-us/windows/win32/cimwin32prov/win32-logicaldisk)
+///
+/// The size properties are null for drives without media (like an empty DVD drive).
+///
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename = "Win32_LogicalDisk")]
+#[serde(rename_all = "PascalCase")]
+pub struct LogicalDisk {
+    #[serde(rename = "DeviceID")]
+    pub device_id: String,
+    /// `2` for a removable disk, `3` for a local disk, `4` for a network drive etc.
+    pub drive_type: u32,
+    pub media_type: Option<u32>,
+    pub description: Option<String>,
+    pub file_system: Option<String>,
+    pub volume_name: Option<String>,
+    pub volume_serial_number: Option<String>,
+    /// The UNC path of a network drive.
+    pub provider_name: Option<String>,
+    /// In bytes.
+    pub size: Option<u64>,
+    /// In bytes.
+    pub free_space: Option<u64>,
+}
+
+/// [`Win32_Service`](https://docs.microsoft.com/en-us/windows/win32/cimwin32prov/win32-service)
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename = "Win32_Service")]
+#[serde(rename_all = "PascalCase")]
+pub struct Service {
+    pub name: String,
+    pub display_name: Option<String>,
+    pub description: Option<String>,
+    /// For example, `Running` or `Stopped`.
+    pub state: String,
+    /// For example, `Auto`, `Manual` or `Disabled`.
+    pub start_mode: String,
+    pub status: String,
+    pub started: bool,
+    pub accept_stop: bool,
+    pub accept_pause: bool,
+    pub service_type: String,
+    pub path_name: Option<String>,
+    /// The account the service runs as.
+    pub start_name: Option<String>,
+    /// `0` if the service isn't running.
+    pub process_id: u32,
+    pub exit_code: u32,
+}
+
+/// [`Win32_ComputerSystem`](https://docs.microsoft.com/en-us/windows/win32/cimwin32prov/win32-computersystem)
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename = "Win32_ComputerSystem")]
+#[serde(rename_all = "PascalCase")]
+pub struct ComputerSystem {
+    pub name: String,
+    pub domain: String,
+    pub part_of_domain: bool,
+    pub workgroup: Option<String>,
+    pub domain_role: u16,
+    pub manufacturer: String,
+    pub model: String,
+    pub system_type: String,
+    #[serde(rename = "PCSystemType")]
+    pub pc_system_type: u16,
+    pub number_of_processors: u32,
+    pub number_of_logical_processors: u32,
+    /// In bytes.
+    pub total_physical_memory: u64,
+    /// The user logged on to the console, if any.
+    pub user_name: Option<String>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::fixtures::*;
+
+    #[test]
+    fn it_desr_operating_system() {
+        let wmi_con = wmi_con();
+
+        let os: OperatingSystem = wmi_con.get().unwrap();
+
+        assert_eq!(os.caption, "Microsoft Windows 10 Pro");
+        assert!(os.build_number.parse::<u32>().is_ok());
+        assert!(os.last_boot_up_time > os.install_date);
+        assert!(os.total_visible_memory_size >= os.free_physical_memory);
+        assert_eq!(os.os_type, 18);
+    }
+
+    #[test]
+    fn it_desr_processes() {
+        let wmi_con = wmi_con();
+
+        let processes: Vec<Process> = wmi_con.query().unwrap();
+
+        assert!(processes
+            .iter()
+            .any(|process| process.process_id == 4 && process.name == "System"));
+        assert!(processes
+            .iter()
+            .any(|process| process.name == "cargo.exe" && process.working_set_size > 0));
+    }
+
+    #[test]
+    fn it_desr_logical_disks() {
+        let wmi_con = wmi_con();
+
+        let disks: Vec<LogicalDisk> = wmi_con.query().unwrap();
+
+        let c = disks.iter().find(|disk| disk.device_id == "C:").unwrap();
+
+        assert_eq!(c.drive_type, 3);
+        assert!(c.size.unwrap() >= c.free_space.unwrap());
+        assert_eq!(c.file_system.as_deref(), Some("NTFS"));
+    }
+
+    #[test]
+    fn it_desr_services() {
+        let wmi_con = wmi_con();
+
+        let services: Vec<Service> = wmi_con.query().unwrap();
+
+        let winmgmt = services
+            .iter()
+            .find(|service| service.name == "Winmgmt")
+            .unwrap();
+
+        assert_eq!(winmgmt.state, "Running");
+        assert!(winmgmt.started);
+        assert_ne!(winmgmt.process_id, 0);
+    }
+
+    #[test]
+    fn it_desr_computer_system() {
+        let wmi_con = wmi_con();
+
+        let system: ComputerSystem = wmi_con.get().unwrap();
+
+        assert!(system.total_physical_memory > 0);
+        assert!(system.number_of_logical_processors >= system.number_of_processors);
+        assert!(!system.name.is_empty());
+    }
+}
@@ -1,17 +1,19 @@
-use crate::utils::check_hres;
-use failure::Error;
-use log::debug;
+use crate::utils::{check_hres, WMIError};
+use failure::{bail, Error};
+use log::{debug, warn};
+use std::cell::Cell;
 use std::ptr;
 use std::ptr::NonNull;
 use std::rc::Rc;
 use widestring::WideCString;
 use winapi::{
     shared::{
-        ntdef::NULL,
+        ntdef::{HRESULT, NULL},
         rpcdce::{
             RPC_C_AUTHN_LEVEL_CALL, RPC_C_AUTHN_LEVEL_DEFAULT, RPC_C_AUTHN_WINNT, RPC_C_AUTHZ_NONE,
             RPC_C_IMP_LEVEL_IMPERSONATE,
         },
+        winerror::{RPC_E_CHANGED_MODE, S_FALSE, S_OK},
         wtypesbase::CLSCTX_INPROC_SERVER,
     },
     um::{
@@ -19,42 +21,135 @@ use winapi::{
             CoCreateInstance, CoInitializeEx, CoInitializeSecurity, CoSetProxyBlanket,
             CoUninitialize,
         },
-        objbase::COINIT_MULTITHREADED,
+        objbase::{COINIT, COINIT_APARTMENTTHREADED, COINIT_MULTITHREADED},
         objidl::EOAC_NONE,
+        unknwnbase::IUnknown,
         wbemcli::{CLSID_WbemLocator, IID_IWbemLocator, IWbemLocator, IWbemServices},
     },
 };
 
 pub struct COMLibrary {}
 
+thread_local! {
+    // The number of live `COMLibrary` instances created on this thread.
+    static COM_LIBRARIES: Cell<usize> = const { Cell::new(0) };
+}
+
 /// Initialize COM.
 ///
 /// COM will be `CoUninitialize`d after this object is dropped.
 ///
+/// By default, the calling thread joins the multithreaded apartment (MTA).
+/// Applications which already run in a single-threaded apartment (like most GUI threads)
+/// can use [`new_sta`](COMLibrary::new_sta) instead, since a thread can't change its apartment
+/// once COM was initialized.
+///
 impl COMLibrary {
     /// `CoInitialize`s the COM library for use by the calling thread.
     ///
-    pub fn new() -> Result<Self, Error> {
-        unsafe { check_hres(CoInitializeEx(ptr::null_mut(), COINIT_MULTITHREADED))? }
+    /// If COM (or its security) can't be initialized, the failing `HRESULT` is returned
+    /// as a [`WMIError::HResultError`](crate::utils::WMIError::HResultError).
+    ///
+    pub fn new() -> Result<Self, WMIError> {
+        Self::new_mta()
+    }
 
-        let instance = Self {};
+    /// Like `new`, joining the multithreaded apartment (`COINIT_MULTITHREADED`).
+    ///
+    pub fn new_mta() -> Result<Self, WMIError> {
+        let instance = Self::init(COINIT_MULTITHREADED)?;
 
         instance.init_security()?;
 
         Ok(instance)
     }
 
-    /// `CoInitialize`s the COM library for use by the calling thread, but without setting the security context.
+    /// Like `new`, but initializing a single-threaded apartment (`COINIT_APARTMENTTHREADED`).
     ///
-    pub fn without_security() -> Result<Self, Error> {
-        unsafe { check_hres(CoInitializeEx(ptr::null_mut(), COINIT_MULTITHREADED))? }
+    /// Connections created in an STA must only be used from the thread which created them,
+    /// and calls which wait for WMI (like iterating over query results) require the thread
+    /// to keep pumping window messages for COM callbacks to be delivered.
+    ///
+    pub fn new_sta() -> Result<Self, WMIError> {
+        let instance = Self::init(COINIT_APARTMENTTHREADED)?;
 
-        let instance = Self {};
+        instance.init_security()?;
 
         Ok(instance)
     }
 
-    fn init_security(&self) -> Result<(), Error> {
+    /// `CoInitialize`s the COM library for use by the calling thread, but without setting the security context.
+    ///
+    pub fn without_security() -> Result<Self, WMIError> {
+        Self::init(COINIT_MULTITHREADED)
+    }
+
+    fn init(coinit: COINIT) -> Result<Self, WMIError> {
+        unsafe { check_hres(CoInitializeEx(ptr::null_mut(), coinit))? }
+
+        COM_LIBRARIES.with(|count| count.set(count.get() + 1));
+
+        Ok(Self {})
+    }
+
+    /// Whether a `COMLibrary` is alive on the calling thread.
+    ///
+    /// This doesn't account for COM which was initialized by other code (see `is_initialized`).
+    ///
+    pub fn is_initialized_by_this_crate() -> bool {
+        COM_LIBRARIES.with(|count| count.get() > 0)
+    }
+
+    /// Whether COM is initialized on the calling thread, either by a `COMLibrary`
+    /// or by other code (like the host application).
+    ///
+    /// When no `COMLibrary` is alive on this thread, COM is probed by initializing it
+    /// (and immediately uninitializing it again), so this should only be used for debugging.
+    ///
+    pub fn is_initialized() -> bool {
+        if Self::is_initialized_by_this_crate() {
+            return true;
+        }
+
+        let hres: HRESULT = unsafe { CoInitializeEx(ptr::null_mut(), COINIT_MULTITHREADED) };
+
+        match hres {
+            // We were the first to initialize COM on this thread.
+            S_OK => {
+                unsafe { CoUninitialize() };
+                false
+            }
+            S_FALSE => {
+                unsafe { CoUninitialize() };
+                true
+            }
+            // Already initialized, as a single-threaded apartment.
+            RPC_E_CHANGED_MODE => true,
+            _ => false,
+        }
+    }
+
+    /// Panic (in debug builds) if COM isn't initialized on the calling thread.
+    ///
+    /// Using a connection on a thread which never initialized COM (for example, after moving
+    /// the work to a thread pool) usually fails with a confusing `CO_E_NOTINITIALIZED`
+    /// somewhere down the line, so this can be used to catch it early.
+    ///
+    /// ```edition2018
+    /// # use wmi::*;
+    /// let com_con = COMLibrary::new().unwrap();
+    ///
+    /// COMLibrary::assert_initialized();
+    /// ```
+    pub fn assert_initialized() {
+        debug_assert!(
+            Self::is_initialized(),
+            "COM is not initialized on thread {:?} (see `COMLibrary::new`)",
+            std::thread::current().id()
+        );
+    }
+
+    fn init_security(&self) -> Result<(), WMIError> {
         unsafe {
             check_hres(CoInitializeSecurity(
                 NULL,
@@ -76,13 +171,122 @@ impl COMLibrary {
 impl Drop for COMLibrary {
     fn drop(&mut self) {
         unsafe { CoUninitialize() };
+
+        COM_LIBRARIES.with(|count| count.set(count.get().saturating_sub(1)));
     }
 }
 
-pub struct WMIConnection {
+/// A shared `IWbemLocator`, which can be used to create multiple connections
+/// (for example, to different namespaces) without creating a new locator for each one.
+///
+pub struct WMILocator {
     com_con: Rc<COMLibrary>,
     p_loc: Option<NonNull<IWbemLocator>>,
+}
+
+impl WMILocator {
+    pub fn new(com_lib: Rc<COMLibrary>) -> Result<Self, Error> {
+        debug!("Calling CoCreateInstance for CLSID_WbemLocator");
+
+        let mut p_loc = NULL;
+
+        unsafe {
+            check_hres(CoCreateInstance(
+                &CLSID_WbemLocator,
+                ptr::null_mut(),
+                CLSCTX_INPROC_SERVER,
+                &IID_IWbemLocator,
+                &mut p_loc,
+            ))?;
+        }
+
+        let p_loc = NonNull::new(p_loc as *mut IWbemLocator);
+
+        debug!("Got locator {:?}", p_loc);
+
+        Ok(Self {
+            com_con: com_lib,
+            p_loc,
+        })
+    }
+
+    fn loc(&self) -> Result<*mut IWbemLocator, Error> {
+        match self.p_loc {
+            Some(loc) => Ok(loc.as_ptr()),
+            None => bail!("A locator created from a services pointer can't connect"),
+        }
+    }
+}
+
+impl Clone for WMILocator {
+    fn clone(&self) -> Self {
+        if let Some(loc) = self.p_loc {
+            unsafe {
+                (*loc.as_ptr()).AddRef();
+            }
+        }
+
+        Self {
+            com_con: self.com_con.clone(),
+            p_loc: self.p_loc,
+        }
+    }
+}
+
+impl Drop for WMILocator {
+    fn drop(&mut self) {
+        if let Some(loc) = self.p_loc {
+            unsafe {
+                (*loc.as_ptr()).Release();
+            }
+        }
+    }
+}
+
+pub struct WMIConnection {
+    locator: WMILocator,
     p_svc: Option<NonNull<IWbemServices>>,
+    server: String,
+    namespace: String,
+    proxy_blanket_per_call: bool,
+    // Whether `svc` already warned about a thread without a `COMLibrary`.
+    warned_uninitialized: Cell<bool>,
+}
+
+/// The server name used in namespace paths for the local computer.
+const LOCAL_SERVER: &str = ".";
+
+/// Split a namespace path like `\\server\ROOT\CIMV2` into its server and namespace parts.
+/// A path without a server part (like `ROOT\CIMV2`) refers to the local computer.
+///
+fn split_namespace_path(namespace_path: &str) -> (&str, &str) {
+    let server_and_namespace = namespace_path
+        .strip_prefix("\\\\")
+        .or_else(|| namespace_path.strip_prefix("//"));
+
+    match server_and_namespace {
+        Some(server_and_namespace) => match server_and_namespace.find(['\\', '/']) {
+            Some(idx) => (
+                &server_and_namespace[..idx],
+                &server_and_namespace[idx + 1..],
+            ),
+            None => (server_and_namespace, ""),
+        },
+        None => (LOCAL_SERVER, namespace_path),
+    }
+}
+
+/// Join a namespace with a (relative) child namespace, like `ROOT` and `CIMV2`.
+///
+fn join_namespace(namespace: &str, child: &str) -> String {
+    let namespace = namespace.trim_end_matches(['\\', '/']);
+    let child = child.trim_start_matches(['\\', '/']);
+
+    if namespace.is_empty() {
+        child.to_owned()
+    } else {
+        format!("{}\\{}", namespace, child)
+    }
 }
 
 /// A connection to the local WMI provider, which provides querying capabilities.
@@ -90,62 +294,246 @@ pub struct WMIConnection {
 /// Currently does not support remote providers (e.g connecting to other computers).
 ///
 impl WMIConnection {
+    /// Creates a connection with the default `ROOT\CIMV2` namespace.
+    ///
     pub fn new(com_lib: Rc<COMLibrary>) -> Result<Self, Error> {
+        Self::with_namespace_path("ROOT\\CIMV2", com_lib)
+    }
+
+    /// Creates a connection with the default `ROOT\CIMV2` namespace, taking ownership of `com_lib`.
+    ///
+    pub fn with_com(com_lib: COMLibrary) -> Result<Self, Error> {
+        Self::new(Rc::new(com_lib))
+    }
+
+    /// Initializes COM (see [`COMLibrary::new`](COMLibrary::new)) and creates a connection
+    /// with the default `ROOT\CIMV2` namespace, which is useful for quick scripts and tests.
+    ///
+    /// COM will stay initialized for as long as the connection (or any of its clones) are alive.
+    ///
+    /// ```edition2018
+    /// # use wmi::*;
+    /// let wmi_con = WMIConnection::local().unwrap();
+    /// ```
+    pub fn local() -> Result<Self, Error> {
+        Self::with_com(COMLibrary::new()?)
+    }
+
+    /// Creates a connection with the given namespace path.
+    ///
+    /// ```edition2018
+    /// # use wmi::*;
+    /// let wmi_con = WMIConnection::with_namespace_path("ROOT\\Microsoft\\Windows\\Storage", COMLibrary::new().unwrap().into()).unwrap();
+    /// ```
+    pub fn with_namespace_path(namespace_path: &str, com_lib: Rc<COMLibrary>) -> Result<Self, Error> {
+        let locator = WMILocator::new(com_lib)?;
+
+        Self::with_existing_locator(namespace_path, &locator)
+    }
+
+    /// Creates a connection with the given namespace path, reusing an existing locator.
+    ///
+    /// ```edition2018
+    /// # use wmi::*;
+    /// let locator = WMILocator::new(COMLibrary::new().unwrap().into()).unwrap();
+    ///
+    /// let cimv2_con = WMIConnection::with_existing_locator("ROOT\\CIMV2", &locator).unwrap();
+    /// let default_con = WMIConnection::with_existing_locator("ROOT\\DEFAULT", &locator).unwrap();
+    /// ```
+    pub fn with_existing_locator(namespace_path: &str, locator: &WMILocator) -> Result<Self, Error> {
+        let (server, namespace) = split_namespace_path(namespace_path);
+
         let mut instance = Self {
-            com_con: com_lib,
-            p_loc: None,
+            locator: locator.clone(),
             p_svc: None,
+            server: server.to_owned(),
+            namespace: namespace.to_owned(),
+            proxy_blanket_per_call: false,
+            warned_uninitialized: Cell::new(false),
         };
 
-        instance.create_locator()?;
-
-        instance.create_services()?;
+        instance.create_services(namespace_path)?;
 
         instance.set_proxy()?;
 
         Ok(instance)
     }
 
-    pub fn svc(&self) -> *mut IWbemServices {
-        self.p_svc.unwrap().as_ptr()
-    }
-
-    fn loc(&self) -> *mut IWbemLocator {
-        self.p_loc.unwrap().as_ptr()
-    }
+    /// Open a namespace relative to the namespace of this connection
+    /// (for example, `CIMV2` when connected to `ROOT`), using `IWbemServices::OpenNamespace`.
+    ///
+    /// The new connection is made to the same server, with the same security settings and locator.
+    ///
+    /// ```edition2018
+    /// # use wmi::*;
+    /// let root_con = WMIConnection::with_namespace_path("ROOT", COMLibrary::new().unwrap().into()).unwrap();
+    ///
+    /// let cimv2_con = root_con.open_namespace("CIMV2").unwrap();
+    /// ```
+    pub fn open_namespace(&self, namespace: &str) -> Result<Self, Error> {
+        debug!("Calling OpenNamespace");
 
-    fn create_locator(&mut self) -> Result<(), Error> {
-        debug!("Calling CoCreateInstance for CLSID_WbemLocator");
+        let namespace_bstr = WideCString::from_str(namespace)?;
 
-        let mut p_loc = NULL;
+        let mut p_svc = ptr::null_mut::<IWbemServices>();
 
         unsafe {
-            check_hres(CoCreateInstance(
-                &CLSID_WbemLocator,
+            check_hres((*self.svc()).OpenNamespace(
+                namespace_bstr.as_ptr() as *mut _,
+                0,
+                ptr::null_mut(),
+                &mut p_svc,
                 ptr::null_mut(),
-                CLSCTX_INPROC_SERVER,
-                &IID_IWbemLocator,
-                &mut p_loc,
             ))?;
         }
 
-        self.p_loc = NonNull::new(p_loc as *mut IWbemLocator);
+        let instance = Self {
+            locator: self.locator.clone(),
+            p_svc: NonNull::new(p_svc),
+            server: self.server.clone(),
+            namespace: join_namespace(&self.namespace, namespace),
+            proxy_blanket_per_call: self.proxy_blanket_per_call,
+            warned_uninitialized: Cell::new(false),
+        };
+
+        instance.set_proxy()?;
+
+        Ok(instance)
+    }
 
-        debug!("Got locator {:?}", self.p_loc);
+    /// Create a connection from an existing `IWbemServices` pointer (for example, one created by
+    /// another part of a COM application), without connecting again.
+    ///
+    /// The connection takes ownership of one reference to the pointer, and releases it when dropped.
+    /// Call `AddRef` before passing the pointer to keep using it elsewhere.
+    ///
+    /// The proxy blanket of the pointer is used as is. Since they can't be queried from the pointer,
+    /// the `server` (`.` for the local computer) and `namespace` it's connected to must be given.
+    /// The connection's `locator()` can't be used to create other connections.
+    ///
+    /// Fails if `ptr` is null (in which case no reference is taken).
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must be null or a valid `IWbemServices` pointer, which can be used on this thread
+    /// (while `com` keeps COM initialized).
+    ///
+    pub unsafe fn from_services_ptr(
+        ptr: *mut IWbemServices,
+        server: &str,
+        namespace: &str,
+        com: COMLibrary,
+    ) -> Result<Self, Error> {
+        let p_svc = match NonNull::new(ptr) {
+            Some(p_svc) => p_svc,
+            None => bail!("Can't create a connection from a null IWbemServices pointer"),
+        };
+
+        Ok(Self {
+            locator: WMILocator {
+                com_con: Rc::new(com),
+                p_loc: None,
+            },
+            p_svc: Some(p_svc),
+            server: server.to_owned(),
+            namespace: namespace.to_owned(),
+            proxy_blanket_per_call: false,
+            warned_uninitialized: Cell::new(false),
+        })
+    }
+
+    /// Return the connection's `IWbemServices` pointer, transferring the connection's reference
+    /// to the caller (who must `Release` it when done).
+    ///
+    /// COM must stay initialized on this thread for as long as the pointer is used
+    /// (the `COMLibrary` of the connection isn't kept alive by the pointer).
+    ///
+    pub fn into_services_ptr(mut self) -> *mut IWbemServices {
+        match self.p_svc.take() {
+            Some(svc) => svc.as_ptr(),
+            None => ptr::null_mut(),
+        }
+    }
+
+    /// Also set the proxy blanket (`CoSetProxyBlanket`) on every enumerator returned by
+    /// a query (and every call result, see `get_objects`), and not only on the services
+    /// pointer of the connection.
+    ///
+    /// Enumerators are separate proxies, so when connecting to a remote computer they must
+    /// be blanketed too, or iterating them can fail mid-enumeration with `E_ACCESSDENIED`
+    /// (see "Setting the Security on IWbemServices and Other Proxies" in the WMI docs).
+    /// This isn't needed for local connections, so it's disabled by default.
+    ///
+    /// ```edition2018
+    /// # use wmi::*;
+    /// let wmi_con = WMIConnection::new(COMLibrary::new().unwrap().into())
+    ///     .unwrap()
+    ///     .with_proxy_blanket_per_call(true);
+    /// ```
+    pub fn with_proxy_blanket_per_call(mut self, proxy_blanket_per_call: bool) -> Self {
+        self.proxy_blanket_per_call = proxy_blanket_per_call;
+        self
+    }
+
+    /// Set the proxy blanket on a proxy returned by a call using this connection
+    /// (like an enumerator), if `with_proxy_blanket_per_call` is enabled.
+    ///
+    pub(crate) fn set_call_proxy(&self, proxy: *mut IUnknown) -> Result<(), Error> {
+        if self.proxy_blanket_per_call {
+            set_proxy_blanket(proxy)?;
+        }
 
         Ok(())
     }
 
-    fn create_services(&mut self) -> Result<(), Error> {
+    /// The server this connection is connected to (`.` for the local computer).
+    ///
+    pub fn server(&self) -> &str {
+        &self.server
+    }
+
+    /// The namespace this connection is connected to (for example, `ROOT\CIMV2`).
+    ///
+    pub fn namespace(&self) -> &str {
+        &self.namespace
+    }
+
+    /// The locator used to create this connection (which can be shared with other connections).
+    ///
+    pub fn locator(&self) -> &WMILocator {
+        &self.locator
+    }
+
+    /// In debug builds, a warning is logged (once per connection) if no `COMLibrary` is alive
+    /// on the calling thread. This is only a cheap hint, which is also logged if COM was initialized
+    /// by other code (use [`COMLibrary::assert_initialized`] to actually check it).
+    ///
+    pub fn svc(&self) -> *mut IWbemServices {
+        #[cfg(debug_assertions)]
+        {
+            if !COMLibrary::is_initialized_by_this_crate() && !self.warned_uninitialized.get() {
+                self.warned_uninitialized.set(true);
+
+                warn!(
+                    "Connection to {} is used on thread {:?}, which has no `COMLibrary`",
+                    self.namespace,
+                    std::thread::current().id()
+                );
+            }
+        }
+
+        self.p_svc.unwrap().as_ptr()
+    }
+
+    fn create_services(&mut self, namespace_path: &str) -> Result<(), Error> {
         debug!("Calling ConnectServer");
 
         let mut p_svc = ptr::null_mut::<IWbemServices>();
 
-        let object_path = "ROOT\\CIMV2";
-        let mut object_path_bstr = WideCString::from_str(object_path)?;
+        let object_path_bstr = WideCString::from_str(namespace_path)?;
 
         unsafe {
-            check_hres((*self.loc()).ConnectServer(
+            check_hres((*self.locator.loc()?).ConnectServer(
                 object_path_bstr.as_ptr() as *mut _,
                 ptr::null_mut(),
                 ptr::null_mut(),
@@ -165,22 +553,48 @@ impl WMIConnection {
     }
 
     fn set_proxy(&self) -> Result<(), Error> {
-        debug!("Calling CoSetProxyBlanket");
+        set_proxy_blanket(self.svc() as _)
+    }
+}
 
-        unsafe {
-            check_hres(CoSetProxyBlanket(
-                self.svc() as _,             // Indicates the proxy to set
-                RPC_C_AUTHN_WINNT,           // RPC_C_AUTHN_xxx
-                RPC_C_AUTHZ_NONE,            // RPC_C_AUTHZ_xxx
-                ptr::null_mut(),             // Server principal name
-                RPC_C_AUTHN_LEVEL_CALL,      // RPC_C_AUTHN_LEVEL_xxx
-                RPC_C_IMP_LEVEL_IMPERSONATE, // RPC_C_IMP_LEVEL_xxx
-                NULL,                        // client identity
-                EOAC_NONE,                   // proxy capabilities
-            ))?;
+fn set_proxy_blanket(proxy: *mut IUnknown) -> Result<(), Error> {
+    debug!("Calling CoSetProxyBlanket");
+
+    unsafe {
+        check_hres(CoSetProxyBlanket(
+            proxy,                       // Indicates the proxy to set
+            RPC_C_AUTHN_WINNT,           // RPC_C_AUTHN_xxx
+            RPC_C_AUTHZ_NONE,            // RPC_C_AUTHZ_xxx
+            ptr::null_mut(),             // Server principal name
+            RPC_C_AUTHN_LEVEL_CALL,      // RPC_C_AUTHN_LEVEL_xxx
+            RPC_C_IMP_LEVEL_IMPERSONATE, // RPC_C_IMP_LEVEL_xxx
+            NULL,                        // client identity
+            EOAC_NONE,                   // proxy capabilities
+        ))?;
+    }
+
+    Ok(())
+}
+
+/// Cloning a connection `AddRef`s the underlying COM pointers,
+/// so each clone can be used (and dropped) independently.
+///
+impl Clone for WMIConnection {
+    fn clone(&self) -> Self {
+        if let Some(svc) = self.p_svc {
+            unsafe {
+                (*svc.as_ptr()).AddRef();
+            }
         }
 
-        Ok(())
+        Self {
+            locator: self.locator.clone(),
+            p_svc: self.p_svc,
+            server: self.server.clone(),
+            namespace: self.namespace.clone(),
+            proxy_blanket_per_call: self.proxy_blanket_per_call,
+            warned_uninitialized: Cell::new(false),
+        }
     }
 }
 
@@ -192,17 +606,76 @@ impl Drop for WMIConnection {
             }
         }
 
-        if let Some(loc) = self.p_loc {
-            unsafe {
-                (*loc.as_ptr()).Release();
-            }
-        }
+        // The locator is released when the `locator` field is dropped.
     }
 }
 
 mod tests {
     use super::*;
 
+    #[test]
+    fn com_library_new_returns_ok() {
+        // Run on a fresh thread, so COM was not initialized by other tests.
+        let res = std::thread::spawn(|| COMLibrary::new().map(|_| ()))
+            .join()
+            .unwrap();
+
+        assert!(res.is_ok());
+    }
+
+    #[test]
+    fn it_tracks_com_initialization_per_thread() {
+        let (before, during, after) = std::thread::spawn(|| {
+            let before = COMLibrary::is_initialized();
+
+            let com_con = COMLibrary::new().unwrap();
+            let during = COMLibrary::is_initialized_by_this_crate();
+            COMLibrary::assert_initialized();
+
+            drop(com_con);
+
+            (before, during, COMLibrary::is_initialized_by_this_crate())
+        })
+        .join()
+        .unwrap();
+
+        assert!(!before);
+        assert!(during);
+        assert!(!after);
+    }
+
+    #[test]
+    fn com_library_new_mta_returns_ok() {
+        let res = std::thread::spawn(|| COMLibrary::new_mta().map(|_| ()))
+            .join()
+            .unwrap();
+
+        assert!(res.is_ok());
+    }
+
+    #[test]
+    fn it_can_connect_from_an_sta() {
+        let caption = std::thread::spawn(|| {
+            let com_con = COMLibrary::new_sta().unwrap();
+            let wmi_con = WMIConnection::new(com_con.into()).unwrap();
+
+            let os: std::collections::HashMap<String, crate::Variant> = wmi_con
+                .raw_query("SELECT Caption FROM Win32_OperatingSystem")
+                .unwrap()
+                .pop()
+                .unwrap();
+
+            os["Caption"].clone()
+        })
+        .join()
+        .unwrap();
+
+        assert_eq!(
+            caption,
+            crate::Variant::String("Microsoft Windows 10 Pro".into())
+        );
+    }
+
     #[test]
     fn it_works() {
         let com_con = COMLibrary::new().unwrap();
@@ -212,4 +685,202 @@ mod tests {
 
         assert_eq!(p_svc.is_null(), false);
     }
+
+    #[test]
+    fn it_can_connect_locally() {
+        // Run on a fresh thread, so COM was not initialized by other tests.
+        let res = std::thread::spawn(|| {
+            let wmi_con = WMIConnection::local()?;
+
+            wmi_con
+                .exec_query_native_wrapper("SELECT Caption FROM Win32_OperatingSystem")
+                .map(|enumerator| enumerator.count())
+        })
+        .join()
+        .unwrap();
+
+        assert_eq!(res.unwrap(), 1);
+    }
+
+    #[test]
+    fn it_exposes_server_and_namespace() {
+        let com_con = COMLibrary::without_security().unwrap();
+        let wmi_con = WMIConnection::new(com_con.into()).unwrap();
+
+        assert_eq!(wmi_con.server(), ".");
+        assert_eq!(wmi_con.namespace(), "ROOT\\CIMV2");
+
+        let com_con = COMLibrary::without_security().unwrap();
+        let wmi_con =
+            WMIConnection::with_namespace_path("\\\\.\\ROOT\\DEFAULT", com_con.into()).unwrap();
+
+        assert_eq!(wmi_con.server(), ".");
+        assert_eq!(wmi_con.namespace(), "ROOT\\DEFAULT");
+    }
+
+    #[test]
+    fn it_splits_namespace_paths() {
+        assert_eq!(split_namespace_path("ROOT\\CIMV2"), (".", "ROOT\\CIMV2"));
+        assert_eq!(
+            split_namespace_path("\\\\remote\\ROOT\\CIMV2"),
+            ("remote", "ROOT\\CIMV2")
+        );
+        assert_eq!(
+            split_namespace_path("//remote/ROOT/CIMV2"),
+            ("remote", "ROOT/CIMV2")
+        );
+        assert_eq!(split_namespace_path("\\\\remote"), ("remote", ""));
+    }
+
+    #[test]
+    fn it_joins_namespaces() {
+        assert_eq!(join_namespace("ROOT", "CIMV2"), "ROOT\\CIMV2");
+        assert_eq!(join_namespace("ROOT\\", "\\CIMV2"), "ROOT\\CIMV2");
+        assert_eq!(
+            join_namespace("ROOT\\Microsoft", "Windows\\Storage"),
+            "ROOT\\Microsoft\\Windows\\Storage"
+        );
+        assert_eq!(join_namespace("", "ROOT"), "ROOT");
+    }
+
+    #[test]
+    fn it_can_open_a_child_namespace() {
+        let com_con = COMLibrary::new().unwrap();
+        let root_con = WMIConnection::with_namespace_path("ROOT", com_con.into()).unwrap();
+
+        let cimv2_con = root_con.open_namespace("CIMV2").unwrap();
+
+        assert_eq!(cimv2_con.server(), ".");
+        assert_eq!(cimv2_con.namespace(), "ROOT\\CIMV2");
+
+        let results: Vec<std::collections::HashMap<String, crate::Variant>> = cimv2_con
+            .raw_query("SELECT Caption FROM Win32_OperatingSystem")
+            .unwrap();
+
+        assert_eq!(results.len(), 1);
+    }
+
+    #[test]
+    fn it_can_share_a_locator() {
+        let com_con = COMLibrary::without_security().unwrap();
+        let locator = WMILocator::new(com_con.into()).unwrap();
+
+        let cimv2_con = WMIConnection::with_existing_locator("ROOT\\CIMV2", &locator).unwrap();
+        let default_con = WMIConnection::with_existing_locator("ROOT\\DEFAULT", &locator).unwrap();
+
+        drop(locator);
+
+        let enumerator = cimv2_con
+            .exec_query_native_wrapper("SELECT Caption FROM Win32_OperatingSystem")
+            .unwrap();
+
+        assert_eq!(enumerator.count(), 1);
+
+        let enumerator = default_con
+            .exec_query_native_wrapper("SELECT * FROM StdRegProv")
+            .unwrap();
+
+        for res in enumerator {
+            assert!(res.is_ok());
+        }
+    }
+
+    #[test]
+    fn it_can_be_cloned() {
+        let com_con = COMLibrary::without_security().unwrap();
+        let wmi_con = WMIConnection::new(com_con.into()).unwrap();
+
+        let first = wmi_con.clone();
+        let second = first.clone();
+
+        drop(wmi_con);
+
+        for con in [&second, &first].iter() {
+            let enumerator = con
+                .exec_query_native_wrapper("SELECT Caption FROM Win32_OperatingSystem")
+                .unwrap();
+
+            assert_eq!(enumerator.count(), 1);
+        }
+
+        drop(first);
+
+        let enumerator = second
+            .exec_query_native_wrapper("SELECT Caption FROM Win32_OperatingSystem")
+            .unwrap();
+
+        assert_eq!(enumerator.count(), 1);
+    }
+
+    #[test]
+    fn it_round_trips_through_a_services_ptr() {
+        let com_con = COMLibrary::new().unwrap();
+        let wmi_con = WMIConnection::new(COMLibrary::new().unwrap().into()).unwrap();
+
+        // `com_con` keeps COM initialized after the first connection is dropped.
+        let p_svc = wmi_con.into_services_ptr();
+
+        assert!(!p_svc.is_null());
+
+        let wmi_con =
+            unsafe { WMIConnection::from_services_ptr(p_svc, ".", "ROOT\\CIMV2", com_con) }
+                .unwrap();
+
+        assert_eq!(wmi_con.svc(), p_svc);
+        assert_eq!(wmi_con.server(), ".");
+        assert_eq!(wmi_con.namespace(), "ROOT\\CIMV2");
+
+        // The locator has no `IWbemLocator`, so it can't create other connections.
+        assert!(WMIConnection::with_existing_locator("ROOT\\DEFAULT", wmi_con.locator()).is_err());
+
+        let enumerator = wmi_con
+            .exec_query_native_wrapper("SELECT Caption FROM Win32_OperatingSystem")
+            .unwrap();
+
+        assert_eq!(enumerator.count(), 1);
+    }
+
+    #[test]
+    fn it_fails_to_create_a_connection_from_a_null_services_ptr() {
+        let com_con = COMLibrary::new().unwrap();
+
+        let res = unsafe {
+            WMIConnection::from_services_ptr(ptr::null_mut(), ".", "ROOT\\CIMV2", com_con)
+        };
+
+        assert!(res.is_err());
+    }
+
+    #[test]
+    fn it_can_set_proxy_blanket_per_call() {
+        let wmi_con = WMIConnection::new(COMLibrary::new().unwrap().into())
+            .unwrap()
+            .with_proxy_blanket_per_call(true);
+
+        let results: Vec<std::collections::HashMap<String, crate::Variant>> = wmi_con
+            .raw_query("SELECT Name FROM Win32_Processor")
+            .unwrap();
+
+        assert!(!results.is_empty());
+    }
+
+    /// A manual test, which requires a remote computer (set `WMI_REMOTE_HOST`)
+    /// which the current user can access. Run with `cargo test -- --ignored remote`.
+    #[test]
+    #[ignore]
+    fn it_can_enumerate_a_remote_connection_with_proxy_blanket_per_call() {
+        let host = std::env::var("WMI_REMOTE_HOST").expect("WMI_REMOTE_HOST is not set");
+
+        let wmi_con = WMIConnection::with_namespace_path(
+            &format!("\\\\{}\\ROOT\\CIMV2", host),
+            COMLibrary::new().unwrap().into(),
+        )
+        .unwrap()
+        .with_proxy_blanket_per_call(true);
+
+        let results: Vec<std::collections::HashMap<String, crate::Variant>> =
+            wmi_con.raw_query("SELECT Name FROM Win32_Process").unwrap();
+
+        assert!(!results.is_empty());
+    }
 }
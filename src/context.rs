@@ -0,0 +1,159 @@
+use crate::{utils::check_hres, Variant};
+use failure::Error;
+use log::debug;
+use std::fmt;
+use std::ptr::{self, NonNull};
+use widestring::WideCString;
+use winapi::{
+    shared::{ntdef::NULL, wtypesbase::CLSCTX_INPROC_SERVER},
+    um::{
+        combaseapi::CoCreateInstance,
+        oleauto::VariantClear,
+        wbemcli::{CLSID_WbemContext, IID_IWbemContext, IWbemContext},
+    },
+};
+
+/// A wrapper around `IWbemContext`, which passes extra values to the providers of a call.
+///
+/// For example, setting `__ProviderArchitecture` to `32` makes `StdRegProv` read the
+/// 32-bit view of the registry on 64-bit Windows.
+///
+/// COM must be initialized (see [`COMLibrary`](crate::COMLibrary)) before creating a context.
+///
+/// ```edition2018
+/// # use wmi::*;
+/// # use wmi::query::QueryOptions;
+/// # use std::collections::HashMap;
+/// # let con = WMIConnection::new(COMLibrary::new().unwrap().into()).unwrap();
+/// let context = WMIContext::new().unwrap();
+/// context.set_value("__ProviderArchitecture", &Variant::I4(32)).unwrap();
+///
+/// let options = QueryOptions { context: Some(context), ..Default::default() };
+///
+/// let results: Vec<HashMap<String, Variant>> = con.raw_query_with_options("SELECT * FROM Win32_OperatingSystem", &options).unwrap();
+/// ```
+pub struct WMIContext {
+    p_ctx: Option<NonNull<IWbemContext>>,
+}
+
+impl WMIContext {
+    pub fn new() -> Result<Self, Error> {
+        debug!("Calling CoCreateInstance for CLSID_WbemContext");
+
+        let mut p_ctx = NULL;
+
+        unsafe {
+            check_hres(CoCreateInstance(
+                &CLSID_WbemContext,
+                ptr::null_mut(),
+                CLSCTX_INPROC_SERVER,
+                &IID_IWbemContext,
+                &mut p_ctx,
+            ))?;
+        }
+
+        Ok(Self {
+            p_ctx: NonNull::new(p_ctx as *mut IWbemContext),
+        })
+    }
+
+    /// Set a named value of the context (overwriting the current value, if any).
+    ///
+    pub fn set_value(&self, name: &str, value: &Variant) -> Result<(), Error> {
+        let name = WideCString::from_str(name)?;
+
+        let mut vt_value = value.to_variant()?;
+
+        let res = unsafe { check_hres((*self.ctx()).SetValue(name.as_ptr(), 0, &mut vt_value)) };
+
+        unsafe { VariantClear(&mut vt_value) };
+
+        Ok(res?)
+    }
+
+    /// Return a named value of the context.
+    ///
+    pub fn get_value(&self, name: &str) -> Result<Variant, Error> {
+        let name = WideCString::from_str(name)?;
+
+        let mut vt_value = unsafe { std::mem::zeroed() };
+
+        unsafe {
+            check_hres((*self.ctx()).GetValue(name.as_ptr(), 0, &mut vt_value))?;
+        }
+
+        let value = Variant::from_variant(vt_value);
+
+        unsafe { VariantClear(&mut vt_value) };
+
+        value
+    }
+
+    pub fn ctx(&self) -> *mut IWbemContext {
+        self.p_ctx.unwrap().as_ptr()
+    }
+}
+
+impl fmt::Debug for WMIContext {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("WMIContext")
+            .field("p_ctx", &self.p_ctx)
+            .finish()
+    }
+}
+
+/// Cloning a context `AddRef`s the underlying `IWbemContext`,
+/// so the clones share the same values.
+///
+impl Clone for WMIContext {
+    fn clone(&self) -> Self {
+        if let Some(ctx) = self.p_ctx {
+            unsafe {
+                (*ctx.as_ptr()).AddRef();
+            }
+        }
+
+        Self { p_ctx: self.p_ctx }
+    }
+}
+
+impl Drop for WMIContext {
+    fn drop(&mut self) {
+        if let Some(ctx) = self.p_ctx {
+            unsafe {
+                (*ctx.as_ptr()).Release();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::fixtures::*;
+
+    #[test]
+    fn it_sets_and_gets_values() {
+        // Initializes COM.
+        let _wmi_con = wmi_con();
+
+        let context = WMIContext::new().unwrap();
+
+        context
+            .set_value("__ProviderArchitecture", &Variant::I4(32))
+            .unwrap();
+        context
+            .set_value("__RequiredArchitecture", &Variant::Bool(true))
+            .unwrap();
+
+        assert_eq!(
+            context.get_value("__ProviderArchitecture").unwrap(),
+            Variant::I4(32)
+        );
+        assert_eq!(
+            context.clone().get_value("__RequiredArchitecture").unwrap(),
+            Variant::Bool(true)
+        );
+        assert!(context.get_value("NotSet").is_err());
+    }
+}
@@ -1,31 +1,116 @@
+use crate::utils::WMIError;
 use chrono::prelude::*;
 use failure::{bail, Error};
 use serde::{de, ser};
+use std::convert::TryFrom;
 use std::fmt;
+use std::ops::Deref;
 use std::str::FromStr;
+use winapi::shared::minwindef::FILETIME;
+
+const DATETIME_LEN: usize = 21;
+const OFFSET_LEN: usize = 4;
+
+// `FILETIME`s count 100-nanosecond ticks since 1601-01-01, while Unix timestamps start at 1970-01-01.
+const TICKS_PER_SEC: u64 = 10_000_000;
+const NANOS_PER_TICK: u32 = 100;
+const FILETIME_TO_UNIX_EPOCH_SECS: i64 = 11_644_473_600;
 
 /// A wrapper type around chrono's DateTime, which supports parsing from WMI-format strings.
 ///
-#[derive(Debug)]
+/// Comparison and ordering are chronological (like chrono's `DateTime`), so values with
+/// different UTC offsets compare by the instant they represent.
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct WMIDateTime(pub DateTime<FixedOffset>);
 
+impl Deref for WMIDateTime {
+    type Target = DateTime<FixedOffset>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl AsRef<DateTime<FixedOffset>> for WMIDateTime {
+    fn as_ref(&self) -> &DateTime<FixedOffset> {
+        &self.0
+    }
+}
+
 impl FromStr for WMIDateTime {
     type Err = Error;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        if s.len() < 21 {
-            bail!("Expected {:?} to be at least 21 chars", s)
+        // A CIM_DATETIME is `yyyymmddHHMMSS.mmmmmmsUUU`, which is 21 chars for the date and time,
+        // followed by 4 chars for the sign and the UTC offset in minutes.
+        // See: https://docs.microsoft.com/en-us/windows/desktop/wmisdk/cim-datetime
+        if s.len() != DATETIME_LEN + OFFSET_LEN || !s.is_ascii() {
+            bail!(
+                "Expected {:?} to be exactly {} ASCII chars",
+                s,
+                DATETIME_LEN + OFFSET_LEN
+            )
         }
 
-        let (datetime_part, tz_part) = s.split_at(21);
+        let (datetime_part, tz_part) = s.split_at(DATETIME_LEN);
+
+        if !tz_part.starts_with('+') && !tz_part.starts_with('-') {
+            bail!("Expected the UTC offset of {:?} to start with `+` or `-`", s)
+        }
 
-        let tz_min: i32 = tz_part.parse()?;
+        let tz_min: i32 = tz_part.parse().map_err(WMIError::from)?;
 
         let tz = FixedOffset::east(tz_min * 60);
 
-        let dt = tz.datetime_from_str(datetime_part, "%Y%m%d%H%M%S.%f")?;
+        let naive = NaiveDateTime::parse_from_str(datetime_part, "%Y%m%d%H%M%S.%6f")
+            .map_err(WMIError::from)?;
+
+        // A fixed offset never has ambiguous or missing local times.
+        match tz.from_local_datetime(&naive).single() {
+            Some(dt) => Ok(Self(dt)),
+            None => bail!("Expected {:?} to be a valid local time", s),
+        }
+    }
+}
+
+impl WMIDateTime {
+    /// Convert to a Windows `FILETIME` (in UTC), keeping the full 100-nanosecond precision.
+    ///
+    /// Note that CIM_DATETIME strings only carry microseconds, so a value which was parsed
+    /// from WMI will never have sub-microsecond ticks.
+    /// Dates before 1601-01-01 (which can't be represented) are clamped to a zero `FILETIME`.
+    ///
+    pub fn to_filetime(&self) -> FILETIME {
+        let secs = self.0.timestamp() + FILETIME_TO_UNIX_EPOCH_SECS;
+        let sub_sec_ticks = i64::from(self.0.timestamp_subsec_nanos() / NANOS_PER_TICK);
+
+        let ticks = secs
+            .saturating_mul(TICKS_PER_SEC as i64)
+            .saturating_add(sub_sec_ticks)
+            .max(0) as u64;
+
+        FILETIME {
+            dwLowDateTime: ticks as u32,
+            dwHighDateTime: (ticks >> 32) as u32,
+        }
+    }
+
+    /// Create from a Windows `FILETIME` (which is always in UTC), keeping the full 100-nanosecond precision.
+    ///
+    /// Note that converting the result back to a CIM_DATETIME is lossy below microseconds.
+    ///
+    pub fn from_filetime(filetime: FILETIME) -> Self {
+        let ticks = (u64::from(filetime.dwHighDateTime) << 32) | u64::from(filetime.dwLowDateTime);
+
+        let secs = (ticks / TICKS_PER_SEC) as i64 - FILETIME_TO_UNIX_EPOCH_SECS;
+        let nanos = (ticks % TICKS_PER_SEC) as u32 * NANOS_PER_TICK;
+
+        // Even the largest `FILETIME` is well within chrono's supported range,
+        // and `Utc` never has ambiguous timestamps.
+        let dt = Utc.timestamp_opt(secs, nanos).unwrap();
 
-        Ok(Self(dt))
+        Self(dt.with_timezone(&FixedOffset::east_opt(0).unwrap()))
     }
 }
 
@@ -35,7 +120,7 @@ impl<'de> de::Visitor<'de> for DateTimeVisitor {
     type Value = WMIDateTime;
 
     fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
-        write!(formatter, "a timestamp in WMI format")
+        write!(formatter, "a timestamp in WMI format or a unix timestamp")
     }
 
     fn visit_str<E>(self, value: &str) -> Result<Self::Value, E>
@@ -44,6 +129,33 @@ impl<'de> de::Visitor<'de> for DateTimeVisitor {
     {
         value.parse().map_err(|err| E::custom(format!("{}", err)))
     }
+
+    /// Seconds since the Unix epoch (in UTC), for data which was re-ingested from other sources.
+    ///
+    /// Since `WMIDateTime` is deserialized using `deserialize_str`, this is only reached with
+    /// deserializers which visit integers there (like the one for `Variant`), but not (for example)
+    /// with `serde_json`.
+    fn visit_i64<E>(self, value: i64) -> Result<Self::Value, E>
+    where
+        E: de::Error,
+    {
+        let utc = FixedOffset::east_opt(0).ok_or_else(|| E::custom("Invalid UTC offset"))?;
+
+        match Utc.timestamp_opt(value, 0).single() {
+            Some(dt) => Ok(WMIDateTime(dt.with_timezone(&utc))),
+            None => Err(E::custom(format!("Unix timestamp {} is out of range", value))),
+        }
+    }
+
+    fn visit_u64<E>(self, value: u64) -> Result<Self::Value, E>
+    where
+        E: de::Error,
+    {
+        match i64::try_from(value) {
+            Ok(value) => self.visit_i64(value),
+            Err(_) => Err(E::custom(format!("Unix timestamp {} is out of range", value))),
+        }
+    }
 }
 
 impl<'de> de::Deserialize<'de> for WMIDateTime {
@@ -67,20 +179,30 @@ impl ser::Serialize for WMIDateTime {
 #[cfg(test)]
 mod tests {
     use super::WMIDateTime;
+    use crate::utils::WMIError;
+    use crate::Variant;
+    use serde::Deserialize;
     use serde_json;
+    use winapi::shared::minwindef::FILETIME;
+
+    // 1970-01-01T00:00:00Z, which is 116444736000000000 ticks since 1601-01-01.
+    const UNIX_EPOCH_FILETIME: FILETIME = FILETIME {
+        dwLowDateTime: 0xD53E_8000,
+        dwHighDateTime: 0x019D_B1DE,
+    };
 
     #[test]
     fn it_works_with_negative_offset() {
         let dt: WMIDateTime = "20190113200517.500000-180".parse().unwrap();
 
-        assert_eq!(dt.0.to_rfc3339(), "2019-01-13T20:05:17.000500-03:00");
+        assert_eq!(dt.0.to_rfc3339(), "2019-01-13T20:05:17.500-03:00");
     }
 
     #[test]
     fn it_works_with_positive_offset() {
         let dt: WMIDateTime = "20190113200517.500000+060".parse().unwrap();
 
-        assert_eq!(dt.0.to_rfc3339(), "2019-01-13T20:05:17.000500+01:00");
+        assert_eq!(dt.0.to_rfc3339(), "2019-01-13T20:05:17.500+01:00");
     }
 
     #[test]
@@ -92,16 +214,144 @@ mod tests {
 
     #[test]
     fn it_fails_with_malformed_str_with_no_tz() {
-        let dt_res: Result<WMIDateTime, _> = "20190113200517.000500".parse();
+        let dt_res: Result<WMIDateTime, _> = "20190113200517.500".parse();
+
+        assert!(dt_res.is_err());
+    }
+
+    #[test]
+    fn it_fails_with_parse_error_as_source() {
+        let err = "20190113200517.50000x+060".parse::<WMIDateTime>().unwrap_err();
+
+        let wmi_err = err.downcast_ref::<WMIError>().unwrap();
+
+        match wmi_err {
+            WMIError::ParseDatetimeError(_) => {}
+            _ => assert!(false),
+        }
+
+        assert!(std::error::Error::source(wmi_err).is_some());
+    }
+
+    #[test]
+    fn it_fails_with_malformed_offset_sign() {
+        let dt_res: Result<WMIDateTime, _> = "20190113200517.500000 060".parse();
+
+        assert!(dt_res.is_err());
+    }
+
+    #[test]
+    fn it_fails_with_trailing_chars() {
+        let dt_res: Result<WMIDateTime, _> = "20190113200517.500000+0600".parse();
+
+        assert!(dt_res.is_err());
+    }
+
+    #[test]
+    fn it_fails_with_non_ascii_str() {
+        let dt_res: Result<WMIDateTime, _> = "20190113200517.5000é+060".parse();
 
         assert!(dt_res.is_err());
     }
 
+    #[test]
+    fn it_converts_from_filetime() {
+        let dt = WMIDateTime::from_filetime(UNIX_EPOCH_FILETIME);
+
+        assert_eq!(dt.0.to_rfc3339(), "1970-01-01T00:00:00+00:00");
+    }
+
+    #[test]
+    fn it_converts_to_filetime() {
+        let dt: WMIDateTime = "19700101020000.000000+120".parse().unwrap();
+
+        let ft = dt.to_filetime();
+
+        assert_eq!(ft.dwLowDateTime, UNIX_EPOCH_FILETIME.dwLowDateTime);
+        assert_eq!(ft.dwHighDateTime, UNIX_EPOCH_FILETIME.dwHighDateTime);
+    }
+
+    #[test]
+    fn it_converts_microseconds_to_filetime_ticks() {
+        let dt: WMIDateTime = "19700101000000.500000+000".parse().unwrap();
+
+        let ft = dt.to_filetime();
+        let ticks = (u64::from(ft.dwHighDateTime) << 32) | u64::from(ft.dwLowDateTime);
+        let epoch_ticks = (u64::from(UNIX_EPOCH_FILETIME.dwHighDateTime) << 32)
+            | u64::from(UNIX_EPOCH_FILETIME.dwLowDateTime);
+
+        assert_eq!(ticks - epoch_ticks, 5_000_000);
+    }
+
+    #[test]
+    fn it_keeps_filetime_ticks_when_round_tripping() {
+        let ft = FILETIME {
+            dwLowDateTime: UNIX_EPOCH_FILETIME.dwLowDateTime + 1,
+            dwHighDateTime: UNIX_EPOCH_FILETIME.dwHighDateTime,
+        };
+
+        let dt = WMIDateTime::from_filetime(ft);
+        assert_eq!(dt.0.to_rfc3339(), "1970-01-01T00:00:00.000000100+00:00");
+
+        let round_tripped = dt.to_filetime();
+        assert_eq!(round_tripped.dwLowDateTime, ft.dwLowDateTime);
+        assert_eq!(round_tripped.dwHighDateTime, ft.dwHighDateTime);
+    }
+
+    #[test]
+    fn it_sorts_chronologically() {
+        let mut dts: Vec<WMIDateTime> = [
+            "20190113200517.500000+060",
+            "20190113200517.500000-180",
+            "20190113193517.500000+000",
+        ]
+        .iter()
+        .map(|s| s.parse().unwrap())
+        .collect();
+
+        dts.sort();
+
+        let sorted: Vec<String> = dts.iter().map(|dt| dt.to_rfc3339()).collect();
+
+        assert_eq!(
+            sorted,
+            [
+                "2019-01-13T20:05:17.500+01:00",
+                "2019-01-13T19:35:17.500+00:00",
+                "2019-01-13T20:05:17.500-03:00",
+            ]
+        );
+    }
+
+    #[test]
+    fn it_deserializes_from_cim_string_or_unix_timestamp() {
+        let dt: WMIDateTime = serde_json::from_str("\"20190113200517.500000+060\"").unwrap();
+        assert_eq!(dt.0.to_rfc3339(), "2019-01-13T20:05:17.500+01:00");
+
+        let cim = Variant::String("20190113200517.500000+060".into());
+        let dt = WMIDateTime::deserialize(cim).unwrap();
+        assert_eq!(dt.0.to_rfc3339(), "2019-01-13T20:05:17.500+01:00");
+
+        let dt = WMIDateTime::deserialize(Variant::UI8(1547406317)).unwrap();
+        assert_eq!(dt.0.to_rfc3339(), "2019-01-13T19:05:17+00:00");
+
+        let dt = WMIDateTime::deserialize(Variant::I8(-1)).unwrap();
+        assert_eq!(dt.0.to_rfc3339(), "1969-12-31T23:59:59+00:00");
+
+        let dt = WMIDateTime::deserialize(Variant::I4(0)).unwrap();
+        assert_eq!(dt.0.to_rfc3339(), "1970-01-01T00:00:00+00:00");
+
+        assert!(WMIDateTime::deserialize(Variant::UI8(u64::max_value())).is_err());
+
+        // `serde_json` doesn't visit integers when asked for a string.
+        assert!(serde_json::from_str::<WMIDateTime>("1547406317").is_err());
+    }
+
     #[test]
     fn it_serializes_to_rfc() {
         let dt: WMIDateTime = "20190113200517.500000+060".parse().unwrap();
 
         let v = serde_json::to_string(&dt).unwrap();
-        assert_eq!(v, "\"2019-01-13T20:05:17.000500+01:00\"");
+        assert_eq!(v, "\"2019-01-13T20:05:17.500+01:00\"");
     }
 }
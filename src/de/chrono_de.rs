@@ -0,0 +1,140 @@
+//! Helpers for deserializing CIM_DATETIME strings directly into `chrono` types,
+//! without wrapping every field with a [`WMIDateTime`](crate::WMIDateTime).
+//!
+//! ```edition2018
+//! # use wmi::*;
+//! # let wmi_con = WMIConnection::new(COMLibrary::new().unwrap().into()).unwrap();
+//! use chrono::{DateTime, NaiveDateTime, Utc};
+//! use serde::Deserialize;
+//!
+//! #[derive(Deserialize, Debug)]
+//! #[serde(rename = "Win32_OperatingSystem")]
+//! #[serde(rename_all = "PascalCase")]
+//! struct OperatingSystem {
+//!     #[serde(deserialize_with = "wmi::de::cim_datetime")]
+//!     last_boot_up_time: DateTime<Utc>,
+//!     #[serde(deserialize_with = "wmi::de::cim_datetime")]
+//!     local_date_time: NaiveDateTime,
+//!     #[serde(deserialize_with = "wmi::de::cim_datetime_opt")]
+//!     install_date: Option<DateTime<Utc>>,
+//! }
+//!
+//! let os: OperatingSystem = wmi_con.get().unwrap();
+//! ```
+//!
+use crate::datetime::WMIDateTime;
+use chrono::{DateTime, FixedOffset, NaiveDateTime, Utc};
+use serde::de::{Deserialize, Deserializer};
+
+/// A `chrono` type which can be created from a parsed CIM_DATETIME.
+///
+pub trait FromWMIDateTime {
+    fn from_wmi_datetime(dt: WMIDateTime) -> Self;
+}
+
+impl FromWMIDateTime for DateTime<FixedOffset> {
+    fn from_wmi_datetime(dt: WMIDateTime) -> Self {
+        dt.0
+    }
+}
+
+impl FromWMIDateTime for DateTime<Utc> {
+    fn from_wmi_datetime(dt: WMIDateTime) -> Self {
+        dt.0.with_timezone(&Utc)
+    }
+}
+
+/// The date and time as written in the CIM_DATETIME string (ignoring the UTC offset).
+impl FromWMIDateTime for NaiveDateTime {
+    fn from_wmi_datetime(dt: WMIDateTime) -> Self {
+        dt.0.naive_local()
+    }
+}
+
+/// Deserialize a CIM_DATETIME string into a `chrono` type.
+/// Use with `#[serde(deserialize_with = "wmi::de::cim_datetime")]`.
+///
+pub fn cim_datetime<'de, D, T>(deserializer: D) -> Result<T, D::Error>
+where
+    D: Deserializer<'de>,
+    T: FromWMIDateTime,
+{
+    let dt = WMIDateTime::deserialize(deserializer)?;
+
+    Ok(T::from_wmi_datetime(dt))
+}
+
+/// Deserialize a (possibly null) CIM_DATETIME string into an optional `chrono` type.
+/// Use with `#[serde(deserialize_with = "wmi::de::cim_datetime_opt")]`.
+///
+pub fn cim_datetime_opt<'de, D, T>(deserializer: D) -> Result<Option<T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: FromWMIDateTime,
+{
+    let dt = Option::<WMIDateTime>::deserialize(deserializer)?;
+
+    Ok(dt.map(T::from_wmi_datetime))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::Deserialize;
+
+    #[derive(Deserialize, Debug)]
+    struct Event {
+        #[serde(deserialize_with = "cim_datetime")]
+        utc: DateTime<Utc>,
+        #[serde(deserialize_with = "cim_datetime")]
+        naive: NaiveDateTime,
+        #[serde(deserialize_with = "cim_datetime_opt")]
+        optional: Option<DateTime<FixedOffset>>,
+    }
+
+    #[test]
+    fn it_desr_present_timestamps() {
+        let event: Event = serde_json::from_str(
+            r#"{
+                "utc": "20190113200517.500000+060",
+                "naive": "20190113200517.500000+060",
+                "optional": "20190113200517.500000-180"
+            }"#,
+        )
+        .unwrap();
+
+        assert_eq!(event.utc.to_rfc3339(), "2019-01-13T19:05:17.500+00:00");
+        assert_eq!(event.naive.to_string(), "2019-01-13 20:05:17.500");
+        assert_eq!(
+            event.optional.unwrap().to_rfc3339(),
+            "2019-01-13T20:05:17.500-03:00"
+        );
+    }
+
+    #[test]
+    fn it_desr_null_timestamp() {
+        let event: Event = serde_json::from_str(
+            r#"{
+                "utc": "20190113200517.500000+060",
+                "naive": "20190113200517.500000+060",
+                "optional": null
+            }"#,
+        )
+        .unwrap();
+
+        assert!(event.optional.is_none());
+    }
+
+    #[test]
+    fn it_fails_to_desr_null_into_required_timestamp() {
+        let res: Result<Event, _> = serde_json::from_str(
+            r#"{
+                "utc": null,
+                "naive": "20190113200517.500000+060",
+                "optional": null
+            }"#,
+        );
+
+        assert!(res.is_err());
+    }
+}
@@ -1,3 +1,13 @@
+#[cfg(target_os = "windows")]
+pub mod chrono_de;
 pub mod meta;
+#[cfg(feature = "uuid")]
+pub mod uuid_de;
 pub mod variant_de;
+#[cfg(target_os = "windows")]
 pub mod wbem_class_de;
+
+#[cfg(target_os = "windows")]
+pub use chrono_de::{cim_datetime, cim_datetime_opt};
+#[cfg(feature = "uuid")]
+pub use uuid_de::{wmi_guid, wmi_guid_opt};
@@ -0,0 +1,103 @@
+//! Helpers for deserializing GUID strings (like `{4D36E967-E325-11CE-BFC1-08002BE10318}`)
+//! directly into `uuid::Uuid`s. Requires the `uuid` feature.
+//!
+//! ```edition2018
+//! # use wmi::*;
+//! # let wmi_con = WMIConnection::new(COMLibrary::new().unwrap().into()).unwrap();
+//! use serde::Deserialize;
+//! use uuid::Uuid;
+//!
+//! #[derive(Deserialize, Debug)]
+//! #[serde(rename = "Win32_PnPEntity")]
+//! #[serde(rename_all = "PascalCase")]
+//! struct PnPEntity {
+//!     #[serde(deserialize_with = "wmi::de::wmi_guid_opt")]
+//!     class_guid: Option<Uuid>,
+//! }
+//!
+//! let entities: Vec<PnPEntity> = wmi_con.query().unwrap();
+//! ```
+//!
+use serde::de::{self, Deserialize, Deserializer};
+use uuid::Uuid;
+
+fn parse_guid<E>(s: &str) -> Result<Uuid, E>
+where
+    E: de::Error,
+{
+    let trimmed = s.trim();
+
+    let unbraced = if trimmed.starts_with('{') && trimmed.ends_with('}') {
+        &trimmed[1..trimmed.len() - 1]
+    } else {
+        trimmed
+    };
+
+    Uuid::parse_str(unbraced).map_err(|e| E::custom(format!("Invalid GUID {:?}: {}", s, e)))
+}
+
+/// Deserialize a GUID string, with or without braces, into a `Uuid`.
+/// Use with `#[serde(deserialize_with = "wmi::de::wmi_guid")]`.
+///
+pub fn wmi_guid<'de, D>(deserializer: D) -> Result<Uuid, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let s = String::deserialize(deserializer)?;
+
+    parse_guid(&s)
+}
+
+/// Deserialize a (possibly null) GUID string, with or without braces, into an optional `Uuid`.
+/// Use with `#[serde(deserialize_with = "wmi::de::wmi_guid_opt")]`.
+///
+pub fn wmi_guid_opt<'de, D>(deserializer: D) -> Result<Option<Uuid>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    match Option::<String>::deserialize(deserializer)? {
+        Some(s) => parse_guid(&s).map(Some),
+        None => Ok(None),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Variant;
+
+    // The `DiskDrive` device setup class.
+    const DISK_DRIVE: u128 = 0x4D36E967_E325_11CE_BFC1_08002BE10318;
+
+    #[test]
+    fn it_desr_braced_guid() {
+        let variant = Variant::String("{4D36E967-E325-11CE-BFC1-08002BE10318}".into());
+
+        assert_eq!(wmi_guid(variant).unwrap(), Uuid::from_u128(DISK_DRIVE));
+    }
+
+    #[test]
+    fn it_desr_unbraced_guid() {
+        let variant = Variant::String("4d36e967-e325-11ce-bfc1-08002be10318".into());
+
+        assert_eq!(wmi_guid(variant).unwrap(), Uuid::from_u128(DISK_DRIVE));
+    }
+
+    #[test]
+    fn it_desr_optional_guid() {
+        let variant = Variant::String("{4D36E967-E325-11CE-BFC1-08002BE10318}".into());
+
+        assert_eq!(
+            wmi_guid_opt(variant).unwrap(),
+            Some(Uuid::from_u128(DISK_DRIVE))
+        );
+        assert_eq!(wmi_guid_opt(Variant::Null).unwrap(), None);
+    }
+
+    #[test]
+    fn it_fails_to_desr_invalid_guid() {
+        assert!(wmi_guid(Variant::String("{4D36E967-E325}".into())).is_err());
+        assert!(wmi_guid(Variant::String("not a guid".into())).is_err());
+        assert!(wmi_guid(Variant::Null).is_err());
+    }
+}
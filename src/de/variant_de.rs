@@ -1,32 +1,50 @@
+use crate::error::Error;
 use crate::variant::Variant;
 use log::debug;
+use serde::de::value::MapDeserializer;
 use serde::{de, forward_to_deserialize_any, Deserialize};
 use std::fmt;
+use std::iter;
 use std::vec::IntoIter;
 
 #[derive(Debug)]
 struct SeqAccess {
     data: IntoIter<Variant>,
+    index: usize,
 }
 
 impl<'de> de::SeqAccess<'de> for SeqAccess {
-    type Error = crate::error::Error;
+    type Error = Error;
 
     fn next_element_seed<T>(&mut self, seed: T) -> Result<Option<T::Value>, Self::Error>
     where
         T: de::DeserializeSeed<'de>,
     {
+        let index = self.index;
+        self.index += 1;
+
         match self.data.next() {
-            Some(variant) => seed.deserialize(variant).map(Some),
+            Some(variant) => seed
+                .deserialize(variant)
+                .map(Some)
+                .map_err(|e| de::Error::custom(format!("element {}: {}", index, e))),
             None => Ok(None),
         }
     }
 }
 
+impl<'de> de::IntoDeserializer<'de, Error> for Variant {
+    type Deserializer = Self;
+
+    fn into_deserializer(self) -> Self::Deserializer {
+        self
+    }
+}
+
 impl<'de> serde::Deserializer<'de> for Variant {
-    type Error = crate::error::Error;
+    type Error = Error;
 
-    fn deserialize_any<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+    fn deserialize_any<V>(self, visitor: V) -> Result<V::Value, Error>
     where
         V: de::Visitor<'de>,
     {
@@ -37,33 +55,271 @@ impl<'de> serde::Deserializer<'de> for Variant {
             Variant::I2(n) => visitor.visit_i16(n),
             Variant::I4(n) => visitor.visit_i32(n),
             Variant::I8(n) => visitor.visit_i64(n),
+            Variant::R4(n) => visitor.visit_f32(n),
+            Variant::R8(n) => visitor.visit_f64(n),
             Variant::Bool(b) => visitor.visit_bool(b),
             Variant::UI1(n) => visitor.visit_u8(n),
             Variant::UI8(n) => visitor.visit_u64(n),
-            Variant::Array(mut v) => visitor.visit_seq(SeqAccess {
+            Variant::Error(scode) => Err(de::Error::custom(format!(
+                "The provider failed to compute the value ({:#X})",
+                scode
+            ))),
+            Variant::Array(v) => visitor.visit_seq(SeqAccess {
                 data: v.into_iter(),
+                index: 0,
             }),
+            Variant::Bytes(bytes) => {
+                visitor.visit_seq(de::value::SeqDeserializer::new(bytes.into_iter()))
+            }
         }
     }
 
-    fn deserialize_option<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+    /// An `Error` (a value which the provider failed to compute) is deserialized as `None`.
+    fn deserialize_option<V>(self, visitor: V) -> Result<V::Value, Error>
     where
         V: de::Visitor<'de>,
     {
         match self {
             Variant::Null => visitor.visit_none(),
             Variant::Empty => visitor.visit_none(),
+            Variant::Error(_) => visitor.visit_none(),
             some => visitor.visit_some(some),
         }
     }
 
+    /// Some providers return `boolean` properties as integers, so the accepted forms are
+    /// `VT_BOOL`, the integers 0 and 1, and the strings "True", "False", "1" and "0".
+    fn deserialize_bool<V>(self, visitor: V) -> Result<V::Value, Error>
+    where
+        V: de::Visitor<'de>,
+    {
+        let value = match &self {
+            Variant::Bool(b) => Some(*b),
+            Variant::I2(n) => int_to_bool((*n).into()),
+            Variant::I4(n) => int_to_bool((*n).into()),
+            Variant::I8(n) => int_to_bool(*n),
+            Variant::UI1(n) => int_to_bool((*n).into()),
+            Variant::String(s) => match s.as_str() {
+                "1" => Some(true),
+                "0" => Some(false),
+                s if s.eq_ignore_ascii_case("true") => Some(true),
+                s if s.eq_ignore_ascii_case("false") => Some(false),
+                _ => None,
+            },
+            _ => None,
+        };
+
+        match value {
+            Some(b) => visitor.visit_bool(b),
+            None => Err(de::Error::custom(format!(
+                "Expected a bool (or 0/1), got {:?}",
+                self
+            ))),
+        }
+    }
+
+    /// Arrays of bytes (`uint8[]`) are visited as a single buffer (for example, for `serde_bytes`).
+    fn deserialize_byte_buf<V>(self, visitor: V) -> Result<V::Value, Error>
+    where
+        V: de::Visitor<'de>,
+    {
+        match self {
+            Variant::Bytes(bytes) => visitor.visit_byte_buf(bytes),
+            Variant::Array(items) => match Variant::Array(items).into_bytes() {
+                Some(bytes) => visitor.visit_byte_buf(bytes),
+                None => Err(de::Error::custom("Expected an array of bytes")),
+            },
+            other => other.deserialize_any(visitor),
+        }
+    }
+
+    fn deserialize_bytes<V>(self, visitor: V) -> Result<V::Value, Error>
+    where
+        V: de::Visitor<'de>,
+    {
+        self.deserialize_byte_buf(visitor)
+    }
+
+    /// Scalar values are coerced to strings (so, for example, any object can be
+    /// deserialized into a `HashMap<String, String>`). Nulls and arrays are not.
+    fn deserialize_string<V>(self, visitor: V) -> Result<V::Value, Error>
+    where
+        V: de::Visitor<'de>,
+    {
+        let s = match self {
+            Variant::String(s) => s,
+            Variant::I2(n) => n.to_string(),
+            Variant::I4(n) => n.to_string(),
+            Variant::I8(n) => n.to_string(),
+            Variant::R4(n) => n.to_string(),
+            Variant::R8(n) => n.to_string(),
+            Variant::Bool(b) => b.to_string(),
+            Variant::UI1(n) => n.to_string(),
+            Variant::UI8(n) => n.to_string(),
+            other => return other.deserialize_any(visitor),
+        };
+
+        visitor.visit_string(s)
+    }
+
+    /// Strings are always owned (they are copied out of the native `BSTR`),
+    /// so borrowing types like `Cow<str>` (even with `#[serde(borrow)]`) get a `Cow::Owned`.
+    ///
+    /// Unlike `deserialize_string`, integers are visited as integers, so types which are
+    /// usually strings but also accept integers (like `WMIDateTime`, which accepts Unix timestamps)
+    /// can handle them.
+    fn deserialize_str<V>(self, visitor: V) -> Result<V::Value, Error>
+    where
+        V: de::Visitor<'de>,
+    {
+        match self {
+            Variant::I2(_)
+            | Variant::I4(_)
+            | Variant::I8(_)
+            | Variant::UI1(_)
+            | Variant::UI8(_) => self.deserialize_any(visitor),
+            other => other.deserialize_string(visitor),
+        }
+    }
+
+    /// WMI returns `uint32` properties as `VT_I4`, so the bits of an `I4` are reinterpreted
+    /// (a bitmask like `0x8000_0000` is returned as a negative number).
+    fn deserialize_u32<V>(self, visitor: V) -> Result<V::Value, Error>
+    where
+        V: de::Visitor<'de>,
+    {
+        match self {
+            Variant::I4(n) => visitor.visit_u32(n as u32),
+            other => other.deserialize_any(visitor),
+        }
+    }
+
+    /// WMI returns `uint64` properties as strings (`VT_BSTR`), which are parsed.
+    fn deserialize_u64<V>(self, visitor: V) -> Result<V::Value, Error>
+    where
+        V: de::Visitor<'de>,
+    {
+        match self {
+            Variant::String(s) => match s.parse() {
+                Ok(n) => visitor.visit_u64(n),
+                Err(_) => Err(de::Error::custom(format!(
+                    "Expected an unsigned integer, got {:?}",
+                    s
+                ))),
+            },
+            other => other.deserialize_any(visitor),
+        }
+    }
+
+    /// WMI returns `sint64` properties as strings (`VT_BSTR`), which are parsed.
+    fn deserialize_i64<V>(self, visitor: V) -> Result<V::Value, Error>
+    where
+        V: de::Visitor<'de>,
+    {
+        match self {
+            Variant::String(s) => match s.parse() {
+                Ok(n) => visitor.visit_i64(n),
+                Err(_) => Err(de::Error::custom(format!(
+                    "Expected an integer, got {:?}",
+                    s
+                ))),
+            },
+            other => other.deserialize_any(visitor),
+        }
+    }
+
+    /// `real32` and `real64` values (and integers) are accepted, anything else
+    /// (like a string in what should be a `real64[]` array) is an error naming the value.
+    fn deserialize_f32<V>(self, visitor: V) -> Result<V::Value, Error>
+    where
+        V: de::Visitor<'de>,
+    {
+        match self {
+            Variant::R8(n) => visitor.visit_f32(n as f32),
+            other => other.deserialize_f64(visitor),
+        }
+    }
+
+    fn deserialize_f64<V>(self, visitor: V) -> Result<V::Value, Error>
+    where
+        V: de::Visitor<'de>,
+    {
+        match self {
+            Variant::R4(_)
+            | Variant::R8(_)
+            | Variant::I2(_)
+            | Variant::I4(_)
+            | Variant::I8(_)
+            | Variant::UI1(_)
+            | Variant::UI8(_) => self.deserialize_any(visitor),
+            other => Err(de::Error::custom(format!(
+                "Expected a float, got {:?}",
+                other
+            ))),
+        }
+    }
+
+    fn deserialize_ignored_any<V>(self, visitor: V) -> Result<V::Value, Error>
+    where
+        V: de::Visitor<'de>,
+    {
+        visitor.visit_unit()
+    }
+
+    /// `Variant`'s own `Deserialize` asks for the value as-is using a marker name,
+    /// any other newtype struct is deserialized from the value itself.
+    fn deserialize_newtype_struct<V>(
+        self,
+        name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Error>
+    where
+        V: de::Visitor<'de>,
+    {
+        if name != VARIANT_TOKEN {
+            return self.deserialize_any(visitor);
+        }
+
+        // These are the values which `deserialize_any` can't visit as-is
+        // (`Empty` would be `None`, `Bytes` a sequence, and an `Error` fails).
+        match self {
+            Variant::Empty => visitor.visit_map(MapDeserializer::new(iter::once((EMPTY_KEY, ())))),
+            Variant::Error(scode) => {
+                visitor.visit_map(MapDeserializer::new(iter::once((ERROR_KEY, scode))))
+            }
+            Variant::Bytes(bytes) => visitor.visit_byte_buf(bytes),
+            other => other.deserialize_any(visitor),
+        }
+    }
+
     forward_to_deserialize_any! {
-        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
-        bytes byte_buf unit unit_struct newtype_struct seq tuple
-        tuple_struct map struct enum identifier ignored_any
+        i8 i16 i32 i128 u8 u16 u128 char
+        unit unit_struct seq tuple
+        tuple_struct map struct enum identifier
+    }
+}
+
+fn int_to_bool(n: i64) -> Option<bool> {
+    match n {
+        0 => Some(false),
+        1 => Some(true),
+        _ => None,
     }
 }
 
+// Like serde_json's `RawValue`, a `Variant` is requested as a newtype struct with this name,
+// so this crate's deserializers can hand it over as-is, using maps with these keys for the
+// values which have no matching serde type.
+const VARIANT_TOKEN: &str = "$wmi::private::Variant";
+const EMPTY_KEY: &str = "$wmi::private::Variant::Empty";
+const ERROR_KEY: &str = "$wmi::private::Variant::Error";
+
+/// Deserializing a `Variant` captures the value as-is (for example, a `VT_I2` stays an `I2`,
+/// an `Empty` stays `Empty`, `Bytes` stay `Bytes`, and an `Error` is kept instead of failing),
+/// so a struct field of type `Variant` can hold properties whose type varies between instances.
+///
+/// Other deserializers (like `serde_json`'s) produce the closest `Variant` to their values.
+///
 impl<'de> Deserialize<'de> for Variant {
     #[inline]
     fn deserialize<D>(deserializer: D) -> Result<Variant, D::Error>
@@ -84,6 +340,21 @@ impl<'de> Deserialize<'de> for Variant {
                 Ok(Variant::Bool(value))
             }
 
+            #[inline]
+            fn visit_i16<E>(self, value: i16) -> Result<Self::Value, E> {
+                Ok(Variant::I2(value))
+            }
+
+            #[inline]
+            fn visit_i32<E>(self, value: i32) -> Result<Self::Value, E> {
+                Ok(Variant::I4(value))
+            }
+
+            #[inline]
+            fn visit_u8<E>(self, value: u8) -> Result<Self::Value, E> {
+                Ok(Variant::UI1(value))
+            }
+
             #[inline]
             fn visit_i64<E>(self, value: i64) -> Result<Self::Value, E> {
                 Ok(Variant::I8(value))
@@ -94,9 +365,14 @@ impl<'de> Deserialize<'de> for Variant {
                 Ok(Variant::UI8(value))
             }
 
+            #[inline]
+            fn visit_f32<E>(self, value: f32) -> Result<Self::Value, E> {
+                Ok(Variant::R4(value))
+            }
+
             #[inline]
             fn visit_f64<E>(self, value: f64) -> Result<Self::Value, E> {
-                unimplemented!();
+                Ok(Variant::R8(value))
             }
 
             #[inline]
@@ -112,6 +388,16 @@ impl<'de> Deserialize<'de> for Variant {
                 Ok(Variant::String(value))
             }
 
+            #[inline]
+            fn visit_bytes<E>(self, value: &[u8]) -> Result<Self::Value, E> {
+                Ok(Variant::Bytes(value.to_vec()))
+            }
+
+            #[inline]
+            fn visit_byte_buf<E>(self, value: Vec<u8>) -> Result<Self::Value, E> {
+                Ok(Variant::Bytes(value))
+            }
+
             #[inline]
             fn visit_none<E>(self) -> Result<Self::Value, E> {
                 Ok(Variant::Null)
@@ -144,14 +430,226 @@ impl<'de> Deserialize<'de> for Variant {
                 Ok(Variant::Array(vec))
             }
 
+            #[inline]
+            fn visit_newtype_struct<D>(self, deserializer: D) -> Result<Self::Value, D::Error>
+            where
+                D: serde::Deserializer<'de>,
+            {
+                deserializer.deserialize_any(self)
+            }
+
             fn visit_map<V>(self, mut visitor: V) -> Result<Self::Value, V::Error>
             where
                 V: de::MapAccess<'de>,
             {
-                unimplemented!()
+                match visitor.next_key::<String>()?.as_deref() {
+                    Some(EMPTY_KEY) => {
+                        visitor.next_value::<()>()?;
+
+                        Ok(Variant::Empty)
+                    }
+                    Some(ERROR_KEY) => Ok(Variant::Error(visitor.next_value()?)),
+                    _ => Err(de::Error::invalid_type(de::Unexpected::Map, &self)),
+                }
             }
         }
 
-        deserializer.deserialize_any(VariantVisitor)
+        deserializer.deserialize_newtype_struct(VARIANT_TOKEN, VariantVisitor)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_desr_bool_from_accepted_forms() {
+        let accepted = vec![
+            (Variant::Bool(true), true),
+            (Variant::Bool(false), false),
+            (Variant::I4(1), true),
+            (Variant::I4(0), false),
+            (Variant::I2(1), true),
+            (Variant::UI1(0), false),
+            (Variant::String("True".into()), true),
+            (Variant::String("False".into()), false),
+            (Variant::String("1".into()), true),
+            (Variant::String("0".into()), false),
+        ];
+
+        for (variant, expected) in accepted {
+            assert_eq!(bool::deserialize(variant).unwrap(), expected);
+        }
+    }
+
+    #[test]
+    fn it_desr_64bit_integers_from_strings() {
+        assert_eq!(
+            u64::deserialize(Variant::String("18446744073709551615".into())).unwrap(),
+            u64::max_value()
+        );
+        assert_eq!(
+            i64::deserialize(Variant::String("-9223372036854775808".into())).unwrap(),
+            i64::min_value()
+        );
+        assert_eq!(
+            Option::<u64>::deserialize(Variant::String("42".into())).unwrap(),
+            Some(42)
+        );
+        assert_eq!(u64::deserialize(Variant::UI8(7)).unwrap(), 7);
+
+        assert!(u64::deserialize(Variant::String("-1".into())).is_err());
+        assert!(i64::deserialize(Variant::String("12a".into())).is_err());
+    }
+
+    #[test]
+    fn it_fails_to_desr_bool_from_other_values() {
+        let rejected = vec![
+            Variant::I4(2),
+            Variant::I4(-1),
+            Variant::String("yes".into()),
+            Variant::Null,
+        ];
+
+        for variant in rejected {
+            assert!(bool::deserialize(variant).is_err());
+        }
+    }
+
+    #[test]
+    fn it_desr_optional_bool_from_int() {
+        assert_eq!(
+            Option::<bool>::deserialize(Variant::I4(1)).unwrap(),
+            Some(true)
+        );
+        assert_eq!(Option::<bool>::deserialize(Variant::Null).unwrap(), None);
+    }
+
+    #[test]
+    fn it_desr_ignored_any() {
+        let variant = Variant::Array(vec![Variant::I4(1), Variant::String("a".into())]);
+
+        assert!(de::IgnoredAny::deserialize(variant).is_ok());
+    }
+
+    #[test]
+    fn it_desr_u32_bitmasks() {
+        #[derive(Deserialize, Debug, PartialEq)]
+        #[serde(transparent)]
+        struct Flags(u32);
+
+        assert_eq!(u32::deserialize(Variant::I4(256)).unwrap(), 256);
+        assert_eq!(
+            u32::deserialize(Variant::I4(0x8000_0001u32 as i32)).unwrap(),
+            0x8000_0001
+        );
+        assert_eq!(
+            Flags::deserialize(Variant::I4(0x8000_0000u32 as i32)).unwrap(),
+            Flags(0x8000_0000)
+        );
+        assert_eq!(u32::deserialize(Variant::UI1(7)).unwrap(), 7);
+        assert!(u32::deserialize(Variant::I8(-1)).is_err());
+    }
+
+    #[test]
+    fn it_coerces_scalars_to_strings() {
+        assert_eq!(String::deserialize(Variant::I4(-4)).unwrap(), "-4");
+        assert_eq!(String::deserialize(Variant::Bool(true)).unwrap(), "true");
+        assert_eq!(String::deserialize(Variant::R8(0.5)).unwrap(), "0.5");
+        assert_eq!(String::deserialize(Variant::UI8(8)).unwrap(), "8");
+        assert!(String::deserialize(Variant::Null).is_err());
+        assert!(String::deserialize(Variant::Array(vec![])).is_err());
+    }
+
+    #[test]
+    fn it_desr_float_arrays() {
+        let doubles = Variant::Array(vec![Variant::R8(1.5), Variant::R8(-0.25)]);
+
+        assert_eq!(Vec::<f64>::deserialize(doubles).unwrap(), [1.5, -0.25]);
+
+        let floats = Variant::Array(vec![Variant::R4(0.5), Variant::R8(2.0)]);
+
+        assert_eq!(Vec::<f32>::deserialize(floats).unwrap(), [0.5, 2.0]);
+
+        let mixed = Variant::Array(vec![Variant::R8(1.0), Variant::String("2.0".into())]);
+
+        let err = Vec::<f64>::deserialize(mixed).unwrap_err();
+
+        assert_eq!(
+            format!("{}", err),
+            r#"element 1: Expected a float, got String("2.0")"#
+        );
+    }
+
+    #[test]
+    fn it_desr_cow_str() {
+        use std::borrow::Cow;
+
+        let s = Cow::<str>::deserialize(Variant::String("abc".into())).unwrap();
+
+        assert!(matches!(s, Cow::Owned(ref s) if s == "abc"));
+
+        assert_eq!(
+            Option::<Cow<str>>::deserialize(Variant::Null).unwrap(),
+            None
+        );
+        assert_eq!(Cow::<str>::deserialize(Variant::I4(4)).unwrap(), "4");
+    }
+
+    #[test]
+    fn it_desr_error_variants_as_failures_or_none() {
+        let err = u32::deserialize(Variant::Error(0x8004_1001u32 as i32)).unwrap_err();
+
+        assert_eq!(
+            format!("{}", err),
+            "The provider failed to compute the value (0x80041001)"
+        );
+        assert!(String::deserialize(Variant::Error(1)).is_err());
+
+        assert_eq!(
+            Option::<u32>::deserialize(Variant::Error(0x8004_1001u32 as i32)).unwrap(),
+            None
+        );
+    }
+
+    #[test]
+    fn it_desr_variant_as_is() {
+        let values = vec![
+            Variant::Empty,
+            Variant::Null,
+            Variant::Bytes(vec![0xCA, 0xFE]),
+            Variant::Error(0x8004_1001u32 as i32),
+            Variant::Array(vec![Variant::Empty, Variant::Error(1), Variant::I2(2)]),
+        ];
+
+        for value in values {
+            assert_eq!(Variant::deserialize(value.clone()).unwrap(), value);
+        }
+    }
+
+    #[test]
+    fn it_desr_variant_fields_as_is() {
+        use serde::de::value::MapDeserializer;
+
+        #[derive(Deserialize, Debug)]
+        #[allow(non_snake_case)]
+        struct Fixture {
+            Missing: Variant,
+            Data: Variant,
+            Counter: Variant,
+        }
+
+        let properties = vec![
+            ("Missing".to_owned(), Variant::Empty),
+            ("Data".to_owned(), Variant::Bytes(vec![0xCA, 0xFE])),
+            ("Counter".to_owned(), Variant::Error(0x8004_1001u32 as i32)),
+        ];
+
+        let fixture: Fixture =
+            Fixture::deserialize(MapDeserializer::<_, Error>::new(properties.into_iter())).unwrap();
+
+        assert_eq!(fixture.Missing, Variant::Empty);
+        assert_eq!(fixture.Data, Variant::Bytes(vec![0xCA, 0xFE]));
+        assert_eq!(fixture.Counter, Variant::Error(0x8004_1001u32 as i32));
     }
 }
@@ -2,23 +2,114 @@ use failure::format_err;
 use serde::de::{self, DeserializeOwned, DeserializeSeed, IntoDeserializer, MapAccess, Visitor};
 use serde::forward_to_deserialize_any;
 
-use std::{iter::Peekable, mem, ptr};
-use widestring::WideCString;
-use winapi::{um::oaidl::VARIANT, um::oleauto::VariantClear};
+use std::cell::RefCell;
+use std::collections::HashSet;
+use std::iter::Peekable;
+use winapi::um::wbemcli::WBEM_E_NOT_FOUND;
 
 use crate::error::Error;
-use crate::result_enumerator::IWbemClassWrapper;
-use crate::variant::Variant;
+use crate::result_enumerator::{IWbemClassWrapper, PropertyNamesCache};
+use crate::utils::{bstr_to_i64, bstr_to_u64, WMIError};
+use crate::variant::{NativeVariant, Variant};
 
 pub struct Deserializer<'a> {
     // This string starts with the input data and characters are truncated off
     // the beginning as data is parsed.
     pub wbem_class_obj: &'a IWbemClassWrapper,
+    pub trim_strings: bool,
+    pub property_names_cache: Option<&'a RefCell<PropertyNamesCache>>,
+    pub on_error: OnError,
+    // The properties which failed to convert in previous attempts.
+    failed_properties: HashSet<String>,
+    // The property which failed to convert in the current attempt.
+    last_failed_property: RefCell<Option<String>>,
+}
+
+/// What to do when a single property fails to convert
+/// (for example, a malformed CIM_DATETIME string), used by
+/// [`Deserializer::deserialize_with_policy`].
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OnError {
+    /// Fail the entire object (the default).
+    #[default]
+    Fail,
+    /// Use the field's "empty" value instead: `None` for `Option`s, `0`, `false`,
+    /// an empty string or an empty `Vec`. Fields without such a value (like a `WMIDateTime`)
+    /// still fail the entire object.
+    UseDefault,
+    /// Treat the property as if it was missing, so the field gets its `#[serde(default)]`
+    /// (or `None` for `Option`s). Required fields still fail the entire object.
+    Skip,
 }
 
 impl<'a> Deserializer<'a> {
     pub fn from_wbem_class_obj(wbem_class_obj: &'a IWbemClassWrapper) -> Self {
-        Deserializer { wbem_class_obj }
+        Deserializer {
+            wbem_class_obj,
+            trim_strings: false,
+            property_names_cache: None,
+            on_error: OnError::Fail,
+            failed_properties: HashSet::new(),
+            last_failed_property: RefCell::new(None),
+        }
+    }
+
+    /// Trim leading and trailing whitespace from every string value (including array elements).
+    pub fn trim_strings(mut self, trim_strings: bool) -> Self {
+        self.trim_strings = trim_strings;
+        self
+    }
+
+    /// List the properties using the given cache (shared by the objects of an enumeration)
+    /// when deserializing a map, instead of retrieving them from the object.
+    /// Structs only read their own fields, so they never use the cache.
+    pub fn property_names_cache(mut self, cache: &'a RefCell<PropertyNamesCache>) -> Self {
+        self.property_names_cache = Some(cache);
+        self
+    }
+
+    /// Set what to do when a single property fails to convert.
+    pub fn on_error(mut self, on_error: OnError) -> Self {
+        self.on_error = on_error;
+        self
+    }
+
+    /// Deserialize the object, applying the `on_error` policy to properties which fail to convert.
+    ///
+    /// Every failed property is recorded, and the object is deserialized again
+    /// (with the property's value replaced or skipped) until it succeeds.
+    ///
+    pub fn deserialize_with_policy<T>(&mut self) -> Result<T, Error>
+    where
+        T: DeserializeOwned,
+    {
+        loop {
+            self.last_failed_property.replace(None);
+
+            let err = match T::deserialize(&mut *self) {
+                Ok(t) => return Ok(t),
+                Err(err) => err,
+            };
+
+            match self.last_failed_property.take() {
+                Some(property) if self.on_error != OnError::Fail => {
+                    // A property which fails again (for example, a required field which was skipped).
+                    if !self.failed_properties.insert(property) {
+                        return Err(err);
+                    }
+                }
+                _ => return Err(err),
+            }
+        }
+    }
+}
+
+fn trim_variant(value: Variant) -> Variant {
+    match value {
+        Variant::String(s) => Variant::String(s.trim().to_owned()),
+        Variant::Array(items) => Variant::Array(items.into_iter().map(trim_variant).collect()),
+        other => other,
     }
 }
 
@@ -32,21 +123,37 @@ where
     Ok(t)
 }
 
-struct WMIMapAccess<'a, 'de, S, I>
+/// Like `from_wbem_class_obj`, but applying the given policy to properties which fail to convert.
+///
+pub fn from_wbem_class_obj_with_policy<T>(
+    wbem_class_obj: &IWbemClassWrapper,
+    on_error: OnError,
+) -> Result<T, Error>
+where
+    T: DeserializeOwned,
+{
+    Deserializer::from_wbem_class_obj(wbem_class_obj)
+        .on_error(on_error)
+        .deserialize_with_policy()
+}
+
+// Property values are never borrowed from the object, so the map can be deserialized
+// with any lifetime (which allows deserializing embedded objects, see `PropertyValue`).
+struct WMIMapAccess<'a, 'b, S, I>
 where
     S: AsRef<str>,
     I: Iterator<Item = S>,
 {
     fields: Peekable<I>,
-    de: &'a Deserializer<'de>,
+    de: &'a Deserializer<'b>,
 }
 
-impl<'a, 'de, S, I> WMIMapAccess<'a, 'de, S, I>
+impl<'a, 'b, S, I> WMIMapAccess<'a, 'b, S, I>
 where
     S: AsRef<str>,
     I: Iterator<Item = S>,
 {
-    pub fn new(fields: I, de: &'a Deserializer<'de>) -> Self {
+    pub fn new(fields: I, de: &'a Deserializer<'b>) -> Self {
         Self {
             fields: fields.peekable(),
             de,
@@ -54,7 +161,7 @@ where
     }
 }
 
-impl<'de, 'a, S, I> MapAccess<'de> for WMIMapAccess<'a, 'de, S, I>
+impl<'de, 'a, 'b, S, I> MapAccess<'de> for WMIMapAccess<'a, 'b, S, I>
 where
     S: AsRef<str>,
     I: Iterator<Item = S>,
@@ -65,6 +172,16 @@ where
     where
         K: DeserializeSeed<'de>,
     {
+        if self.de.on_error == OnError::Skip {
+            while let Some(field) = self.fields.peek() {
+                if !self.de.failed_properties.contains(field.as_ref()) {
+                    break;
+                }
+
+                self.fields.next();
+            }
+        }
+
         if let Some(field) = self.fields.peek() {
             seed.deserialize(field.as_ref().into_deserializer())
                 .map(Some)
@@ -82,49 +199,100 @@ where
             .next()
             .ok_or(format_err!("Expected current field to not be None"))?;
 
-        let name_prop = WideCString::from_str(current_field).map_err(Error::from_err)?;
+        let property_value = match self
+            .de
+            .wbem_class_obj
+            .get_native_property(current_field.as_ref())
+        {
+            Ok((property_value, _)) => property_value,
+            // A property which the object doesn't have (for example, a field of a subclass
+            // when querying its base class) is `Empty`, so `Option` fields are `None`.
+            Err(e) if is_missing_property(&e) => NativeVariant::empty(),
+            Err(e) => return Err(property_error(current_field.as_ref(), e)),
+        };
 
-        let mut vt_prop: VARIANT = unsafe { mem::zeroed() };
+        let res = if self.de.on_error == OnError::UseDefault
+            && self.de.failed_properties.contains(current_field.as_ref())
+        {
+            seed.deserialize(DefaultValueDeserializer)
+        } else if self.de.trim_strings {
+            let value = property_value
+                .to_variant()
+                .map_err(|e| property_error(current_field.as_ref(), e))?;
 
-        unsafe {
-            (*self.de.wbem_class_obj.inner.unwrap().as_ptr()).Get(
-                name_prop.as_ptr() as *mut _,
-                0,
-                &mut vt_prop,
-                ptr::null_mut(),
-                ptr::null_mut(),
-            );
-        }
+            seed.deserialize(trim_variant(value))
+        } else {
+            seed.deserialize(PropertyValue(property_value))
+        };
+
+        res.map_err(|err| {
+            self.de
+                .last_failed_property
+                .replace(Some(current_field.as_ref().to_owned()));
+
+            property_error(current_field.as_ref(), err.into_inner())
+        })
+    }
+}
 
-        let property_value = Variant::from_variant(vt_prop)?;
+/// Wrap an error which occurred while reading or deserializing a property, keeping it as the `source`
+/// (as a `WMIError` if it is one, so it can be downcast).
+fn property_error(field: &str, err: failure::Error) -> Error {
+    let source: Box<dyn std::error::Error + Send + Sync> = match err.downcast::<WMIError>() {
+        Ok(err) => Box::new(err),
+        Err(err) => Box::new(err.compat()),
+    };
 
-        unsafe { VariantClear(&mut vt_prop) };
+    Error::from(failure::Error::from(WMIError::Deserialization {
+        field: field.to_owned(),
+        source,
+    }))
+}
 
-        seed.deserialize(property_value)
+fn is_missing_property(err: &failure::Error) -> bool {
+    match err.downcast_ref::<WMIError>() {
+        Some(WMIError::HResultError { hres }) => *hres as u32 == WBEM_E_NOT_FOUND,
+        _ => false,
     }
 }
 
 impl<'de, 'a> de::Deserializer<'de> for &'a mut Deserializer<'de> {
     type Error = Error;
 
+    /// A WMI object is always a map of its properties, so self-describing types
+    /// (like `serde_json::Value`) are deserialized as a map of all the available properties.
     fn deserialize_any<V>(self, visitor: V) -> Result<V::Value, Self::Error>
     where
         V: Visitor<'de>,
     {
-        Err(Error::from_err(format_err!(
-            "Only structs and maps can be deserialized from WMI objects"
-        )))
+        self.deserialize_map(visitor)
     }
 
+    /// Used for maps, but also for structs with a `#[serde(flatten)]` field,
+    /// in which case all the properties of the object are visited and the ones
+    /// which do not match a named field are collected by the flattened field.
     fn deserialize_map<V>(self, visitor: V) -> Result<V::Value, Self::Error>
     where
         V: Visitor<'de>,
     {
-        let fields = self.wbem_class_obj.list_properties()?;
+        match self.property_names_cache {
+            Some(cache) => {
+                let mut cache = cache.borrow_mut();
+                let fields = cache.list_properties(self.wbem_class_obj)?;
 
-        visitor.visit_map(WMIMapAccess::new(fields.iter(), &self))
+                visitor.visit_map(WMIMapAccess::new(fields.iter(), self))
+            }
+            None => {
+                let fields = self.wbem_class_obj.list_properties()?;
+
+                visitor.visit_map(WMIMapAccess::new(fields.iter(), self))
+            }
+        }
     }
 
+    /// Properties are retrieved using the struct's field names.
+    /// WMI property names are case-insensitive, so a field named `ProcessID`
+    /// matches the `ProcessId` property (and the field keeps its own name).
     fn deserialize_struct<V>(
         self,
         name: &'static str,
@@ -134,179 +302,1381 @@ impl<'de, 'a> de::Deserializer<'de> for &'a mut Deserializer<'de> {
     where
         V: Visitor<'de>,
     {
-        visitor.visit_map(WMIMapAccess::new(fields.iter(), &self))
+        visitor.visit_map(WMIMapAccess::new(fields.iter(), self))
+    }
+
+    /// Enums are used for polymorphic results (like the instances of different subclasses
+    /// returned when querying a base class): the variant is selected by the object's `__CLASS`,
+    /// and its contents (a struct or a newtype) are deserialized from the entire object.
+    ///
+    /// Use `#[serde(other)]` on a unit variant to allow classes without a matching variant.
+    fn deserialize_enum<V>(
+        self,
+        _name: &'static str,
+        _variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        let class = match self.wbem_class_obj.get_property("__CLASS")? {
+            Variant::String(class) => class,
+            other => {
+                return Err(de::Error::custom(format!(
+                    "Expected `__CLASS` to be a string, got {:?}",
+                    other
+                )))
+            }
+        };
+
+        visitor.visit_enum(WMIEnumAccess { class, de: self })
+    }
+
+    /// Ignored values are discarded without reading any of the object's properties.
+    fn deserialize_ignored_any<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_unit()
     }
 
     forward_to_deserialize_any! {
         bool i8 i16 i32 i64 u8 u16 u32 u64 f32 f64 char str string bytes
         byte_buf option unit unit_struct newtype_struct seq tuple
-        tuple_struct enum identifier ignored_any
+        tuple_struct identifier
     }
 }
 
-#[allow(non_snake_case)]
-#[allow(non_camel_case_types)]
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use crate::datetime::WMIDateTime;
-    use serde::Deserialize;
-    use std::collections::HashMap;
-
-    use crate::tests::fixtures::*;
-
-    #[test]
-    fn it_works() {
-        let wmi_con = wmi_con();
-
-        #[derive(Deserialize, Debug)]
-        struct Win32_OperatingSystem {
-            Caption: String,
-            Name: String,
-            CurrentTimeZone: i16,
-            Debug: bool,
+struct WMIEnumAccess<'a, 'de> {
+    class: String,
+    de: &'a mut Deserializer<'de>,
+}
 
-            // This actually returns as an i32 from COM.
-            EncryptionLevel: u32,
-            ForegroundApplicationBoost: u8,
+impl<'de, 'a> de::EnumAccess<'de> for WMIEnumAccess<'a, 'de> {
+    type Error = Error;
+    type Variant = Self;
 
-            LastBootUpTime: WMIDateTime,
-        }
+    fn variant_seed<V>(self, seed: V) -> Result<(V::Value, Self::Variant), Self::Error>
+    where
+        V: DeserializeSeed<'de>,
+    {
+        let class: de::value::StringDeserializer<Error> = self.class.clone().into_deserializer();
 
-        let enumerator = wmi_con
-            .exec_query_native_wrapper("SELECT * FROM Win32_OperatingSystem")
-            .unwrap();
+        Ok((seed.deserialize(class)?, self))
+    }
+}
 
-        for res in enumerator {
-            let w = res.unwrap();
+impl<'de, 'a> de::VariantAccess<'de> for WMIEnumAccess<'a, 'de> {
+    type Error = Error;
 
-            let w: Win32_OperatingSystem = from_wbem_class_obj(&w).unwrap();
+    fn unit_variant(self) -> Result<(), Self::Error> {
+        Ok(())
+    }
 
-            assert_eq!(w.Caption, "Microsoft Windows 10 Pro");
-            assert_eq!(
-                w.Name,
-                "Microsoft Windows 10 Pro|C:\\WINDOWS|\\Device\\Harddisk0\\Partition3"
-            );
-            assert_eq!(w.CurrentTimeZone, 120);
-            assert_eq!(w.Debug, false);
-            assert_eq!(w.EncryptionLevel, 256);
-            assert_eq!(w.ForegroundApplicationBoost, 2);
-            assert_eq!(
-                w.LastBootUpTime.0.timezone().local_minus_utc() / 60,
-                w.CurrentTimeZone as i32
-            );
-        }
+    fn newtype_variant_seed<T>(self, seed: T) -> Result<T::Value, Self::Error>
+    where
+        T: DeserializeSeed<'de>,
+    {
+        seed.deserialize(&mut *self.de)
     }
 
-    #[test]
-    fn it_desr_into_map() {
-        let wmi_con = wmi_con();
+    fn tuple_variant<V>(self, _len: usize, _visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        Err(de::Error::custom(format!(
+            "Tuple variants are not supported (for class {})",
+            self.class
+        )))
+    }
 
-        let enumerator = wmi_con
-            .exec_query_native_wrapper("SELECT * FROM Win32_OperatingSystem")
-            .unwrap();
+    fn struct_variant<V>(
+        self,
+        fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        de::Deserializer::deserialize_struct(&mut *self.de, "", fields, visitor)
+    }
+}
 
-        for res in enumerator {
-            let w = res.unwrap();
+/// A property's native value, which is converted into a `Variant` when deserialized.
+///
+/// WMI returns `uint64` and `sint64` properties as strings, which are parsed directly
+/// (without allocating a `String`) when deserializing integers.
+///
+/// Embedded objects (like the `TargetInstance` of an event, or the `Representative`
+/// of an `__AggregateEvent`) are deserialized as a struct or a map of their properties.
+struct PropertyValue(NativeVariant);
 
-            let w: HashMap<String, Variant> = from_wbem_class_obj(&w).unwrap();
+fn visit_embedded_object<'de, V>(
+    object: &IWbemClassWrapper,
+    fields: Option<&'static [&'static str]>,
+    visitor: V,
+) -> Result<V::Value, Error>
+where
+    V: Visitor<'de>,
+{
+    let de = Deserializer::from_wbem_class_obj(object);
 
-            assert_eq!(
-                *w.get("Caption").unwrap(),
-                Variant::String("Microsoft Windows 10 Pro".into())
-            );
-            assert_eq!(*w.get("Debug").unwrap(), Variant::Bool(false));
+    match fields {
+        Some(fields) => visitor.visit_map(WMIMapAccess::new(fields.iter(), &de)),
+        None => {
+            let fields = object.list_properties()?;
 
-            assert_eq!(
-                *w.get("MUILanguages").unwrap(),
-                Variant::Array(vec![Variant::String("en-US".into())])
-            );
+            visitor.visit_map(WMIMapAccess::new(fields.iter(), &de))
         }
     }
+}
 
-    #[test]
-    fn it_desr_into_map_with_selected_fields() {
-        let wmi_con = wmi_con();
-
-        let enumerator = wmi_con
-            .exec_query_native_wrapper("SELECT Caption FROM Win32_OperatingSystem")
-            .unwrap();
-
-        for res in enumerator {
-            let w = res.unwrap();
+macro_rules! forward_to_variant {
+    ($($method:ident)*) => {
+        $(
+            fn $method<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+            where
+                V: Visitor<'de>,
+            {
+                self.0.to_variant()?.$method(visitor)
+            }
+        )*
+    };
+}
 
-            let w: HashMap<String, Variant> = from_wbem_class_obj(&w).unwrap();
+impl<'de> de::Deserializer<'de> for PropertyValue {
+    type Error = Error;
 
-            assert_eq!(
-                *w.get("Caption").unwrap(),
-                Variant::String("Microsoft Windows 10 Pro".into())
-            );
-            assert_eq!(w.get("Debug"), None);
+    fn deserialize_any<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        match self.0.as_object()? {
+            Some(object) => visit_embedded_object(&object, None, visitor),
+            None => self.0.to_variant()?.deserialize_any(visitor),
         }
     }
 
-    #[test]
-    fn it_desr_array() {
-        let wmi_con = wmi_con();
-
-        #[derive(Deserialize, Debug)]
-        struct Win32_ComputerSystem {
-            BootStatus: Vec<i32>,
-            Roles: Vec<String>,
+    fn deserialize_map<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        match self.0.as_object()? {
+            Some(object) => visit_embedded_object(&object, None, visitor),
+            None => self.0.to_variant()?.deserialize_map(visitor),
         }
+    }
 
-        let results: Vec<Win32_ComputerSystem> = wmi_con.query().unwrap();
-
-        for res in results {
-            assert_eq!(res.BootStatus, [0, 0, 0, 33, 31, 158, 0, 3, 2, 2]);
-            assert_eq!(res.Roles, ["LM_Workstation", "LM_Server", "NT"]);
+    fn deserialize_u64<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        match self.0.as_bstr() {
+            Some(bstr) => visitor.visit_u64(unsafe { bstr_to_u64(bstr) }?),
+            None => self.0.to_variant()?.deserialize_u64(visitor),
         }
     }
 
-    #[test]
-    fn it_desr_option_string() {
-        let wmi_con = wmi_con();
+    fn deserialize_i64<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        match self.0.as_bstr() {
+            Some(bstr) => visitor.visit_i64(unsafe { bstr_to_i64(bstr) }?),
+            None => self.0.to_variant()?.deserialize_i64(visitor),
+        }
+    }
 
-        #[derive(Deserialize, Debug)]
-        pub struct Win32_Service {
-            pub Name: String,
-            pub PathName: Option<String>,
+    fn deserialize_option<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        // Keep the value native, so an `Option<u64>` also uses the fast path
+        // (and an `Option` of an embedded object is deserialized from the object).
+        if self.0.as_bstr().is_some() || self.0.as_object()?.is_some() {
+            return visitor.visit_some(self);
         }
 
-        let results: Vec<Win32_Service> = wmi_con.query().unwrap();
+        self.0.to_variant()?.deserialize_option(visitor)
+    }
 
-        let lsm_service = results
-            .iter()
-            .find(|&service| service.Name == "LSM")
-            .unwrap();
+    forward_to_variant! {
+        deserialize_bool deserialize_i8 deserialize_i16 deserialize_i32
+        deserialize_u8 deserialize_u16 deserialize_u32 deserialize_f32 deserialize_f64
+        deserialize_char deserialize_str deserialize_string deserialize_bytes
+        deserialize_byte_buf deserialize_unit deserialize_seq
+        deserialize_identifier deserialize_ignored_any
+    }
 
-        assert_eq!(lsm_service.PathName, None);
+    fn deserialize_unit_struct<V>(
+        self,
+        name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        self.0.to_variant()?.deserialize_unit_struct(name, visitor)
+    }
 
-        let lmhosts_service = results
-            .iter()
-            .find(|&service| service.Name == "lmhosts")
-            .unwrap();
+    fn deserialize_newtype_struct<V>(
+        self,
+        name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        self.0
+            .to_variant()?
+            .deserialize_newtype_struct(name, visitor)
+    }
 
-        assert!(lmhosts_service.PathName.is_some());
+    fn deserialize_tuple<V>(self, len: usize, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        self.0.to_variant()?.deserialize_tuple(len, visitor)
     }
 
-    #[test]
-    fn it_fail_to_desr_null_to_string() {
-        // Values can return as Null / Empty from WMI.
-        // It is impossible to `desr` such values to `String` (for example).
-        // See `it_desr_option_string` on how to fix this error.
-        let wmi_con = wmi_con();
+    fn deserialize_tuple_struct<V>(
+        self,
+        name: &'static str,
+        len: usize,
+        visitor: V,
+    ) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        self.0
+            .to_variant()?
+            .deserialize_tuple_struct(name, len, visitor)
+    }
 
-        #[derive(Deserialize, Debug)]
-        pub struct Win32_Service {
-            pub Name: String,
-            pub PathName: String,
+    fn deserialize_struct<V>(
+        self,
+        name: &'static str,
+        fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        match self.0.as_object()? {
+            Some(object) => visit_embedded_object(&object, Some(fields), visitor),
+            None => self
+                .0
+                .to_variant()?
+                .deserialize_struct(name, fields, visitor),
+        }
+    }
+
+    fn deserialize_enum<V>(
+        self,
+        name: &'static str,
+        variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        self.0
+            .to_variant()?
+            .deserialize_enum(name, variants, visitor)
+    }
+}
+
+/// Produces the "empty" value of the deserialized type, used for `OnError::UseDefault`.
+struct DefaultValueDeserializer;
+
+impl<'de> de::Deserializer<'de> for DefaultValueDeserializer {
+    type Error = Error;
+
+    fn deserialize_any<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_unit()
+    }
+
+    fn deserialize_bool<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_bool(false)
+    }
+
+    fn deserialize_i8<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_i8(0)
+    }
+
+    fn deserialize_i16<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_i16(0)
+    }
+
+    fn deserialize_i32<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_i32(0)
+    }
+
+    fn deserialize_i64<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_i64(0)
+    }
+
+    fn deserialize_u8<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_u8(0)
+    }
+
+    fn deserialize_u16<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_u16(0)
+    }
+
+    fn deserialize_u32<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_u32(0)
+    }
+
+    fn deserialize_u64<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_u64(0)
+    }
+
+    fn deserialize_f32<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_f32(0.0)
+    }
+
+    fn deserialize_f64<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_f64(0.0)
+    }
+
+    fn deserialize_str<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_str("")
+    }
+
+    fn deserialize_string<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_string(String::new())
+    }
+
+    fn deserialize_bytes<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_bytes(&[])
+    }
+
+    fn deserialize_byte_buf<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_byte_buf(vec![])
+    }
+
+    fn deserialize_option<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_none()
+    }
+
+    fn deserialize_newtype_struct<V>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_seq<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_seq(de::value::SeqDeserializer::new(
+            std::iter::empty::<Variant>(),
+        ))
+    }
+
+    fn deserialize_map<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_map(de::value::MapDeserializer::new(std::iter::empty::<(
+            Variant,
+            Variant,
+        )>()))
+    }
+
+    forward_to_deserialize_any! {
+        i128 u128 char unit unit_struct tuple
+        tuple_struct struct enum identifier ignored_any
+    }
+}
+
+#[allow(non_snake_case)]
+#[allow(non_camel_case_types)]
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::datetime::WMIDateTime;
+    use crate::WMIConnection;
+    use serde::Deserialize;
+    use std::collections::HashMap;
+
+    use crate::tests::fixtures::*;
+    use crate::tests::{make_event, make_object};
+
+    #[test]
+    fn it_works() {
+        let wmi_con = wmi_con();
+
+        #[derive(Deserialize, Debug)]
+        struct Win32_OperatingSystem {
+            Caption: String,
+            Name: String,
+            CurrentTimeZone: i16,
+            Debug: bool,
+
+            // This actually returns as an i32 from COM.
+            EncryptionLevel: u32,
+            ForegroundApplicationBoost: u8,
+
+            LastBootUpTime: WMIDateTime,
+        }
+
+        let enumerator = wmi_con
+            .exec_query_native_wrapper("SELECT * FROM Win32_OperatingSystem")
+            .unwrap();
+
+        for res in enumerator {
+            let w = res.unwrap();
+
+            let w: Win32_OperatingSystem = from_wbem_class_obj(&w).unwrap();
+
+            assert_eq!(w.Caption, "Microsoft Windows 10 Pro");
+            assert_eq!(
+                w.Name,
+                "Microsoft Windows 10 Pro|C:\\WINDOWS|\\Device\\Harddisk0\\Partition3"
+            );
+            assert_eq!(w.CurrentTimeZone, 120);
+            assert_eq!(w.Debug, false);
+            assert_eq!(w.EncryptionLevel, 256);
+            assert_eq!(w.ForegroundApplicationBoost, 2);
+            assert_eq!(
+                w.LastBootUpTime.0.timezone().local_minus_utc() / 60,
+                w.CurrentTimeZone as i32
+            );
+        }
+    }
+
+    #[test]
+    fn it_desr_into_map() {
+        let wmi_con = wmi_con();
+
+        let enumerator = wmi_con
+            .exec_query_native_wrapper("SELECT * FROM Win32_OperatingSystem")
+            .unwrap();
+
+        for res in enumerator {
+            let w = res.unwrap();
+
+            let w: HashMap<String, Variant> = from_wbem_class_obj(&w).unwrap();
+
+            assert_eq!(
+                *w.get("Caption").unwrap(),
+                Variant::String("Microsoft Windows 10 Pro".into())
+            );
+            assert_eq!(*w.get("Debug").unwrap(), Variant::Bool(false));
+
+            assert_eq!(
+                *w.get("MUILanguages").unwrap(),
+                Variant::Array(vec![Variant::String("en-US".into())])
+            );
+        }
+    }
+
+    #[test]
+    fn it_desr_into_map_with_selected_fields() {
+        let wmi_con = wmi_con();
+
+        let enumerator = wmi_con
+            .exec_query_native_wrapper("SELECT Caption FROM Win32_OperatingSystem")
+            .unwrap();
+
+        for res in enumerator {
+            let w = res.unwrap();
+
+            let w: HashMap<String, Variant> = from_wbem_class_obj(&w).unwrap();
+
+            assert_eq!(
+                *w.get("Caption").unwrap(),
+                Variant::String("Microsoft Windows 10 Pro".into())
+            );
+            assert_eq!(w.get("Debug"), None);
+        }
+    }
+
+    #[test]
+    fn it_desr_extra_properties_into_flattened_map() {
+        let wmi_con = wmi_con();
+
+        #[derive(Deserialize, Debug)]
+        struct Win32_OperatingSystem {
+            Caption: String,
+            Debug: bool,
+
+            #[serde(flatten)]
+            extra: HashMap<String, Variant>,
+        }
+
+        let results: Vec<Win32_OperatingSystem> = wmi_con
+            .raw_query("SELECT * FROM Win32_OperatingSystem")
+            .unwrap();
+
+        for res in results {
+            assert_eq!(res.Caption, "Microsoft Windows 10 Pro");
+            assert_eq!(res.Debug, false);
+
+            // Named fields are consumed, and everything else lands in the catch-all map.
+            assert_eq!(res.extra.get("Caption"), None);
+            assert_eq!(res.extra.get("Debug"), None);
+            assert_eq!(
+                *res.extra.get("MUILanguages").unwrap(),
+                Variant::Array(vec![Variant::String("en-US".into())])
+            );
+        }
+    }
+
+    #[test]
+    fn it_desr_into_json_value() {
+        let wmi_con = wmi_con();
+
+        let enumerator = wmi_con
+            .exec_query_native_wrapper("SELECT * FROM Win32_OperatingSystem")
+            .unwrap();
+
+        for res in enumerator {
+            let w = res.unwrap();
+
+            let w: serde_json::Value = from_wbem_class_obj(&w).unwrap();
+
+            assert_eq!(w["Caption"], serde_json::json!("Microsoft Windows 10 Pro"));
+            assert_eq!(w["Debug"], serde_json::json!(false));
+            assert_eq!(w["CurrentTimeZone"], serde_json::json!(120));
+            assert_eq!(w["MUILanguages"], serde_json::json!(["en-US"]));
+            assert!(w.as_object().unwrap().contains_key("BootDevice"));
+        }
+    }
+
+    #[test]
+    fn it_desr_variant_fields() {
+        let wmi_con = wmi_con();
+
+        #[derive(Deserialize, Debug)]
+        struct Win32_OperatingSystem {
+            Caption: String,
+            CurrentTimeZone: Variant,
+            Debug: Variant,
+            MUILanguages: Variant,
+        }
+
+        let results: Vec<Win32_OperatingSystem> = wmi_con.query().unwrap();
+
+        for res in results {
+            assert_eq!(res.Caption, "Microsoft Windows 10 Pro");
+            assert_eq!(res.CurrentTimeZone, Variant::I2(120));
+            assert_eq!(res.Debug, Variant::Bool(false));
+            assert_eq!(
+                res.MUILanguages,
+                Variant::Array(vec![Variant::String("en-US".into())])
+            );
+        }
+    }
+
+    #[test]
+    fn it_desr_byte_arrays_and_missing_properties_into_variant_fields() {
+        use std::{mem, ptr};
+        use widestring::WideCString;
+        use winapi::shared::wtypes::{VARTYPE, VT_ARRAY, VT_UI1};
+        use winapi::um::oaidl::VARIANT;
+        use winapi::um::oleauto::{
+            SafeArrayAccessData, SafeArrayCreateVector, SafeArrayUnaccessData, VariantClear,
+        };
+        use winapi::um::wbemcli::{CIM_FLAG_ARRAY, CIM_UINT8};
+
+        use crate::utils::check_hres;
+
+        #[derive(Deserialize, Debug)]
+        struct WMIRS_Fixture {
+            Data: Variant,
+            Missing: Variant,
+        }
+
+        let obj = make_object(&[("Name", Variant::String("A".into()))]);
+
+        let bytes = [0xCAu8, 0xFE];
+        let name = WideCString::from_str("Data").unwrap();
+        let mut vt_prop: VARIANT = unsafe { mem::zeroed() };
+
+        unsafe {
+            let arr = SafeArrayCreateVector(VT_UI1 as VARTYPE, 0, bytes.len() as u32);
+            assert!(!arr.is_null());
+
+            let mut p_data = ptr::null_mut();
+            check_hres(SafeArrayAccessData(arr, &mut p_data)).unwrap();
+            ptr::copy_nonoverlapping(bytes.as_ptr(), p_data as *mut u8, bytes.len());
+            check_hres(SafeArrayUnaccessData(arr)).unwrap();
+
+            let n2 = vt_prop.n1.n2_mut();
+            n2.vt = (VT_ARRAY | VT_UI1) as VARTYPE;
+            *n2.n3.parray_mut() = arr;
+
+            check_hres((*obj.inner.unwrap().as_ptr()).Put(
+                name.as_ptr(),
+                0,
+                &mut vt_prop,
+                (CIM_UINT8 | CIM_FLAG_ARRAY) as i32,
+            ))
+            .unwrap();
+
+            VariantClear(&mut vt_prop);
+        }
+
+        let w: WMIRS_Fixture = from_wbem_class_obj(&obj).unwrap();
+
+        assert_eq!(w.Data, Variant::Bytes(bytes.to_vec()));
+        assert_eq!(w.Missing, Variant::Empty);
+    }
+
+    #[test]
+    fn it_desr_fields_with_different_case() {
+        let wmi_con = wmi_con();
+
+        // WMI reports these as `ProcessId` and `Name`.
+        #[derive(Deserialize, Debug)]
+        struct Win32_Process {
+            ProcessID: u32,
+            NAME: String,
+        }
+
+        let results: Vec<Win32_Process> = wmi_con.query().unwrap();
+
+        assert!(results
+            .iter()
+            .any(|process| process.ProcessID == 4 && process.NAME == "System"));
+    }
+
+    #[test]
+    fn it_desr_missing_property_as_none() {
+        let wmi_con = wmi_con();
+
+        #[derive(Deserialize, Debug)]
+        struct Win32_OperatingSystem {
+            Caption: String,
+            WMIRS_Missing: Option<u64>,
+        }
+
+        let results: Vec<Win32_OperatingSystem> = wmi_con
+            .raw_query("SELECT * FROM Win32_OperatingSystem")
+            .unwrap();
+
+        for res in results {
+            assert_eq!(res.WMIRS_Missing, None);
+        }
+    }
+
+    #[test]
+    fn it_desr_array() {
+        let wmi_con = wmi_con();
+
+        #[derive(Deserialize, Debug)]
+        struct Win32_ComputerSystem {
+            BootStatus: Vec<i32>,
+            Roles: Vec<String>,
+        }
+
+        let results: Vec<Win32_ComputerSystem> = wmi_con.query().unwrap();
+
+        for res in results {
+            assert_eq!(res.BootStatus, [0, 0, 0, 33, 31, 158, 0, 3, 2, 2]);
+            assert_eq!(res.Roles, ["LM_Workstation", "LM_Server", "NT"]);
+        }
+    }
+
+    #[test]
+    fn it_desr_option_string() {
+        let wmi_con = wmi_con();
+
+        #[derive(Deserialize, Debug)]
+        pub struct Win32_Service {
+            pub Name: String,
+            pub PathName: Option<String>,
+        }
+
+        let results: Vec<Win32_Service> = wmi_con.query().unwrap();
+
+        let lsm_service = results
+            .iter()
+            .find(|&service| service.Name == "LSM")
+            .unwrap();
+
+        assert_eq!(lsm_service.PathName, None);
+
+        let lmhosts_service = results
+            .iter()
+            .find(|&service| service.Name == "lmhosts")
+            .unwrap();
+
+        assert!(lmhosts_service.PathName.is_some());
+    }
+
+    #[test]
+    fn it_fail_to_desr_null_to_string() {
+        // Values can return as Null / Empty from WMI.
+        // It is impossible to `desr` such values to `String` (for example).
+        // See `it_desr_option_string` on how to fix this error.
+        let wmi_con = wmi_con();
+
+        #[derive(Deserialize, Debug)]
+        pub struct Win32_Service {
+            pub Name: String,
+            pub PathName: String,
         }
 
         let res: Result<Vec<Win32_Service>, _> = wmi_con.query();
 
         let err = res.err().unwrap();
 
-        assert_eq!(format!("{}", err), "invalid type: Option value, expected a string")
+        assert_eq!(
+            format!("{}", err),
+            "Failed to deserialize property PathName: invalid type: Option value, expected a string"
+        )
+    }
+
+    #[test]
+    fn it_trims_strings_only_when_asked() {
+        let wmi_con = wmi_con();
+
+        #[derive(Deserialize, Debug)]
+        struct WMIRS_Padded {
+            SerialNumber: String,
+        }
+
+        let obj = new_class_obj(&wmi_con, "WMIRS_Padded");
+
+        obj.put_property("SerialNumber", &Variant::String("  ABC123    ".into()))
+            .unwrap();
+
+        let w: WMIRS_Padded = from_wbem_class_obj(&obj).unwrap();
+
+        assert_eq!(w.SerialNumber, "  ABC123    ");
+
+        let mut deserializer = Deserializer::from_wbem_class_obj(&obj).trim_strings(true);
+        let w = WMIRS_Padded::deserialize(&mut deserializer).unwrap();
+
+        assert_eq!(w.SerialNumber, "ABC123");
+    }
+
+    #[test]
+    fn it_desr_64bit_strings_into_integers() {
+        let wmi_con = wmi_con();
+
+        #[derive(Deserialize, Debug)]
+        struct Win32_ComputerSystem {
+            TotalPhysicalMemory: u64,
+        }
+
+        let system: Win32_ComputerSystem = wmi_con.get().unwrap();
+
+        assert!(system.TotalPhysicalMemory > 0);
+
+        #[derive(Deserialize, Debug)]
+        struct WMIRS_Counters {
+            Unsigned: u64,
+            Signed: i64,
+            AsString: String,
+            Optional: Option<u64>,
+        }
+
+        let obj = new_class_obj(&wmi_con, "WMIRS_Counters");
+
+        obj.put_property("Unsigned", &Variant::String("18446744073709551615".into()))
+            .unwrap();
+        obj.put_property("Signed", &Variant::String("-17179869184".into()))
+            .unwrap();
+        obj.put_property("AsString", &Variant::String("42".into()))
+            .unwrap();
+        obj.put_property("Optional", &Variant::String("7".into()))
+            .unwrap();
+
+        let w: WMIRS_Counters = from_wbem_class_obj(&obj).unwrap();
+
+        assert_eq!(w.Unsigned, u64::max_value());
+        assert_eq!(w.Signed, -17_179_869_184);
+        assert_eq!(w.AsString, "42");
+        assert_eq!(w.Optional, Some(7));
+    }
+
+    fn obj_with_bad_field(wmi_con: &WMIConnection) -> IWbemClassWrapper {
+        let obj = new_class_obj(wmi_con, "WMIRS_BadField");
+
+        obj.put_property("Name", &Variant::String("A".into()))
+            .unwrap();
+        obj.put_property("InstallDate", &Variant::String("not a date".into()))
+            .unwrap();
+        obj.put_property("Count", &Variant::String("many".into()))
+            .unwrap();
+
+        obj
+    }
+
+    #[test]
+    fn it_fails_on_bad_field_by_default() {
+        let wmi_con = wmi_con();
+        let obj = obj_with_bad_field(&wmi_con);
+
+        #[derive(Deserialize, Debug)]
+        struct WMIRS_BadField {
+            Name: String,
+            InstallDate: Option<WMIDateTime>,
+        }
+
+        assert!(from_wbem_class_obj::<WMIRS_BadField>(&obj).is_err());
+        assert!(from_wbem_class_obj_with_policy::<WMIRS_BadField>(&obj, OnError::Fail).is_err());
+    }
+
+    #[test]
+    fn it_uses_default_for_bad_fields() {
+        let wmi_con = wmi_con();
+        let obj = obj_with_bad_field(&wmi_con);
+
+        #[derive(Deserialize, Debug)]
+        struct WMIRS_BadField {
+            Name: String,
+            InstallDate: Option<WMIDateTime>,
+            Count: u32,
+        }
+
+        let w: WMIRS_BadField = from_wbem_class_obj_with_policy(&obj, OnError::UseDefault).unwrap();
+
+        assert_eq!(w.Name, "A");
+        assert!(w.InstallDate.is_none());
+        assert_eq!(w.Count, 0);
+
+        // A `WMIDateTime` has no default, so the object still fails.
+        #[derive(Deserialize, Debug)]
+        struct WMIRS_RequiredBadField {
+            Name: String,
+            InstallDate: WMIDateTime,
+        }
+
+        assert!(from_wbem_class_obj_with_policy::<WMIRS_RequiredBadField>(
+            &obj,
+            OnError::UseDefault
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn it_skips_bad_fields() {
+        let wmi_con = wmi_con();
+        let obj = obj_with_bad_field(&wmi_con);
+
+        #[derive(Deserialize, Debug)]
+        struct WMIRS_BadField {
+            Name: String,
+            InstallDate: Option<WMIDateTime>,
+            #[serde(default = "unknown_count")]
+            Count: u32,
+        }
+
+        fn unknown_count() -> u32 {
+            u32::max_value()
+        }
+
+        let w: WMIRS_BadField = from_wbem_class_obj_with_policy(&obj, OnError::Skip).unwrap();
+
+        assert_eq!(w.Name, "A");
+        assert!(w.InstallDate.is_none());
+        assert_eq!(w.Count, u32::max_value());
+
+        // Skipping a required field fails the object.
+        #[derive(Deserialize, Debug)]
+        struct WMIRS_RequiredBadField {
+            Name: String,
+            Count: u32,
+        }
+
+        assert!(
+            from_wbem_class_obj_with_policy::<WMIRS_RequiredBadField>(&obj, OnError::Skip).is_err()
+        );
+    }
+
+    #[test]
+    fn it_desr_subset_of_properties() {
+        let wmi_con = wmi_con();
+
+        #[derive(Deserialize, Debug)]
+        struct Win32_OperatingSystem {
+            Caption: String,
+            BuildNumber: String,
+        }
+
+        let os = wmi_con.get_object("Win32_OperatingSystem=@").unwrap();
+
+        assert!(os.list_properties().unwrap().len() > 2);
+
+        let w: Win32_OperatingSystem = from_wbem_class_obj(&os).unwrap();
+
+        assert_eq!(w.Caption, "Microsoft Windows 10 Pro");
+        assert_ne!(w.BuildNumber, "");
+
+        // Values of properties which are not needed are discarded.
+        let _: de::IgnoredAny = from_wbem_class_obj(&os).unwrap();
+        let w: HashMap<String, de::IgnoredAny> = from_wbem_class_obj(&os).unwrap();
+
+        assert!(w.contains_key("Caption"));
+    }
+
+    #[test]
+    fn it_lists_properties_from_the_cache_only_for_maps() {
+        let wmi_con = wmi_con();
+
+        #[derive(Deserialize, Debug)]
+        struct Win32_Process {
+            ProcessId: u32,
+        }
+
+        let cache = RefCell::new(PropertyNamesCache::new());
+        let mut count = 0;
+
+        for obj in wmi_con
+            .exec_query_native_wrapper("SELECT * FROM Win32_Process")
+            .unwrap()
+        {
+            let obj = obj.unwrap();
+            let get_names_calls = cache.borrow().get_names_calls();
+
+            // Structs only read their own fields, so they never use the cache.
+            let _: Win32_Process = Deserializer::from_wbem_class_obj(&obj)
+                .property_names_cache(&cache)
+                .deserialize_with_policy()
+                .unwrap();
+
+            assert_eq!(cache.borrow().get_names_calls(), get_names_calls);
+
+            let w: HashMap<String, Variant> = Deserializer::from_wbem_class_obj(&obj)
+                .property_names_cache(&cache)
+                .deserialize_with_policy()
+                .unwrap();
+
+            assert!(w.contains_key("ProcessId"));
+
+            count += 1;
+        }
+
+        assert!(count > 1);
+        assert_eq!(cache.borrow().get_names_calls(), 1);
+    }
+
+    #[test]
+    fn it_desr_bitmask_into_newtype() {
+        let wmi_con = wmi_con();
+
+        #[derive(Deserialize, Debug, PartialEq)]
+        #[serde(transparent)]
+        struct ProductSuite(u32);
+
+        impl ProductSuite {
+            const TERMINAL_SERVICES_SINGLE_SESSION: u32 = 0x100;
+
+            fn contains(&self, flag: u32) -> bool {
+                self.0 & flag == flag
+            }
+        }
+
+        #[derive(Deserialize, Debug)]
+        struct Win32_OperatingSystem {
+            OSProductSuite: ProductSuite,
+        }
+
+        let os = wmi_con.get_object("Win32_OperatingSystem=@").unwrap();
+
+        let w: Win32_OperatingSystem = from_wbem_class_obj(&os).unwrap();
+
+        assert!(w
+            .OSProductSuite
+            .contains(ProductSuite::TERMINAL_SERVICES_SINGLE_SESSION));
+    }
+
+    #[test]
+    fn it_desr_into_map_of_strings() {
+        let wmi_con = wmi_con();
+
+        let results: Vec<HashMap<String, String>> = wmi_con
+            .raw_query("SELECT Caption, CurrentTimeZone, Debug FROM Win32_OperatingSystem")
+            .unwrap();
+
+        let os = &results[0];
+
+        assert_eq!(os["Caption"], "Microsoft Windows 10 Pro");
+        assert_eq!(os["CurrentTimeZone"], "120");
+        assert_eq!(os["Debug"], "false");
+    }
+
+    #[test]
+    fn it_deserializes_enum_variants_by_class() {
+        let wmi_con = wmi_con();
+
+        let disk = new_class_obj(&wmi_con, "WMIRS_Disk");
+        disk.put_property("Size", &Variant::String("1024".into()))
+            .unwrap();
+
+        let nic = new_class_obj(&wmi_con, "WMIRS_NetworkAdapter");
+        nic.put_property("Speed", &Variant::String("100".into()))
+            .unwrap();
+
+        #[derive(Deserialize, Debug, PartialEq)]
+        struct Disk {
+            Size: u64,
+        }
+
+        #[derive(Deserialize, Debug, PartialEq)]
+        enum Device {
+            WMIRS_Disk(Disk),
+            WMIRS_NetworkAdapter { Speed: u64 },
+        }
+
+        assert_eq!(
+            from_wbem_class_obj::<Device>(&disk).unwrap(),
+            Device::WMIRS_Disk(Disk { Size: 1024 })
+        );
+        assert_eq!(
+            from_wbem_class_obj::<Device>(&nic).unwrap(),
+            Device::WMIRS_NetworkAdapter { Speed: 100 }
+        );
+
+        let unknown = new_class_obj(&wmi_con, "WMIRS_Unknown");
+
+        assert!(from_wbem_class_obj::<Device>(&unknown).is_err());
+    }
+
+    #[test]
+    fn it_deserializes_polymorphic_query_results() {
+        let wmi_con = wmi_con();
+
+        #[derive(Deserialize, Debug)]
+        struct Processor {
+            Name: String,
+        }
+
+        #[derive(Deserialize, Debug)]
+        struct LogicalDisk {
+            DeviceID: String,
+        }
+
+        #[derive(Deserialize, Debug)]
+        enum Device {
+            Win32_Processor(Processor),
+            Win32_LogicalDisk(LogicalDisk),
+            #[serde(other)]
+            Other,
+        }
+
+        let devices: Vec<Device> = wmi_con
+            .raw_query("SELECT * FROM CIM_LogicalDevice")
+            .unwrap();
+
+        assert!(devices
+            .iter()
+            .any(|d| matches!(d, Device::Win32_Processor(p) if !p.Name.is_empty())));
+        assert!(devices
+            .iter()
+            .any(|d| matches!(d, Device::Win32_LogicalDisk(disk) if disk.DeviceID == "C:")));
+        assert!(devices.iter().any(|d| matches!(d, Device::Other)));
+    }
+
+    #[test]
+    fn it_desr_fixture_objects() {
+        #[derive(Deserialize, Debug, PartialEq)]
+        struct WMIRS_Fixture {
+            Name: String,
+            Size: u64,
+            Enabled: bool,
+            Description: Option<String>,
+        }
+
+        let obj = make_object(&[
+            ("Name", Variant::String("A".into())),
+            ("Size", Variant::String("1024".into())),
+            ("Enabled", Variant::Bool(true)),
+            ("Description", Variant::Null),
+        ]);
+
+        assert_eq!(
+            from_wbem_class_obj::<WMIRS_Fixture>(&obj).unwrap(),
+            WMIRS_Fixture {
+                Name: "A".into(),
+                Size: 1024,
+                Enabled: true,
+                Description: None,
+            }
+        );
+
+        let w: HashMap<String, Variant> = from_wbem_class_obj(&obj).unwrap();
+
+        assert_eq!(w.len(), 4);
+        assert_eq!(w["Enabled"], Variant::Bool(true));
+    }
+
+    #[test]
+    fn it_keeps_a_retained_target_instance_after_the_event_is_dropped() {
+        #[derive(Deserialize, Debug)]
+        struct WMIRS_Fixture {
+            Name: String,
+        }
+
+        #[derive(Deserialize, Debug)]
+        struct WMIRS_Event {
+            TargetInstance: WMIRS_Fixture,
+        }
+
+        fn ref_count(obj: &IWbemClassWrapper) -> u32 {
+            let ptr = obj.inner.unwrap().as_ptr();
+
+            unsafe {
+                (*ptr).AddRef();
+                (*ptr).Release()
+            }
+        }
+
+        let target = make_object(&[("Name", Variant::String("A".into()))]);
+        let event = make_event(&target);
+        drop(target);
+
+        let w: WMIRS_Event = from_wbem_class_obj(&event).unwrap();
+        assert_eq!(w.TargetInstance.Name, "A");
+
+        let (value, _) = event.get_native_property("TargetInstance").unwrap();
+        let retained = value.as_object().unwrap().unwrap();
+
+        // `as_object` holds its own reference (from `QueryInterface`), besides the variant's.
+        let with_variant = ref_count(&retained);
+        drop(value);
+        assert_eq!(ref_count(&retained), with_variant - 1);
+
+        drop(event);
+
+        assert_eq!(
+            retained.get_property("Name").unwrap(),
+            Variant::String("A".into())
+        );
+    }
+
+    #[test]
+    fn it_fails_to_desr_fixture_with_missing_property() {
+        #[derive(Deserialize, Debug)]
+        struct WMIRS_Fixture {
+            Name: String,
+            Size: u64,
+        }
+
+        let obj = make_object(&[("Name", Variant::String("A".into()))]);
+
+        // A missing property is `Empty`, which only an `Option` field accepts.
+        assert!(from_wbem_class_obj::<WMIRS_Fixture>(&obj).is_err());
+
+        let obj = make_object(&[("__CLASS", Variant::String("WMIRS_Other".into()))]);
+
+        assert_eq!(
+            obj.get_property("__CLASS").unwrap(),
+            Variant::String("WMIRS_Other".into())
+        );
+    }
+
+    #[test]
+    fn it_desr_cow_str_fields() {
+        use std::borrow::Cow;
+
+        #[derive(Deserialize, Debug)]
+        struct WMIRS_Fixture<'a> {
+            Name: Cow<'a, str>,
+            Description: Option<Cow<'a, str>>,
+        }
+
+        let obj = make_object(&[
+            ("Name", Variant::String("A".into())),
+            ("Description", Variant::Null),
+        ]);
+
+        let w: WMIRS_Fixture<'static> = from_wbem_class_obj(&obj).unwrap();
+
+        assert_eq!(w.Name, "A");
+        assert!(matches!(w.Name, Cow::Owned(_)));
+        assert_eq!(w.Description, None);
+    }
+
+    #[test]
+    fn it_names_the_field_which_failed_to_desr() {
+        #[derive(Deserialize, Debug)]
+        struct WMIRS_Fixture {
+            Name: String,
+            Size: u32,
+        }
+
+        let obj = make_object(&[
+            ("Name", Variant::String("A".into())),
+            ("Size", Variant::String("large".into())),
+        ]);
+
+        let err = from_wbem_class_obj::<WMIRS_Fixture>(&obj).unwrap_err();
+
+        match err.downcast_ref::<WMIError>() {
+            Some(wmi_err @ WMIError::Deserialization { field, .. }) => {
+                assert_eq!(field, "Size");
+
+                // The serde error is kept as the source.
+                let source = std::error::Error::source(wmi_err).unwrap();
+                assert!(source.to_string().contains("u32"), "{}", source);
+            }
+            other => panic!("Expected a deserialization error, got {:?}", other),
+        }
+
+        assert!(format!("{}", err).starts_with("Failed to deserialize property Size: "));
+    }
+
+    /// Compare deserializing `uint64` properties (which WMI returns as `BSTR`s) directly into
+    /// `u64` fields with deserializing them into `String` fields which are then parsed,
+    /// for 10k objects. Run with `cargo test --release -- --ignored --nocapture bench_`.
+    #[test]
+    #[ignore]
+    fn bench_desr_uint64_properties() {
+        use std::time::Instant;
+
+        #[derive(Deserialize)]
+        struct WMIRS_Fixture {
+            KernelModeTime: u64,
+            OtherOperationCount: u64,
+            OtherTransferCount: u64,
+            PeakVirtualSize: u64,
+            PrivatePageCount: u64,
+            ReadOperationCount: u64,
+            UserModeTime: u64,
+            VirtualSize: u64,
+        }
+
+        #[derive(Deserialize)]
+        #[serde(rename = "WMIRS_Fixture")]
+        struct WMIRS_FixtureStrings {
+            KernelModeTime: String,
+            OtherOperationCount: String,
+            OtherTransferCount: String,
+            PeakVirtualSize: String,
+            PrivatePageCount: String,
+            ReadOperationCount: String,
+            UserModeTime: String,
+            VirtualSize: String,
+        }
+
+        let names = [
+            "KernelModeTime",
+            "OtherOperationCount",
+            "OtherTransferCount",
+            "PeakVirtualSize",
+            "PrivatePageCount",
+            "ReadOperationCount",
+            "UserModeTime",
+            "VirtualSize",
+        ];
+
+        let properties: Vec<(&str, Variant)> = names
+            .iter()
+            .enumerate()
+            .map(|(i, name)| {
+                let value = i as u64 * 4096 + 17_179_869_184;
+
+                (*name, Variant::String(value.to_string()))
+            })
+            .collect();
+
+        let obj = make_object(&properties);
+
+        let start = Instant::now();
+        let mut string_sum = 0u64;
+        for _ in 0..10_000 {
+            let w: WMIRS_FixtureStrings = from_wbem_class_obj(&obj).unwrap();
+
+            string_sum += [
+                w.KernelModeTime,
+                w.OtherOperationCount,
+                w.OtherTransferCount,
+                w.PeakVirtualSize,
+                w.PrivatePageCount,
+                w.ReadOperationCount,
+                w.UserModeTime,
+                w.VirtualSize,
+            ]
+            .iter()
+            .map(|s| s.parse::<u64>().unwrap())
+            .sum::<u64>();
+        }
+        let string_elapsed = start.elapsed();
+
+        let start = Instant::now();
+        let mut direct_sum = 0u64;
+        for _ in 0..10_000 {
+            let w: WMIRS_Fixture = from_wbem_class_obj(&obj).unwrap();
+
+            direct_sum += w.KernelModeTime
+                + w.OtherOperationCount
+                + w.OtherTransferCount
+                + w.PeakVirtualSize
+                + w.PrivatePageCount
+                + w.ReadOperationCount
+                + w.UserModeTime
+                + w.VirtualSize;
+        }
+        let direct_elapsed = start.elapsed();
+
+        assert_eq!(string_sum, direct_sum);
+
+        println!(
+            "via String fields: {:?}, directly into u64 fields: {:?}",
+            string_elapsed, direct_elapsed
+        );
     }
 }
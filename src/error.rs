@@ -14,18 +14,30 @@ impl Error {
     pub fn from_err<T: Debug>(err: T) -> Self {
         Self::from(format_err!("{:?}", err))
     }
-}
 
-impl error::Error for Error {
-    fn description(&self) -> &str {
-        ""
+    /// The underlying error, if it's of type `T` (for example, a
+    /// [`WMIError::Deserialization`](crate::utils::WMIError::Deserialization)).
+    pub fn downcast_ref<T: failure::Fail>(&self) -> Option<&T> {
+        self.err.downcast_ref()
     }
 
-    fn cause(&self) -> Option<&error::Error> {
-        None
+    #[cfg(target_os = "windows")]
+    pub(crate) fn into_inner(self) -> failure::Error {
+        self.err
     }
+}
 
+impl error::Error for Error {
+    /// The underlying [`WMIError`](crate::utils::WMIError), if there is one,
+    /// so its own `source` (like a chrono parse error) is also part of the chain.
     fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        #[cfg(target_os = "windows")]
+        {
+            if let Some(err) = self.err.downcast_ref::<crate::utils::WMIError>() {
+                return Some(err);
+            }
+        }
+
         None
     }
 }
@@ -0,0 +1,229 @@
+//! Canned queries for commonly needed data, built on top of the regular query API.
+//!
+//! ```edition2018
+//! # use wmi::*;
+//! # let wmi_con = WMIConnection::new(COMLibrary::new().unwrap().into()).unwrap();
+//! let info = wmi_con.system_info().unwrap();
+//!
+//! println!("{} ({} cores)", info.cpu_name, info.cpu_cores);
+//! ```
+//!
+use crate::connection::WMIConnection;
+use crate::query::FilterValue;
+use failure::{format_err, Error};
+use serde::Deserialize;
+use std::collections::HashMap;
+
+/// Basic facts about the OS, memory and CPU of the connected machine.
+///
+#[derive(Debug, Clone, PartialEq)]
+pub struct SystemInfo {
+    /// For example, `Microsoft Windows 10 Pro`.
+    pub caption: String,
+    /// For example, `10.0.17763`.
+    pub version: String,
+    /// Total physical memory, in bytes.
+    pub total_memory: u64,
+    /// The name of the first processor.
+    pub cpu_name: String,
+    /// The number of cores of all the processors.
+    pub cpu_cores: u32,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename = "Win32_OperatingSystem")]
+#[serde(rename_all = "PascalCase")]
+struct OperatingSystem {
+    caption: String,
+    version: String,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename = "Win32_ComputerSystem")]
+#[serde(rename_all = "PascalCase")]
+struct ComputerSystem {
+    // `uint64` properties are returned as strings.
+    total_physical_memory: String,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename = "Win32_Processor")]
+#[serde(rename_all = "PascalCase")]
+struct Processor {
+    name: String,
+    number_of_cores: u32,
+}
+
+/// The limits on the resources used by WMI provider host processes (`WmiPrvSE.exe`).
+///
+#[derive(Debug, Clone, PartialEq)]
+pub struct ProviderHostQuota {
+    /// In bytes.
+    pub memory_per_host: u64,
+    /// In bytes.
+    pub memory_all_hosts: u64,
+    pub threads_per_host: u32,
+    pub handles_per_host: u32,
+    pub process_limit_all_hosts: u32,
+}
+
+/// Facts about WMI itself on the connected machine.
+///
+#[derive(Debug, Clone, PartialEq)]
+pub struct WMIHealth {
+    pub provider_host_quota: ProviderHostQuota,
+    /// The number of running provider host processes.
+    pub provider_host_count: usize,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename = "__ProviderHostQuotaConfiguration")]
+#[serde(rename_all = "PascalCase")]
+struct ProviderHostQuotaConfiguration {
+    // `uint64` properties are returned as strings.
+    memory_per_host: String,
+    memory_all_hosts: String,
+    threads_per_host: u32,
+    handles_per_host: u32,
+    process_limit_all_hosts: u32,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename = "Win32_Process")]
+#[serde(rename_all = "PascalCase")]
+struct Process {
+    // Only the processes are counted, but a query needs at least one property.
+    #[allow(dead_code)]
+    process_id: u32,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename = "__NAMESPACE")]
+#[serde(rename_all = "PascalCase")]
+struct Namespace {
+    name: String,
+}
+
+impl WMIConnection {
+    /// Return the names of the child namespaces of the connected namespace
+    /// (for example, `CIMV2` for the `ROOT` namespace).
+    ///
+    pub fn namespaces(&self) -> Result<Vec<String>, Error> {
+        let namespaces: Vec<Namespace> = self.query()?;
+
+        Ok(namespaces
+            .into_iter()
+            .map(|namespace| namespace.name)
+            .collect())
+    }
+
+    /// Query the provider host quotas (which are configured in the `ROOT` namespace)
+    /// and the number of running provider hosts.
+    ///
+    /// Must be called on a connection to the `ROOT\CIMV2` namespace.
+    ///
+    pub fn wmi_health(&self) -> Result<WMIHealth, Error> {
+        let root_path = if self.server() == "." {
+            "ROOT".to_owned()
+        } else {
+            format!("\\\\{}\\ROOT", self.server())
+        };
+
+        let root_con = WMIConnection::with_existing_locator(&root_path, self.locator())?;
+
+        let quota: ProviderHostQuotaConfiguration = root_con.get()?;
+
+        let mut filters = HashMap::new();
+        filters.insert("Name".to_owned(), FilterValue::Str("WmiPrvSE.exe"));
+
+        let provider_hosts: Vec<Process> = self.filtered_query(&filters)?;
+
+        Ok(WMIHealth {
+            provider_host_quota: ProviderHostQuota {
+                memory_per_host: quota.memory_per_host.parse()?,
+                memory_all_hosts: quota.memory_all_hosts.parse()?,
+                threads_per_host: quota.threads_per_host,
+                handles_per_host: quota.handles_per_host,
+                process_limit_all_hosts: quota.process_limit_all_hosts,
+            },
+            provider_host_count: provider_hosts.len(),
+        })
+    }
+
+    /// Query the OS, memory and CPU details of the connected machine.
+    ///
+    pub fn system_info(&self) -> Result<SystemInfo, Error> {
+        let os: OperatingSystem = self.get()?;
+        let computer_system: ComputerSystem = self.get()?;
+        let processors: Vec<Processor> = self.query()?;
+
+        let total_memory = computer_system.total_physical_memory.parse()?;
+
+        let cpu_name = processors
+            .first()
+            .map(|processor| processor.name.clone())
+            .ok_or_else(|| format_err!("No processors returned"))?;
+
+        let cpu_cores = processors
+            .iter()
+            .map(|processor| processor.number_of_cores)
+            .sum();
+
+        Ok(SystemInfo {
+            caption: os.caption,
+            version: os.version,
+            total_memory,
+            cpu_name,
+            cpu_cores,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::tests::fixtures::*;
+    use crate::{COMLibrary, WMIConnection};
+
+    #[test]
+    fn it_returns_system_info() {
+        let wmi_con = wmi_con();
+
+        let info = wmi_con.system_info().unwrap();
+
+        assert_ne!(info.caption, "");
+        assert_ne!(info.version, "");
+        assert_ne!(info.cpu_name, "");
+        assert!(info.total_memory > 0);
+        assert!(info.cpu_cores > 0);
+    }
+
+    #[test]
+    fn it_returns_child_namespaces() {
+        let wmi_con = WMIConnection::with_namespace_path(
+            "ROOT",
+            COMLibrary::without_security().unwrap().into(),
+        )
+        .unwrap();
+
+        let namespaces = wmi_con.namespaces().unwrap();
+
+        assert!(namespaces
+            .iter()
+            .any(|namespace| namespace.eq_ignore_ascii_case("cimv2")));
+    }
+
+    #[test]
+    fn it_returns_wmi_health() {
+        let wmi_con = wmi_con();
+
+        let health = wmi_con.wmi_health().unwrap();
+
+        assert!(health.provider_host_quota.memory_per_host > 0);
+        assert!(health.provider_host_quota.memory_all_hosts > 0);
+        assert!(health.provider_host_quota.threads_per_host > 0);
+        assert!(health.provider_host_quota.handles_per_host > 0);
+        assert!(health.provider_host_quota.process_limit_all_hosts > 0);
+        // At least one provider host is running, since it serves this query.
+        assert!(health.provider_host_count > 0);
+    }
+}
@@ -69,6 +69,30 @@
 //! Because the name of the struct given to `serde` matches the [WMI class] name, the SQL query
 //! is inferred.
 //!
+//! Bitmask properties (like `OSProductSuite`) can be deserialized into a `u32` newtype
+//! (for example, a type generated by the `bitflags` crate, or a `#[serde(transparent)]` struct).
+//! Since WMI returns `uint32` values as `VT_I4`, masks with the highest bit set are handled as well.
+//!
+//! ```edition2018
+//! # use wmi::*;
+//! # let wmi_con = WMIConnection::new(COMLibrary::new().unwrap().into()).unwrap();
+//! use serde::Deserialize;
+//!
+//! #[derive(Deserialize, Debug)]
+//! #[serde(transparent)]
+//! struct ProductSuite(u32);
+//!
+//! #[derive(Deserialize, Debug)]
+//! #[serde(rename = "Win32_OperatingSystem")]
+//! #[serde(rename_all = "PascalCase")]
+//! struct OperatingSystem {
+//!     #[serde(rename = "OSProductSuite")]
+//!     product_suite: ProductSuite,
+//! }
+//!
+//! let results: Vec<OperatingSystem> = wmi_con.query().unwrap();
+//! ```
+//!
 //! [WMI]: https://docs.microsoft.com/en-us/windows/desktop/wmisdk/about-wmi
 //! [Creating a WMI Application Using C++]: https://docs.microsoft.com/en-us/windows/desktop/wmisdk/creating-a-wmi-application-using-c-
 //! [`VARIANT`]: https://docs.microsoft.com/en-us/windows/desktop/api/oaidl/ns-oaidl-tagvariant
@@ -94,19 +118,66 @@
 //! Most native objects has an equivalent wrapper struct which implements `Drop` for that data.
 //!
 //!
+// Only `Variant` (and deserializing from it) and the `mock` module are available on other
+// platforms, so code which uses the mock can be tested anywhere.
+pub mod cim_type;
+#[cfg(target_os = "windows")]
+pub mod class;
+#[cfg(all(target_os = "windows", feature = "common-classes"))]
+pub mod classes;
+#[cfg(target_os = "windows")]
 pub mod connection;
+#[cfg(target_os = "windows")]
+pub mod context;
+#[cfg(target_os = "windows")]
 pub mod datetime;
 pub mod de;
 pub mod error;
+#[cfg(target_os = "windows")]
+pub mod helpers;
+#[cfg(target_os = "windows")]
+pub mod method;
+#[cfg(feature = "mock")]
+pub mod mock;
+#[cfg(target_os = "windows")]
+pub mod notification;
+#[cfg(target_os = "windows")]
+pub mod perf;
+#[cfg(target_os = "windows")]
 pub mod query;
+#[cfg(target_os = "windows")]
+pub mod reference;
+#[cfg(target_os = "windows")]
+pub mod refresher;
+#[cfg(target_os = "windows")]
 pub mod result_enumerator;
+#[cfg(target_os = "windows")]
 pub mod safearray;
+#[cfg(target_os = "windows")]
+pub mod ser;
+#[cfg(target_os = "windows")]
 pub mod utils;
 pub mod variant;
 
-#[cfg(any(test, feature = "test"))]
+#[cfg(all(target_os = "windows", any(test, feature = "test")))]
 pub mod tests;
 
-pub use connection::{COMLibrary, WMIConnection};
+#[cfg(target_os = "windows")]
+pub use class::WMIClass;
+#[cfg(target_os = "windows")]
+pub use connection::{COMLibrary, WMIConnection, WMILocator};
+#[cfg(target_os = "windows")]
+pub use context::WMIContext;
+#[cfg(target_os = "windows")]
 pub use datetime::WMIDateTime;
+#[cfg(target_os = "windows")]
+pub use helpers::SystemInfo;
+#[cfg(target_os = "windows")]
+pub use reference::WMIRef;
 pub use variant::Variant;
+#[cfg(feature = "derive")]
+pub use wmi_derive::WMIClass;
+
+// Allows the derive macros (which refer to `::wmi`) to be used in this crate's tests.
+#[cfg(all(test, feature = "derive"))]
+extern crate self as wmi;
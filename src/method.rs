@@ -0,0 +1,246 @@
+use crate::de::wbem_class_de::from_wbem_class_obj;
+use crate::result_enumerator::IWbemClassWrapper;
+use crate::ser::wbem_class_ser::{field_names, to_wbem_class_obj};
+use crate::{connection::WMIConnection, utils::check_hres};
+use failure::{bail, format_err, Error};
+use serde::{de, ser};
+use std::ptr;
+use std::ptr::NonNull;
+use widestring::WideCString;
+use winapi::{shared::ntdef::NULL, um::wbemcli::IWbemClassObject};
+
+impl WMIConnection {
+    /// Create an instance of the in-parameters of a method of the given class.
+    ///
+    /// Returns `None` if the method does not take any parameters.
+    ///
+    pub fn get_method_in_params(
+        &self,
+        class_name: &str,
+        method_name: &str,
+    ) -> Result<Option<IWbemClassWrapper>, Error> {
+        let class = self.get_object(class_name)?;
+
+        let method_name = WideCString::from_str(method_name)?;
+
+        let mut p_in_signature = NULL as *mut IWbemClassObject;
+
+        let ptr = class.inner.unwrap().as_ptr();
+
+        unsafe {
+            check_hres((*ptr).GetMethod(
+                method_name.as_ptr(),
+                0,
+                &mut p_in_signature,
+                ptr::null_mut(),
+            ))?;
+        }
+
+        match NonNull::new(p_in_signature) {
+            Some(p_in_signature) => {
+                let in_signature = IWbemClassWrapper::new(Some(p_in_signature));
+
+                Ok(Some(in_signature.spawn_instance()?))
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// Execute a method of an object (or a static method, using the class name as the object path),
+    /// and return its out-parameters.
+    ///
+    pub fn exec_method_native_wrapper(
+        &self,
+        object_path: &str,
+        method_name: &str,
+        in_params: Option<&IWbemClassWrapper>,
+    ) -> Result<Option<IWbemClassWrapper>, Error> {
+        let object_path = WideCString::from_str(object_path)?;
+        let method_name = WideCString::from_str(method_name)?;
+
+        let p_in_params = in_params
+            .and_then(|in_params| in_params.inner)
+            .map_or(ptr::null_mut(), |p| p.as_ptr());
+
+        let mut p_out_params = NULL as *mut IWbemClassObject;
+
+        unsafe {
+            check_hres((*self.svc()).ExecMethod(
+                object_path.as_ptr() as *mut _,
+                method_name.as_ptr() as *mut _,
+                0,
+                ptr::null_mut(),
+                p_in_params,
+                &mut p_out_params,
+                ptr::null_mut(),
+            ))?;
+        }
+
+        Ok(NonNull::new(p_out_params).map(|p| IWbemClassWrapper::new(Some(p))))
+    }
+
+    /// Execute a method, using the `In` struct as the in-parameters and deserializing
+    /// the out-parameters (including the `ReturnValue`) into the `Out` struct.
+    ///
+    /// Only scalar parameters (strings, integers, bools, floats and options) are supported.
+    /// If the method doesn't take any parameters, `In` must be a struct without any fields.
+    ///
+    /// ```edition2018
+    /// # use wmi::*;
+    /// # let con = WMIConnection::new(COMLibrary::new().unwrap().into()).unwrap();
+    /// use serde::{Deserialize, Serialize};
+    ///
+    /// #[derive(Serialize)]
+    /// #[serde(rename_all = "PascalCase")]
+    /// struct GetOwnerIn {}
+    ///
+    /// #[derive(Deserialize, Debug)]
+    /// #[serde(rename_all = "PascalCase")]
+    /// struct GetOwnerOut {
+    ///     return_value: u32,
+    ///     user: Option<String>,
+    /// }
+    ///
+    /// let path = format!("Win32_Process.Handle=\"{}\"", std::process::id());
+    ///
+    /// let owner: GetOwnerOut = con
+    ///     .exec_method_typed("Win32_Process", &path, "GetOwner", &GetOwnerIn {})
+    ///     .unwrap();
+    /// #
+    ///
+    pub fn exec_method_typed<In, Out>(
+        &self,
+        class_name: &str,
+        object_path: &str,
+        method_name: &str,
+        input: &In,
+    ) -> Result<Out, Error>
+    where
+        In: ser::Serialize,
+        Out: de::DeserializeOwned,
+    {
+        let in_params = self.get_method_in_params(class_name, method_name)?;
+
+        match &in_params {
+            Some(in_params) => to_wbem_class_obj(in_params, input)?,
+            None => {
+                let fields = field_names(input)?;
+
+                if !fields.is_empty() {
+                    bail!(
+                        "Method {} does not take any parameters, but got {}",
+                        method_name,
+                        fields.join(", ")
+                    );
+                }
+            }
+        }
+
+        let out_params = self
+            .exec_method_native_wrapper(object_path, method_name, in_params.as_ref())?
+            .ok_or_else(|| format_err!("Method {} returned no out parameters", method_name))?;
+
+        Ok(from_wbem_class_obj(&out_params)?)
+    }
+}
+
+#[allow(non_snake_case)]
+#[allow(non_camel_case_types)]
+#[cfg(test)]
+mod tests {
+    use crate::tests::fixtures::*;
+    use crate::Variant;
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Serialize, Debug)]
+    struct GetStringValueIn {
+        sSubKeyName: String,
+        sValueName: String,
+    }
+
+    #[derive(Deserialize, Debug)]
+    struct GetStringValueOut {
+        ReturnValue: u32,
+        sValue: Option<String>,
+    }
+
+    #[test]
+    fn it_can_exec_a_static_method_with_typed_params() {
+        let wmi_con = wmi_con();
+
+        let input = GetStringValueIn {
+            sSubKeyName: r#"SOFTWARE\Microsoft\Windows NT\CurrentVersion"#.into(),
+            sValueName: "ProductName".into(),
+        };
+
+        let output: GetStringValueOut = wmi_con
+            .exec_method_typed("StdRegProv", "StdRegProv", "GetStringValue", &input)
+            .unwrap();
+
+        assert_eq!(output.ReturnValue, 0);
+        assert_eq!(output.sValue.unwrap(), "Windows 10 Pro");
+    }
+
+    #[derive(Serialize, Debug)]
+    struct GetOwnerIn {}
+
+    #[derive(Serialize, Debug)]
+    struct GetOwnerWithUnknownParamIn {
+        Domain: String,
+    }
+
+    #[derive(Deserialize, Debug)]
+    struct GetOwnerOut {
+        ReturnValue: u32,
+    }
+
+    #[test]
+    fn it_fails_to_exec_a_method_without_params_with_a_non_empty_input() {
+        let wmi_con = wmi_con();
+
+        let path = format!("Win32_Process.Handle=\"{}\"", std::process::id());
+
+        let output: GetOwnerOut = wmi_con
+            .exec_method_typed("Win32_Process", &path, "GetOwner", &GetOwnerIn {})
+            .unwrap();
+
+        assert_eq!(output.ReturnValue, 0);
+
+        let input = GetOwnerWithUnknownParamIn {
+            Domain: "WORKGROUP".into(),
+        };
+
+        let res: Result<GetOwnerOut, _> =
+            wmi_con.exec_method_typed("Win32_Process", &path, "GetOwner", &input);
+
+        assert!(res
+            .unwrap_err()
+            .to_string()
+            .contains("does not take any parameters, but got Domain"));
+    }
+
+    #[test]
+    fn it_can_fill_method_in_params() {
+        let wmi_con = wmi_con();
+
+        let in_params = wmi_con
+            .get_method_in_params("StdRegProv", "GetStringValue")
+            .unwrap()
+            .unwrap();
+
+        in_params
+            .put_property("sValueName", &Variant::String("ProductName".into()))
+            .unwrap();
+
+        assert_eq!(
+            in_params.get_property("sValueName").unwrap(),
+            Variant::String("ProductName".into())
+        );
+
+        // `hDefKey` defaults to `HKEY_LOCAL_MACHINE`.
+        assert_eq!(
+            in_params.get_property("hDefKey").unwrap(),
+            Variant::I4(0x8000_0002u32 as i32)
+        );
+    }
+}
@@ -0,0 +1,178 @@
+//! A way to unit test code which queries WMI, without a running WMI service.
+//!
+//! Write the code against the [`QueryProvider`] trait (instead of a concrete [`WMIConnection`]),
+//! and use a [`MockConnection`] with canned results in tests.
+//!
+//! Only available with the `mock` feature. Unlike the rest of the crate, this module
+//! (and [`Variant`]) is also available on other platforms, so such tests can run on any CI.
+//!
+//! ```edition2018
+//! # use wmi::Variant;
+//! use wmi::mock::{MockConnection, QueryProvider};
+//! use serde::Deserialize;
+//! use std::collections::HashMap;
+//!
+//! #[derive(Deserialize)]
+//! struct Win32_Service {
+//!     Name: String,
+//! }
+//!
+//! fn running_services(con: &impl QueryProvider) -> Vec<String> {
+//!     let services: Vec<Win32_Service> = con
+//!         .raw_query("SELECT Name FROM Win32_Service WHERE State = 'Running'")
+//!         .unwrap();
+//!
+//!     services.into_iter().map(|service| service.Name).collect()
+//! }
+//!
+//! let mut service = HashMap::new();
+//! service.insert("Name".to_owned(), Variant::String("Winmgmt".into()));
+//!
+//! let con = MockConnection::new().with_results(
+//!     "SELECT Name FROM Win32_Service WHERE State = 'Running'",
+//!     vec![service],
+//! );
+//!
+//! assert_eq!(running_services(&con), ["Winmgmt"]);
+//! ```
+//!
+#[cfg(target_os = "windows")]
+use crate::connection::WMIConnection;
+use crate::Variant;
+use failure::{bail, Error};
+use serde::de::{self, value::MapDeserializer};
+use std::collections::HashMap;
+
+/// Something which can execute WQL queries.
+/// Implemented by the real `WMIConnection` (on Windows) and by [`MockConnection`].
+///
+pub trait QueryProvider {
+    /// Execute a free-text query, returning every object as a map of its properties.
+    fn raw_query_maps(&self, query: &str) -> Result<Vec<HashMap<String, Variant>>, Error>;
+
+    /// Execute a free-text query and deserialize the results.
+    ///
+    /// Note that unlike a real connection, property names are matched case-sensitively
+    /// against the keys of the canned maps.
+    fn raw_query<T>(&self, query: &str) -> Result<Vec<T>, Error>
+    where
+        T: de::DeserializeOwned,
+    {
+        self.raw_query_maps(query)?
+            .into_iter()
+            .map(|properties| {
+                let deserializer = MapDeserializer::new(properties.into_iter());
+
+                T::deserialize(deserializer).map_err(Error::from)
+            })
+            .collect()
+    }
+}
+
+#[cfg(target_os = "windows")]
+impl QueryProvider for WMIConnection {
+    fn raw_query_maps(&self, query: &str) -> Result<Vec<HashMap<String, Variant>>, Error> {
+        WMIConnection::raw_query(self, query)
+    }
+
+    fn raw_query<T>(&self, query: &str) -> Result<Vec<T>, Error>
+    where
+        T: de::DeserializeOwned,
+    {
+        WMIConnection::raw_query(self, query)
+    }
+}
+
+/// A [`QueryProvider`] which returns canned results for known queries.
+///
+/// Queries are matched exactly, and an error is returned for any other query.
+///
+#[derive(Debug, Clone, Default)]
+pub struct MockConnection {
+    results: HashMap<String, Vec<HashMap<String, Variant>>>,
+}
+
+impl MockConnection {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Return `results` whenever `query` is executed.
+    pub fn with_results(
+        mut self,
+        query: impl Into<String>,
+        results: Vec<HashMap<String, Variant>>,
+    ) -> Self {
+        self.results.insert(query.into(), results);
+
+        self
+    }
+}
+
+impl QueryProvider for MockConnection {
+    fn raw_query_maps(&self, query: &str) -> Result<Vec<HashMap<String, Variant>>, Error> {
+        match self.results.get(query) {
+            Some(results) => Ok(results.clone()),
+            None => bail!("No results were set up for query {:?}", query),
+        }
+    }
+}
+
+#[allow(non_snake_case)]
+#[allow(non_camel_case_types)]
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::Deserialize;
+
+    #[derive(Deserialize, Debug, PartialEq)]
+    struct Win32_Process {
+        Name: String,
+        ProcessId: i32,
+        ExecutablePath: Option<String>,
+    }
+
+    fn process_names(con: &impl QueryProvider) -> Result<Vec<String>, Error> {
+        let processes: Vec<Win32_Process> = con.raw_query("SELECT * FROM Win32_Process")?;
+
+        Ok(processes.into_iter().map(|process| process.Name).collect())
+    }
+
+    fn process(name: &str, pid: i32) -> HashMap<String, Variant> {
+        let mut properties = HashMap::new();
+
+        properties.insert("Name".to_owned(), Variant::String(name.to_owned()));
+        properties.insert("ProcessId".to_owned(), Variant::I4(pid));
+        properties.insert("ExecutablePath".to_owned(), Variant::Null);
+
+        properties
+    }
+
+    #[test]
+    fn it_returns_canned_results() {
+        let con = MockConnection::new().with_results(
+            "SELECT * FROM Win32_Process",
+            vec![process("System", 4), process("cargo.exe", 1234)],
+        );
+
+        assert_eq!(process_names(&con).unwrap(), ["System", "cargo.exe"]);
+
+        let processes: Vec<Win32_Process> = con.raw_query("SELECT * FROM Win32_Process").unwrap();
+
+        assert_eq!(
+            processes[1],
+            Win32_Process {
+                Name: "cargo.exe".into(),
+                ProcessId: 1234,
+                ExecutablePath: None,
+            }
+        );
+    }
+
+    #[test]
+    fn it_fails_for_unknown_queries() {
+        let con = MockConnection::new();
+
+        assert!(process_names(&con).is_err());
+    }
+}
@@ -0,0 +1,342 @@
+use crate::de::wbem_class_de::from_wbem_class_obj;
+use crate::result_enumerator::IWbemClassWrapper;
+use crate::{connection::WMIConnection, utils::check_hres};
+use failure::Error;
+use log::trace;
+use serde::de;
+use std::marker::PhantomData;
+use std::ptr;
+use std::ptr::NonNull;
+use std::time::Duration;
+use widestring::WideCString;
+use winapi::{
+    shared::ntdef::{HRESULT, NULL},
+    um::wbemcli::{
+        IEnumWbemClassObject, IWbemClassObject, WBEM_FLAG_FORWARD_ONLY,
+        WBEM_FLAG_RETURN_IMMEDIATELY, WBEM_INFINITE, WBEM_S_TIMEDOUT,
+    },
+};
+
+/// An enumerator of the events returned by a notification query.
+///
+/// A notification query never runs out of results, so iterating over it blocks until the next event.
+/// Use `next_with_timeout` to periodically regain control (for example, to check a shutdown flag).
+///
+/// Dropping the enumerator releases it, which cancels the subscription.
+///
+pub struct NotificationEnumerator<'a> {
+    // The enumerator must not outlive the connection it was created from.
+    _wmi_con: PhantomData<&'a WMIConnection>,
+    p_enumerator: Option<NonNull<IEnumWbemClassObject>>,
+}
+
+impl<'a> NotificationEnumerator<'a> {
+    pub fn new(_wmi_con: &'a WMIConnection, p_enumerator: *mut IEnumWbemClassObject) -> Self {
+        Self {
+            _wmi_con: PhantomData,
+            p_enumerator: NonNull::new(p_enumerator),
+        }
+    }
+
+    /// Wait up to `timeout` for the next event.
+    ///
+    /// Returns `None` if no event arrived in time (`WBEM_S_TIMEDOUT`), in which case
+    /// it's safe to call this method again.
+    ///
+    pub fn next_with_timeout(
+        &mut self,
+        timeout: Duration,
+    ) -> Result<Option<IWbemClassWrapper>, Error> {
+        let timeout_ms = timeout.as_millis().min(i32::MAX as u128) as i32;
+
+        self.next_event(timeout_ms)
+    }
+
+    fn next_event(&mut self, timeout_ms: i32) -> Result<Option<IWbemClassWrapper>, Error> {
+        let p_enumerator = match self.p_enumerator {
+            Some(p_enumerator) => p_enumerator.as_ptr(),
+            None => return Ok(None),
+        };
+
+        let mut pcls_obj = NULL as *mut IWbemClassObject;
+        let mut return_value = 0;
+
+        let hres = unsafe { (*p_enumerator).Next(timeout_ms, 1, &mut pcls_obj, &mut return_value) };
+
+        if hres == WBEM_S_TIMEDOUT as HRESULT {
+            return Ok(None);
+        }
+
+        check_hres(hres)?;
+
+        if return_value == 0 {
+            return Ok(None);
+        }
+
+        trace!(
+            "Got enumerator {:?} and event {:?}",
+            self.p_enumerator,
+            pcls_obj
+        );
+
+        Ok(Some(IWbemClassWrapper::new(NonNull::new(pcls_obj))))
+    }
+
+    /// Stop receiving events. This is the same as dropping the enumerator.
+    ///
+    pub fn cancel(self) {}
+}
+
+impl<'a> Drop for NotificationEnumerator<'a> {
+    fn drop(&mut self) {
+        if let Some(p_enumerator) = self.p_enumerator.take() {
+            unsafe {
+                (*p_enumerator.as_ptr()).Release();
+            }
+        }
+    }
+}
+
+/// Block until the next event arrives.
+impl<'a> Iterator for NotificationEnumerator<'a> {
+    type Item = Result<IWbemClassWrapper, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_event(WBEM_INFINITE as i32).transpose()
+    }
+}
+
+/// The events of a notification query, deserialized into T.
+///
+pub struct NotificationQuery<'a, T> {
+    enumerator: NotificationEnumerator<'a>,
+    _phantom: PhantomData<T>,
+}
+
+impl<'a, T> NotificationQuery<'a, T>
+where
+    T: de::DeserializeOwned,
+{
+    /// Wait up to `timeout` for the next event (see `NotificationEnumerator::next_with_timeout`).
+    ///
+    pub fn next_with_timeout(&mut self, timeout: Duration) -> Result<Option<T>, Error> {
+        match self.enumerator.next_with_timeout(timeout)? {
+            Some(event) => Ok(Some(from_wbem_class_obj(&event)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Stop receiving events. This is the same as dropping the query.
+    ///
+    pub fn cancel(self) {}
+}
+
+impl<'a, T> Iterator for NotificationQuery<'a, T>
+where
+    T: de::DeserializeOwned,
+{
+    type Item = Result<T, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.enumerator
+            .next()
+            .map(|event| Ok(from_wbem_class_obj(&event?)?))
+    }
+}
+
+impl WMIConnection {
+    /// Execute the given notification query (like `SELECT * FROM __InstanceCreationEvent ...`)
+    /// and return an enumerator of the events.
+    ///
+    pub fn exec_notification_query_native_wrapper(
+        &self,
+        query: impl AsRef<str>,
+    ) -> Result<NotificationEnumerator<'_>, Error> {
+        let query_language = WideCString::from_str("WQL")?;
+        let query = WideCString::from_str(query)?;
+
+        let mut p_enumerator = NULL as *mut IEnumWbemClassObject;
+
+        // Notification queries must be semi-synchronous.
+        unsafe {
+            check_hres((*self.svc()).ExecNotificationQuery(
+                query_language.as_ptr() as *mut _,
+                query.as_ptr() as *mut _,
+                (WBEM_FLAG_FORWARD_ONLY | WBEM_FLAG_RETURN_IMMEDIATELY) as i32,
+                ptr::null_mut(),
+                &mut p_enumerator,
+            ))?;
+        }
+
+        trace!("Got notification enumerator {:?}", p_enumerator);
+
+        let enumerator = NotificationEnumerator::new(self, p_enumerator);
+
+        self.set_call_proxy(p_enumerator as _)?;
+
+        Ok(enumerator)
+    }
+
+    /// Execute a free-text notification query, deserializing each event into T.
+    ///
+    /// ```edition2018
+    /// # use wmi::*;
+    /// # use serde::Deserialize;
+    /// # use std::time::Duration;
+    /// # let con = WMIConnection::new(COMLibrary::new().unwrap().into()).unwrap();
+    /// #[derive(Deserialize, Debug)]
+    /// struct __InstanceCreationEvent {
+    ///     // `uint64` properties are returned as strings.
+    ///     TIME_CREATED: String,
+    /// }
+    ///
+    /// let mut events = con
+    ///     .raw_notification_query::<__InstanceCreationEvent>(
+    ///         "SELECT * FROM __InstanceCreationEvent WITHIN 1 WHERE TargetInstance ISA 'Win32_Process'",
+    ///     )
+    ///     .unwrap();
+    ///
+    /// // Returns `None` if no process was created in the last second.
+    /// let event = events.next_with_timeout(Duration::from_secs(1)).unwrap();
+    /// #
+    ///
+    pub fn raw_notification_query<T>(
+        &self,
+        query: impl AsRef<str>,
+    ) -> Result<NotificationQuery<'_, T>, Error>
+    where
+        T: de::DeserializeOwned,
+    {
+        let enumerator = self.exec_notification_query_native_wrapper(query)?;
+
+        Ok(NotificationQuery {
+            enumerator,
+            _phantom: PhantomData,
+        })
+    }
+}
+
+#[allow(non_snake_case)]
+#[allow(non_camel_case_types)]
+#[cfg(test)]
+mod tests {
+    use crate::tests::fixtures::*;
+    use crate::Variant;
+    use serde::Deserialize;
+    use std::process::Command;
+    use std::time::Duration;
+
+    const PROCESS_CREATION_QUERY: &str =
+        "SELECT * FROM __InstanceCreationEvent WITHIN 1 WHERE TargetInstance ISA 'Win32_Process'";
+
+    #[test]
+    fn it_times_out_without_events() {
+        let wmi_con = wmi_con();
+
+        #[derive(Deserialize, Debug)]
+        struct __InstanceDeletionEvent {
+            TIME_CREATED: String,
+        }
+
+        let mut events = wmi_con
+            .raw_notification_query::<__InstanceDeletionEvent>(
+                "SELECT * FROM __InstanceDeletionEvent WITHIN 1 WHERE TargetInstance ISA 'Win32_TapeDrive'",
+            )
+            .unwrap();
+
+        let event = events
+            .next_with_timeout(Duration::from_millis(100))
+            .unwrap();
+
+        assert!(event.is_none());
+
+        events.cancel();
+    }
+
+    /// Might require running as an administrator (depending on the event provider's security).
+    #[test]
+    fn it_receives_process_creation_events() {
+        let wmi_con = wmi_con();
+
+        let mut events = wmi_con
+            .exec_notification_query_native_wrapper(PROCESS_CREATION_QUERY)
+            .unwrap();
+
+        Command::new("cmd.exe")
+            .args(&["/C", "exit"])
+            .status()
+            .unwrap();
+
+        let mut event = None;
+
+        // The query polls every second, so the event should arrive within a few seconds.
+        for _ in 0..10 {
+            event = events.next_with_timeout(Duration::from_secs(1)).unwrap();
+
+            if event.is_some() {
+                break;
+            }
+        }
+
+        let event = event.unwrap();
+
+        assert_eq!(
+            event.get_property("__CLASS").unwrap(),
+            Variant::String("__InstanceCreationEvent".into())
+        );
+    }
+
+    /// Might require running as an administrator (depending on the event provider's security).
+    #[test]
+    fn it_desr_aggregate_events_with_embedded_objects() {
+        let wmi_con = wmi_con();
+
+        #[derive(Deserialize, Debug)]
+        struct Win32_Process {
+            Name: String,
+            ProcessId: u32,
+        }
+
+        #[derive(Deserialize, Debug)]
+        struct __InstanceCreationEvent {
+            TargetInstance: Win32_Process,
+        }
+
+        #[derive(Deserialize, Debug)]
+        struct __AggregateEvent {
+            NumberOfEvents: u32,
+            Representative: __InstanceCreationEvent,
+        }
+
+        let mut events = wmi_con
+            .raw_notification_query::<__AggregateEvent>(format!(
+                "{} GROUP WITHIN 2",
+                PROCESS_CREATION_QUERY
+            ))
+            .unwrap();
+
+        for _ in 0..3 {
+            Command::new("cmd.exe")
+                .args(&["/C", "exit"])
+                .status()
+                .unwrap();
+        }
+
+        let mut event = None;
+
+        // The events are grouped for 2 seconds (after polling every second).
+        for _ in 0..10 {
+            event = events.next_with_timeout(Duration::from_secs(1)).unwrap();
+
+            if event.is_some() {
+                break;
+            }
+        }
+
+        let event = event.unwrap();
+
+        assert!(event.NumberOfEvents >= 1);
+        assert!(!event.Representative.TargetInstance.Name.is_empty());
+        assert_ne!(event.Representative.TargetInstance.ProcessId, 0);
+    }
+}
@@ -0,0 +1,259 @@
+//! Computing values from raw performance counter classes (`Win32_PerfRawData_*`).
+//!
+//! Raw counters are not meaningful on their own: two samples are needed, and the formula
+//! which computes the final value depends on the `CounterType` qualifier of the property.
+//!
+//! ```edition2018
+//! # use wmi::*;
+//! # use wmi::perf::PerfCounter;
+//! # use std::{thread, time::Duration};
+//! # let wmi_con = WMIConnection::new(COMLibrary::new().unwrap().into()).unwrap();
+//! let query = "SELECT * FROM Win32_PerfRawData_PerfOS_System";
+//!
+//! let first_obj = wmi_con.exec_query_native_wrapper(query).unwrap().next().unwrap().unwrap();
+//! let counter = PerfCounter::from_class_obj(&first_obj, "SystemCallsPerSec").unwrap();
+//! let first = counter.sample(&first_obj).unwrap();
+//!
+//! thread::sleep(Duration::from_secs(1));
+//!
+//! let second_obj = wmi_con.exec_query_native_wrapper(query).unwrap().next().unwrap().unwrap();
+//! let second = counter.sample(&second_obj).unwrap();
+//!
+//! println!("System calls/sec: {}", counter.compute(&first, &second).unwrap());
+//! ```
+//!
+//! See [WMI Performance Counter Types] for more info about the different counter types.
+//!
+//! [WMI Performance Counter Types]: https://docs.microsoft.com/en-us/windows/desktop/wmisdk/wmi-performance-counter-types
+//!
+use crate::result_enumerator::IWbemClassWrapper;
+use crate::Variant;
+use failure::{bail, Error};
+
+/// A rate counter (for example, `SystemCallsPerSec`).
+pub const PERF_COUNTER_COUNTER: u32 = 0x1041_0400;
+/// A percentage of elapsed time counter (for example, `PercentProcessorTime` of a process).
+pub const PERF_100NSEC_TIMER: u32 = 0x2051_0500;
+
+/// The supported counter types (which is the value of the `CounterType` qualifier).
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CounterType {
+    /// `PERF_COUNTER_COUNTER`: `(N1 - N0) / ((T1 - T0) / F)`,
+    /// using `Timestamp_PerfTime` and `Frequency_PerfTime`.
+    Counter,
+    /// `PERF_100NSEC_TIMER`: `100 * (N1 - N0) / (T1 - T0)`, using `Timestamp_Sys100NS`.
+    Timer100Ns,
+}
+
+impl CounterType {
+    pub fn from_raw(counter_type: u32) -> Result<Self, Error> {
+        match counter_type {
+            PERF_COUNTER_COUNTER => Ok(CounterType::Counter),
+            PERF_100NSEC_TIMER => Ok(CounterType::Timer100Ns),
+            _ => bail!("Counter type {:#X} is not supported yet", counter_type),
+        }
+    }
+}
+
+/// A single sample of a raw counter, with the timestamps needed to compute its value.
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PerfSample {
+    pub value: u64,
+    pub timestamp_perf_time: u64,
+    pub frequency_perf_time: u64,
+    pub timestamp_sys100ns: u64,
+}
+
+impl PerfSample {
+    /// Read the counter `property` and the timestamp properties of a raw perf class object.
+    ///
+    pub fn from_class_obj(obj: &IWbemClassWrapper, property: &str) -> Result<Self, Error> {
+        Ok(Self {
+            value: variant_to_u64(obj.get_property(property)?)?,
+            timestamp_perf_time: variant_to_u64(obj.get_property("Timestamp_PerfTime")?)?,
+            frequency_perf_time: variant_to_u64(obj.get_property("Frequency_PerfTime")?)?,
+            timestamp_sys100ns: variant_to_u64(obj.get_property("Timestamp_Sys100NS")?)?,
+        })
+    }
+}
+
+/// A raw performance counter property, which computes values from pairs of samples.
+///
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PerfCounter {
+    property: String,
+    counter_type: CounterType,
+}
+
+impl PerfCounter {
+    pub fn new(property: impl Into<String>, counter_type: CounterType) -> Self {
+        Self {
+            property: property.into(),
+            counter_type,
+        }
+    }
+
+    /// Create a counter for `property`, using its `CounterType` qualifier.
+    ///
+    pub fn from_class_obj(obj: &IWbemClassWrapper, property: &str) -> Result<Self, Error> {
+        let counter_type = match obj.get_property_qualifier(property, "CounterType")? {
+            Variant::I4(n) => n as u32,
+            other => bail!("Expected CounterType qualifier to be an I4, got {:?}", other),
+        };
+
+        Ok(Self::new(property, CounterType::from_raw(counter_type)?))
+    }
+
+    pub fn property(&self) -> &str {
+        &self.property
+    }
+
+    pub fn counter_type(&self) -> CounterType {
+        self.counter_type
+    }
+
+    /// Read a sample of this counter from a raw perf class object.
+    ///
+    pub fn sample(&self, obj: &IWbemClassWrapper) -> Result<PerfSample, Error> {
+        PerfSample::from_class_obj(obj, &self.property)
+    }
+
+    /// Compute the value of the counter over the interval between two samples.
+    ///
+    pub fn compute(&self, first: &PerfSample, second: &PerfSample) -> Result<f64, Error> {
+        if second.value < first.value {
+            bail!(
+                "Counter {} decreased between samples ({} -> {})",
+                self.property,
+                first.value,
+                second.value
+            );
+        }
+
+        let value_delta = (second.value - first.value) as f64;
+
+        match self.counter_type {
+            CounterType::Counter => {
+                let time_delta = timestamp_delta(
+                    first.timestamp_perf_time,
+                    second.timestamp_perf_time,
+                )?;
+
+                if second.frequency_perf_time == 0 {
+                    bail!("Expected Frequency_PerfTime to be non-zero");
+                }
+
+                let elapsed_secs = time_delta / second.frequency_perf_time as f64;
+
+                Ok(value_delta / elapsed_secs)
+            }
+            CounterType::Timer100Ns => {
+                let time_delta =
+                    timestamp_delta(first.timestamp_sys100ns, second.timestamp_sys100ns)?;
+
+                Ok(100.0 * value_delta / time_delta)
+            }
+        }
+    }
+}
+
+fn timestamp_delta(first: u64, second: u64) -> Result<f64, Error> {
+    if second <= first {
+        bail!(
+            "Expected the second sample to be taken after the first ({} -> {})",
+            first,
+            second
+        );
+    }
+
+    Ok((second - first) as f64)
+}
+
+fn variant_to_u64(value: Variant) -> Result<u64, Error> {
+    match value {
+        // `uint64` properties are returned as strings.
+        Variant::String(s) => Ok(s.parse()?),
+        Variant::UI8(n) => Ok(n),
+        Variant::UI1(n) => Ok(u64::from(n)),
+        Variant::I8(n) if n >= 0 => Ok(n as u64),
+        Variant::I4(n) if n >= 0 => Ok(n as u64),
+        Variant::I2(n) if n >= 0 => Ok(n as u64),
+        other => bail!("Expected a non-negative integer, got {:?}", other),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_computes_counter_counter() {
+        let counter = PerfCounter::new("SystemCallsPerSec", CounterType::Counter);
+
+        let first = PerfSample {
+            value: 1_000,
+            timestamp_perf_time: 10_000_000,
+            frequency_perf_time: 10_000_000,
+            timestamp_sys100ns: 0,
+        };
+
+        // Two seconds later.
+        let second = PerfSample {
+            value: 5_000,
+            timestamp_perf_time: 30_000_000,
+            ..first
+        };
+
+        assert_eq!(counter.compute(&first, &second).unwrap(), 2_000.0);
+    }
+
+    #[test]
+    fn it_computes_100nsec_timer() {
+        let counter = PerfCounter::new("PercentProcessorTime", CounterType::Timer100Ns);
+
+        let first = PerfSample {
+            value: 2_000_000,
+            timestamp_perf_time: 0,
+            frequency_perf_time: 10_000_000,
+            timestamp_sys100ns: 100_000_000,
+        };
+
+        // One second later, with a quarter of a second of CPU time.
+        let second = PerfSample {
+            value: 4_500_000,
+            timestamp_sys100ns: 110_000_000,
+            ..first
+        };
+
+        assert_eq!(counter.compute(&first, &second).unwrap(), 25.0);
+    }
+
+    #[test]
+    fn it_fails_to_compute_without_elapsed_time() {
+        let counter = PerfCounter::new("SystemCallsPerSec", CounterType::Counter);
+
+        let sample = PerfSample {
+            value: 1_000,
+            timestamp_perf_time: 10_000_000,
+            frequency_perf_time: 10_000_000,
+            timestamp_sys100ns: 0,
+        };
+
+        assert!(counter.compute(&sample, &sample).is_err());
+    }
+
+    #[test]
+    fn it_parses_counter_types() {
+        assert_eq!(
+            CounterType::from_raw(272_696_320).unwrap(),
+            CounterType::Counter
+        );
+        assert_eq!(
+            CounterType::from_raw(542_180_608).unwrap(),
+            CounterType::Timer100Ns
+        );
+        assert!(CounterType::from_raw(0).is_err());
+    }
+}
@@ -1,20 +1,104 @@
-use crate::de::wbem_class_de::from_wbem_class_obj;
-use crate::result_enumerator::QueryResultEnumerator;
-use crate::{connection::WMIConnection, de::meta::struct_name_and_fields, utils::check_hres};
-use failure::{Error, format_err};
+use crate::de::wbem_class_de::{from_wbem_class_obj, Deserializer, OnError};
+use crate::result_enumerator::{IWbemClassWrapper, PropertyNamesCache, QueryResultEnumerator};
+use crate::{
+    cim_type::CimType,
+    class::WMIClass,
+    connection::WMIConnection,
+    context::WMIContext,
+    de::meta::struct_name_and_fields,
+    utils::{check_hres, WMIError},
+    Variant,
+};
+use failure::{format_err, Error};
 use log::trace;
 use serde::de;
+use std::cell::RefCell;
 use std::collections::HashMap;
 use std::ptr;
+use std::ptr::NonNull;
+use std::time::{Duration, Instant};
 use widestring::WideCString;
 use winapi::{
-    shared::ntdef::NULL,
+    shared::ntdef::{HRESULT, NULL},
     um::{
-        wbemcli::IEnumWbemClassObject,
-        wbemcli::{WBEM_FLAG_FORWARD_ONLY, WBEM_FLAG_RETURN_IMMEDIATELY},
+        wbemcli::{IEnumWbemClassObject, IWbemCallResult, IWbemClassObject},
+        wbemcli::{
+            WBEM_E_INVALID_OBJECT_PATH, WBEM_E_INVALID_QUERY, WBEM_FLAG_DIRECT_READ,
+            WBEM_FLAG_ENSURE_LOCATABLE, WBEM_FLAG_FORWARD_ONLY, WBEM_FLAG_PROTOTYPE,
+            WBEM_FLAG_RETURN_IMMEDIATELY, WBEM_FLAG_RETURN_WBEM_COMPLETE,
+            WBEM_FLAG_USE_AMENDED_QUALIFIERS, WBEM_INFINITE,
+        },
     },
 };
 
+/// Options which control how a query is executed.
+///
+/// The defaults match the behavior of the regular query methods.
+///
+#[derive(Debug, Clone, Default)]
+pub struct QueryOptions {
+    /// Use `WBEM_FLAG_ENSURE_LOCATABLE`, so the key properties and the `__PATH`
+    /// (and other path-related system properties) of the returned objects are always available,
+    /// even when only a subset of the properties is selected (like `SELECT Name FROM ...`).
+    pub ensure_locatable: bool,
+    /// Trim leading and trailing whitespace from every deserialized string
+    /// (some classes, like the SMBIOS-backed ones, return space-padded values).
+    pub trim_strings: bool,
+    /// Don't use `WBEM_FLAG_FORWARD_ONLY`, so the results can be iterated again
+    /// using [`QueryResultEnumerator::reset`]. WMI keeps every result alive until the
+    /// enumerator is released, so this uses more memory for large result sets.
+    pub bidirectional: bool,
+    /// Use `WBEM_FLAG_DIRECT_READ`, so only the provider of the queried class is used,
+    /// without considering its subclasses. This is faster for classes with many subclasses,
+    /// but changes the results: instances of subclasses are **not** returned
+    /// (so `SELECT * FROM CIM_LogicalDevice` won't return any `Win32_DiskDrive`).
+    pub direct_read: bool,
+    /// What to do when a single property fails to convert (by default, fail the entire object).
+    pub on_error: OnError,
+    /// Pass the given context (see [`WMIContext`]) to the providers used by the query.
+    pub context: Option<WMIContext>,
+    /// The number of objects requested in each call to `IEnumWbemClassObject::Next`
+    /// (see [`QueryResultEnumerator::batch_size`]). One object at a time if not set.
+    pub batch_size: Option<u32>,
+}
+
+impl QueryOptions {
+    fn flags(&self) -> u32 {
+        let mut flags = WBEM_FLAG_RETURN_IMMEDIATELY;
+
+        if !self.bidirectional {
+            flags |= WBEM_FLAG_FORWARD_ONLY;
+        }
+
+        if self.ensure_locatable {
+            flags |= WBEM_FLAG_ENSURE_LOCATABLE;
+        }
+
+        if self.direct_read {
+            flags |= WBEM_FLAG_DIRECT_READ;
+        }
+
+        flags
+    }
+}
+
+/// Timings of a query, returned by [`WMIConnection::query_with_metrics`].
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct QueryMetrics {
+    /// The time `ExecQuery` took to return an enumerator. Queries use
+    /// `WBEM_FLAG_RETURN_IMMEDIATELY`, so this doesn't include the work of the provider.
+    pub exec_duration: Duration,
+    /// The time it took to enumerate (and deserialize) all the results.
+    pub enumeration_duration: Duration,
+    /// The number of returned objects.
+    pub object_count: usize,
+}
+
+/// The properties of an object with their declared CIM types,
+/// returned by [`WMIConnection::raw_query_with_types`].
+pub type TypedProperties = HashMap<String, (Variant, CimType)>;
+
 pub enum FilterValue {
     Bool(bool),
     Number(i64),
@@ -22,6 +106,12 @@ pub enum FilterValue {
     String(String),
 }
 
+/// The direction of an `ORDER BY` clause.
+pub enum SortOrder {
+    Ascending,
+    Descending,
+}
+
 /// Build an SQL query for the given filters, over the given type (using it's name and fields).
 /// For example, for:
 ///
@@ -46,12 +136,41 @@ pub enum FilterValue {
 /// "SELECT Caption, Debug FROM Win32_OperatingSystem";
 /// ```
 ///
-fn build_query<'de, T>(filters: Option<&HashMap<String, FilterValue>>) -> String
+fn build_query<'de, T>(
+    filters: Option<&HashMap<String, FilterValue>>,
+    order_by: Option<(&str, &SortOrder)>,
+) -> String
+where
+    T: de::Deserialize<'de>,
+{
+    let (name, _) = struct_name_and_fields::<T>();
+
+    build_query_for_class::<T>(name, filters, order_by)
+}
+
+/// Like `build_query`, but selecting from `name` instead of T's class.
+///
+fn build_query_for_class<'de, T>(
+    name: &str,
+    filters: Option<&HashMap<String, FilterValue>>,
+    order_by: Option<(&str, &SortOrder)>,
+) -> String
 where
     T: de::Deserialize<'de>,
 {
-    let (name, fields) = struct_name_and_fields::<T>();
+    let (_, fields) = struct_name_and_fields::<T>();
 
+    build_query_with_fields(name, fields, filters, order_by)
+}
+
+/// Like `build_query`, but using explicit class and property names.
+///
+fn build_query_with_fields(
+    name: &str,
+    fields: &[&str],
+    filters: Option<&HashMap<String, FilterValue>>,
+    order_by: Option<(&str, &SortOrder)>,
+) -> String {
     let optional_where_clause = match filters {
         None => String::new(),
         Some(filters) => {
@@ -85,16 +204,99 @@ where
         }
     };
 
+    let optional_order_by_clause = match order_by {
+        None => String::new(),
+        Some((field, order)) => {
+            let direction = match order {
+                SortOrder::Ascending => "ASC",
+                SortOrder::Descending => "DESC",
+            };
+
+            format!("ORDER BY {} {}", field, direction)
+        }
+    };
+
+    let optional_clauses: Vec<String> = vec![optional_where_clause, optional_order_by_clause]
+        .into_iter()
+        .filter(|clause| !clause.is_empty())
+        .collect();
+
     let query_text = format!(
         "SELECT {} FROM {} {}",
         fields.join(","),
         name,
-        optional_where_clause
+        optional_clauses.join(" ")
     );
 
     query_text
 }
 
+/// Build an `ASSOCIATORS OF` query for the objects of class `result_class`
+/// which are associated with the object at `object_path`.
+///
+fn build_associators_query(object_path: &str, result_class: &str) -> String {
+    format!(
+        "ASSOCIATORS OF {{{}}} WHERE ResultClass = {}",
+        object_path, result_class
+    )
+}
+
+/// The system properties (like `__PATH`) which are explicitly selected by a `SELECT` query,
+/// so they can be deserialized into a map (`GetNames` never returns system properties).
+///
+fn selected_system_properties(query: &str) -> Vec<String> {
+    let query = query.trim_start();
+    let upper = query.to_ascii_uppercase();
+
+    if !upper.starts_with("SELECT ") {
+        return vec![];
+    }
+
+    let columns = match upper.find(" FROM ") {
+        Some(end) => &query["SELECT ".len()..end],
+        None => return vec![],
+    };
+
+    columns
+        .split(',')
+        .map(str::trim)
+        .filter(|column| column.starts_with("__"))
+        .map(str::to_owned)
+        .collect()
+}
+
+/// Build a query which only selects the `__PATH` of the objects of class `class_name`.
+///
+fn build_paths_query(class_name: &str, filter: Option<&str>) -> String {
+    match filter {
+        None => format!("SELECT __PATH FROM {}", class_name),
+        Some(filter) => format!("SELECT __PATH FROM {} WHERE {}", class_name, filter),
+    }
+}
+
+/// Some providers return a success code from `ExecQuery` without an enumerator,
+/// which must not be confused with an enumerator which returns no objects.
+///
+fn check_enumerator(p_enumerator: *mut IEnumWbemClassObject) -> Result<(), WMIError> {
+    if p_enumerator.is_null() {
+        return Err(WMIError::NullEnumerator);
+    }
+
+    Ok(())
+}
+
+/// Check the result of a `GetObject` call, using a dedicated error for malformed paths.
+///
+fn check_object_path_hres(hres: HRESULT, object_path: &str) -> Result<(), WMIError> {
+    if hres == WBEM_E_INVALID_OBJECT_PATH as HRESULT {
+        return Err(WMIError::InvalidObjectPath {
+            path: object_path.to_owned(),
+        });
+    }
+
+    check_hres(hres)
+}
+
 impl WMIConnection {
     /// Execute the given query and return an iterator of WMI pointers.
     /// It's better to use the other query methods, since this is relatively low level.
@@ -103,217 +305,1420 @@ impl WMIConnection {
         &self,
         query: impl AsRef<str>,
     ) -> Result<QueryResultEnumerator, Error> {
+        self.exec_query_native_wrapper_with_options(query, &QueryOptions::default())
+    }
+
+    /// Like `exec_query_native_wrapper`, but using the given options.
+    ///
+    pub fn exec_query_native_wrapper_with_options(
+        &self,
+        query: impl AsRef<str>,
+        options: &QueryOptions,
+    ) -> Result<QueryResultEnumerator<'_>, Error> {
+        let enumerator =
+            self.exec_query_with_flags(query, options.flags(), options.context.as_ref())?;
+
+        Ok(enumerator.batch_size(options.batch_size.unwrap_or(1)))
+    }
+
+    /// Like `exec_query_native_wrapper`, but without `WBEM_FLAG_RETURN_IMMEDIATELY`.
+    ///
+    /// `ExecQuery` will block until the provider is ready to return results,
+    /// so errors like an invalid class are reported here instead of when iterating.
+    /// This adds latency to the call itself (vs. the default semi-synchronous mode
+    /// which returns immediately and waits in each call to `Next`).
+    ///
+    pub fn exec_query_sync_native_wrapper(
+        &self,
+        query: impl AsRef<str>,
+    ) -> Result<QueryResultEnumerator<'_>, Error> {
+        self.exec_query_with_flags(query, WBEM_FLAG_FORWARD_ONLY, None)
+    }
+
+    /// Validate the given query without executing it, so errors like a syntax error
+    /// (`WBEM_E_INVALID_QUERY`) or a missing class (`WBEM_E_INVALID_CLASS`) are returned
+    /// without waiting for (or transferring) any results.
+    ///
+    /// This uses `WBEM_FLAG_PROTOTYPE`, so WMI only builds the class definition of the results.
+    ///
+    /// ```edition2018
+    /// # use wmi::*;
+    /// # let con = WMIConnection::new(COMLibrary::new().unwrap().into()).unwrap();
+    /// assert!(con.validate_query("SELECT Name FROM Win32_Process").is_ok());
+    /// assert!(con.validate_query("SELECT Name FROM Win32_Proces").is_err());
+    /// #
+    ///
+    pub fn validate_query(&self, wql: &str) -> Result<(), Error> {
+        let enumerator = self
+            .exec_query_with_flags(wql, WBEM_FLAG_FORWARD_ONLY | WBEM_FLAG_PROTOTYPE, None)
+            .map_err(|e| self.query_error(wql, e))?;
+
+        enumerator.cancel();
+
+        Ok(())
+    }
+
+    /// Get the prototype of the given class: an object with the class's properties
+    /// (and their declared types and qualifiers), but without any instance data.
+    ///
+    /// This executes `SELECT * FROM <class_name>` with `WBEM_FLAG_PROTOTYPE`,
+    /// so no instances are enumerated (and no provider is called).
+    ///
+    /// ```edition2018
+    /// # use wmi::*;
+    /// # let con = WMIConnection::new(COMLibrary::new().unwrap().into()).unwrap();
+    /// let class = con.get_class("Win32_OperatingSystem").unwrap();
+    ///
+    /// for name in class.list_properties().unwrap() {
+    ///     let (_, cim_type) = class.get_property_with_type(&name).unwrap();
+    ///     println!("{}: {:?}", name, cim_type);
+    /// }
+    /// #
+    ///
+    pub fn get_class(&self, class_name: &str) -> Result<IWbemClassWrapper, Error> {
+        let query = format!("SELECT * FROM {}", class_name);
+
+        let mut enumerator = self
+            .exec_query_with_flags(&query, WBEM_FLAG_FORWARD_ONLY | WBEM_FLAG_PROTOTYPE, None)
+            .map_err(|e| self.query_error(&query, e))?;
+
+        match enumerator.next_raw() {
+            Some(prototype) => prototype,
+            None => Err(WMIError::NoResults.into()),
+        }
+    }
+
+    fn exec_query_with_flags(
+        &self,
+        query: impl AsRef<str>,
+        flags: u32,
+        context: Option<&WMIContext>,
+    ) -> Result<QueryResultEnumerator<'_>, Error> {
         let query_language = WideCString::from_str("WQL")?;
         let query = WideCString::from_str(query)?;
 
         let mut p_enumerator = NULL as *mut IEnumWbemClassObject;
 
-        unsafe {
-            check_hres((*self.svc()).ExecQuery(
+        let hres = unsafe {
+            (*self.svc()).ExecQuery(
                 query_language.as_ptr() as *mut _,
                 query.as_ptr() as *mut _,
-                (WBEM_FLAG_FORWARD_ONLY | WBEM_FLAG_RETURN_IMMEDIATELY) as i32,
-                ptr::null_mut(),
+                flags as i32,
+                context.map_or(ptr::null_mut(), WMIContext::ctx),
                 &mut p_enumerator,
-            ))?;
-        }
+            )
+        };
+
+        self.wrap_exec_query_result(hres, p_enumerator, flags)
+    }
+
+    /// Check the result of an `ExecQuery` call, and wrap the returned enumerator.
+    ///
+    fn wrap_exec_query_result(
+        &self,
+        hres: HRESULT,
+        p_enumerator: *mut IEnumWbemClassObject,
+        flags: u32,
+    ) -> Result<QueryResultEnumerator<'_>, Error> {
+        check_hres(hres)?;
 
         trace!("Got enumerator {:?}", p_enumerator);
 
-        Ok(QueryResultEnumerator::new(self, p_enumerator))
+        check_enumerator(p_enumerator)?;
+
+        let enumerator = QueryResultEnumerator::new(self, p_enumerator)
+            .forward_only(flags & WBEM_FLAG_FORWARD_ONLY != 0);
+
+        self.set_call_proxy(p_enumerator as _)?;
+
+        Ok(enumerator)
     }
 
-    /// Execute a free-text query and deserialize the results.
-    /// Can be used either with a struct (like `query` and `filtered_query`),
-    /// but also with a generic map.
+    /// Get the object at the given path (for example, `Win32_LogicalDisk.DeviceID="C:"`).
+    ///
+    /// If the path is malformed, a [`WMIError::InvalidObjectPath`](crate::utils::WMIError::InvalidObjectPath)
+    /// with the given path is returned.
     ///
     /// ```edition2018
     /// # use wmi::*;
-    /// # use std::collections::HashMap;
     /// # let con = WMIConnection::new(COMLibrary::new().unwrap().into()).unwrap();
-    /// let results : Vec<HashMap<String, Variant>> = con.raw_query("SELECT Name FROM Win32_OperatingSystem").unwrap();
+    /// let disk = con.get_object(r#"Win32_LogicalDisk.DeviceID="C:""#).unwrap();
     /// #
     ///
-    pub fn raw_query<T>(&self, query: impl AsRef<str>) -> Result<Vec<T>, Error>
-    where
-        T: de::DeserializeOwned,
-    {
-        let enumerator = self.exec_query_native_wrapper(query)?;
-
-        enumerator
-            .map(|item| match item {
-                Ok(wbem_class_obj) => {
-                    let value = from_wbem_class_obj(&wbem_class_obj);
-
-                    value.map_err(Error::from)
-                }
-                Err(e) => Err(e),
-            })
-            .collect()
+    pub fn get_object(&self, object_path: &str) -> Result<IWbemClassWrapper, Error> {
+        self.get_object_with_flags(object_path, WBEM_FLAG_RETURN_WBEM_COMPLETE, None)
     }
 
-    /// Query all the objects of type T.
+    /// Like `get_object`, but passing the given context to the provider of the object.
     ///
     /// ```edition2018
     /// # use wmi::*;
-    /// # use serde::Deserialize;
     /// # let con = WMIConnection::new(COMLibrary::new().unwrap().into()).unwrap();
-    /// #[derive(Deserialize)]
-    /// struct Win32_OperatingSystem {
-    ///     Name: String,
-    /// }
-    /// con.query::<Win32_OperatingSystem>();
+    /// let context = WMIContext::new().unwrap();
+    /// context.set_value("__ProviderArchitecture", &Variant::I4(64)).unwrap();
+    ///
+    /// let disk = con.get_object_with_context(r#"Win32_LogicalDisk.DeviceID="C:""#, &context).unwrap();
     /// #
     ///
-    pub fn query<T>(&self) -> Result<Vec<T>, Error>
-    where
-        T: de::DeserializeOwned,
-    {
-        let query_text = build_query::<T>(None);
-
-        self.raw_query(&query_text)
+    pub fn get_object_with_context(
+        &self,
+        object_path: &str,
+        context: &WMIContext,
+    ) -> Result<IWbemClassWrapper, Error> {
+        self.get_object_with_flags(object_path, WBEM_FLAG_RETURN_WBEM_COMPLETE, Some(context))
     }
 
-    /// Query all the objects of type T, while filtering according to `filters`.
+    /// Like `get_object`, but using `WBEM_FLAG_USE_AMENDED_QUALIFIERS`, so the returned object
+    /// also has the localized qualifiers (like `Description` and `Values`) of its class.
     ///
-    pub fn filtered_query<T>(&self, filters: &HashMap<String, FilterValue>) -> Result<Vec<T>, Error>
-    where
-        T: de::DeserializeOwned,
-    {
-        let query_text = build_query::<T>(Some(&filters));
+    /// ```edition2018
+    /// # use wmi::*;
+    /// # let con = WMIConnection::new(COMLibrary::new().unwrap().into()).unwrap();
+    /// let class = con.get_object_with_amended_qualifiers("Win32_LogicalDisk").unwrap();
+    /// let drive_types = class.value_map("DriveType").unwrap();
+    /// #
+    ///
+    pub fn get_object_with_amended_qualifiers(
+        &self,
+        object_path: &str,
+    ) -> Result<IWbemClassWrapper, Error> {
+        self.get_object_with_flags(
+            object_path,
+            WBEM_FLAG_RETURN_WBEM_COMPLETE | WBEM_FLAG_USE_AMENDED_QUALIFIERS,
+            None,
+        )
+    }
 
-        self.raw_query(&query_text)
+    fn get_object_with_flags(
+        &self,
+        object_path: &str,
+        flags: u32,
+        context: Option<&WMIContext>,
+    ) -> Result<IWbemClassWrapper, Error> {
+        let object_path_bstr = WideCString::from_str(object_path)?;
+
+        let mut pcls_obj = NULL as *mut IWbemClassObject;
+
+        let hres = unsafe {
+            (*self.svc()).GetObject(
+                object_path_bstr.as_ptr() as *mut _,
+                flags as i32,
+                context.map_or(ptr::null_mut(), WMIContext::ctx),
+                &mut pcls_obj,
+                ptr::null_mut(),
+            )
+        };
+
+        check_object_path_hres(hres, object_path)?;
+
+        Ok(IWbemClassWrapper::new(NonNull::new(pcls_obj)))
     }
 
-    /// Get a single object of type T.
-    /// If non are found, an error is returned.
-    /// If more than one object is found, all but the first are ignored.
+    /// Get the objects at the given paths (for example, from `query_raw_paths`),
+    /// returning a result for each of the paths (in the same order).
+    ///
+    /// Every call is started before waiting for any of their results, using semisynchronous
+    /// `GetObject` calls (`WBEM_FLAG_RETURN_IMMEDIATELY`). This avoids implementing an
+    /// `IWbemObjectSink` (which `GetObjectAsync` requires) and keeps every call on the calling
+    /// thread, so the connection's apartment rules still apply.
+    ///
+    /// The call results are separate proxies, which are blanketed like enumerators
+    /// (see [`with_proxy_blanket_per_call`](WMIConnection::with_proxy_blanket_per_call)).
     ///
     /// ```edition2018
     /// # use wmi::*;
-    /// # use serde::Deserialize;
     /// # let con = WMIConnection::new(COMLibrary::new().unwrap().into()).unwrap();
-    /// #[derive(Deserialize)]
-    /// struct Win32_OperatingSystem {
-    ///     Name: String,
+    /// let paths = con.query_raw_paths("Win32_Service", None).unwrap();
+    ///
+    /// for service in con.get_objects(&paths) {
+    ///     let service = service.unwrap();
     /// }
-    /// let os = con.get::<Win32_OperatingSystem>();
     /// #
     ///
-    pub fn get<T>(&self) -> Result<T, Error>
-    where
-        T: de::DeserializeOwned,
-    {
-        let results = self.query()?;
-
-        results.into_iter().next().ok_or_else(|| format_err!("No results returned"))
-    }
-}
+    pub fn get_objects(&self, paths: &[String]) -> Vec<Result<IWbemClassWrapper, Error>> {
+        let pending: Vec<_> = paths
+            .iter()
+            .map(|path| self.get_object_semisync(path))
+            .collect();
 
-#[allow(non_snake_case)]
-#[allow(non_camel_case_types)]
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use serde::Deserialize;
-    use std::collections::HashMap;
+        pending
+            .into_iter()
+            .zip(paths)
+            .map(|(call_result, path)| {
+                let call_result = call_result?.as_ptr();
 
-    use crate::tests::fixtures::*;
-    use crate::utils::WMIError;
-    use crate::Variant;
-    use winapi::shared::ntdef::HRESULT;
-    use winapi::um::wbemcli::WBEM_E_INVALID_QUERY;
+                let mut pcls_obj = NULL as *mut IWbemClassObject;
 
-    #[test]
-    fn it_works() {
-        let wmi_con = wmi_con();
+                let hres = unsafe {
+                    let hres = (*call_result).GetResultObject(WBEM_INFINITE as i32, &mut pcls_obj);
 
-        let enumerator = wmi_con
-            .exec_query_native_wrapper("SELECT * FROM Win32_OperatingSystem")
-            .unwrap();
+                    (*call_result).Release();
 
-        for res in enumerator {
-            let w = res.unwrap();
-            let mut props = w.list_properties().unwrap();
+                    hres
+                };
 
-            props.sort();
+                check_object_path_hres(hres, path)?;
 
-            assert_eq!(props.len(), 64);
-            assert_eq!(props[..2], ["BootDevice", "BuildNumber"]);
-            assert_eq!(props[props.len() - 2..], ["Version", "WindowsDirectory"])
-        }
+                Ok(IWbemClassWrapper::new(NonNull::new(pcls_obj)))
+            })
+            .collect()
     }
 
-    #[test]
-    fn it_fails_gracefully() {
-        let wmi_con = wmi_con();
-
-        let enumerator = wmi_con
-            .exec_query_native_wrapper("SELECT NoSuchField FROM Win32_OperatingSystem")
-            .unwrap();
+    /// Start a `GetObject` call, returning its (pending) call result.
+    ///
+    fn get_object_semisync(&self, object_path: &str) -> Result<NonNull<IWbemCallResult>, Error> {
+        let object_path_bstr = WideCString::from_str(object_path)?;
 
-        for res in enumerator {
-            dbg!(&res);
-            assert!(res.is_err())
-        }
-    }
+        let mut p_call_result = NULL as *mut IWbemCallResult;
 
-    #[test]
-    fn it_fails_gracefully_with_invalid_sql() {
-        let wmi_con = wmi_con();
+        let hres = unsafe {
+            (*self.svc()).GetObject(
+                object_path_bstr.as_ptr() as *mut _,
+                (WBEM_FLAG_RETURN_WBEM_COMPLETE | WBEM_FLAG_RETURN_IMMEDIATELY) as i32,
+                ptr::null_mut(),
+                ptr::null_mut(),
+                &mut p_call_result,
+            )
+        };
 
-        let enumerator = wmi_con.exec_query_native_wrapper("42").unwrap();
+        check_object_path_hres(hres, object_path)?;
 
-        // Show how to detect which error had occurred.
-        for res in enumerator {
-            match res {
-                Ok(_) => assert!(false),
-                Err(e) => {
-                    let cause = e.as_fail();
+        let call_result = NonNull::new(p_call_result)
+            .ok_or_else(|| format_err!("Expected a call result for {:?}", object_path))?;
 
-                    if let Some(wmi_err) = cause.downcast_ref::<WMIError>() {
-                        match wmi_err {
-                            WMIError::HResultError { hres } => {
-                                assert_eq!(*hres, WBEM_E_INVALID_QUERY as HRESULT);
-                            }
-                            _ => assert!(false),
-                        }
-                    } else {
-                        assert!(false);
-                    }
-                }
+        if let Err(e) = self.set_call_proxy(call_result.as_ptr() as _) {
+            unsafe {
+                (*call_result.as_ptr()).Release();
             }
-        }
-    }
-
-    #[test]
-    fn it_can_query_a_struct() {
-        let wmi_con = wmi_con();
 
-        #[derive(Deserialize, Debug)]
-        struct Win32_OperatingSystem {
-            Caption: String,
+            return Err(e);
         }
 
-        let results = wmi_con.query::<Win32_OperatingSystem>().unwrap();
-
-        for os in results {
-            assert_eq!(os.Caption, "Microsoft Windows 10 Pro");
-        }
+        Ok(call_result)
     }
 
-    #[test]
-    fn it_fails_gracefully_when_querying_a_struct() {
-        let wmi_con = wmi_con();
-
-        #[derive(Deserialize, Debug)]
+    /// Execute a free-text query and deserialize the results.
+    /// Can be used either with a struct (like `query` and `filtered_query`),
+    /// but also with a generic map.
+    ///
+    /// Any map type with `String` keys and `Variant` values can be used,
+    /// for example `BTreeMap` to get sorted keys (or `IndexMap`, with its `serde` feature).
+    ///
+    /// ```edition2018
+    /// # use wmi::*;
+    /// # use std::collections::{BTreeMap, HashMap};
+    /// # let con = WMIConnection::new(COMLibrary::new().unwrap().into()).unwrap();
+    /// let results : Vec<HashMap<String, Variant>> = con.raw_query("SELECT Name FROM Win32_OperatingSystem").unwrap();
+    /// let sorted_results : Vec<BTreeMap<String, Variant>> = con.raw_query("SELECT * FROM Win32_OperatingSystem").unwrap();
+    /// #
+    ///
+    pub fn raw_query<T>(&self, query: impl AsRef<str>) -> Result<Vec<T>, Error>
+    where
+        T: de::DeserializeOwned,
+    {
+        self.raw_query_with_options(query, &QueryOptions::default())
+    }
+
+    /// Like `raw_query`, but using the given options.
+    ///
+    /// ```edition2018
+    /// # use wmi::*;
+    /// # use wmi::query::QueryOptions;
+    /// # use std::collections::HashMap;
+    /// # let con = WMIConnection::new(COMLibrary::new().unwrap().into()).unwrap();
+    /// let options = QueryOptions { ensure_locatable: true, ..Default::default() };
+    ///
+    /// // Every result will have a `__PATH`, even though it wasn't selected.
+    /// let results : Vec<HashMap<String, Variant>> = con.raw_query_with_options("SELECT Name FROM Win32_Process", &options).unwrap();
+    /// #
+    ///
+    pub fn raw_query_with_options<T>(
+        &self,
+        query: impl AsRef<str>,
+        options: &QueryOptions,
+    ) -> Result<Vec<T>, Error>
+    where
+        T: de::DeserializeOwned,
+    {
+        self.raw_query_iter_with_options(query, options)?.collect()
+    }
+
+    /// Like `raw_query`, but the results are deserialized one at a time while iterating
+    /// (instead of being collected), so memory use stays flat for large result sets.
+    ///
+    /// The iterator borrows the connection, and the underlying enumerator
+    /// is released when the iterator is dropped (for example, when breaking out of a loop).
+    ///
+    /// ```edition2018
+    /// # use wmi::*;
+    /// # use std::collections::HashMap;
+    /// # let con = WMIConnection::new(COMLibrary::new().unwrap().into()).unwrap();
+    /// for process in con.raw_query_iter::<HashMap<String, Variant>>("SELECT Name FROM Win32_Process").unwrap() {
+    ///     let process = process.unwrap();
+    /// }
+    /// #
+    ///
+    pub fn raw_query_iter<'a, T>(
+        &'a self,
+        query: impl AsRef<str>,
+    ) -> Result<impl Iterator<Item = Result<T, Error>> + 'a, Error>
+    where
+        T: de::DeserializeOwned + 'a,
+    {
+        self.raw_query_iter_with_options(query, &QueryOptions::default())
+    }
+
+    /// Like `raw_query_iter`, but using the given options.
+    ///
+    pub fn raw_query_iter_with_options<'a, T>(
+        &'a self,
+        query: impl AsRef<str>,
+        options: &QueryOptions,
+    ) -> Result<impl Iterator<Item = Result<T, Error>> + 'a, Error>
+    where
+        T: de::DeserializeOwned + 'a,
+    {
+        let query = query.as_ref().to_owned();
+
+        let enumerator = self
+            .exec_query_native_wrapper_with_options(&query, options)
+            .map_err(|e| self.query_error(&query, e))?;
+
+        Ok(self.deserialize_results(query, enumerator, options))
+    }
+
+    /// Lazily deserialize the results of `enumerator` (which executed `query`), using the given options.
+    fn deserialize_results<'a, T>(
+        &'a self,
+        query: String,
+        enumerator: QueryResultEnumerator<'a>,
+        options: &QueryOptions,
+    ) -> impl Iterator<Item = Result<T, Error>> + 'a
+    where
+        T: de::DeserializeOwned + 'a,
+    {
+        let trim_strings = options.trim_strings;
+        let on_error = options.on_error;
+        let property_names = RefCell::new(
+            PropertyNamesCache::new().with_system_properties(selected_system_properties(&query)),
+        );
+
+        enumerator.map(move |item| match item {
+            Ok(wbem_class_obj) => {
+                let mut deserializer = Deserializer::from_wbem_class_obj(&wbem_class_obj)
+                    .trim_strings(trim_strings)
+                    .property_names_cache(&property_names)
+                    .on_error(on_error);
+
+                let value = deserializer.deserialize_with_policy();
+
+                value.map_err(Error::from)
+            }
+            Err(e) => Err(self.query_error(&query, e)),
+        })
+    }
+
+    /// Execute a free-text query, returning the results as a JSON array of objects
+    /// (which requires the `serde_json` feature).
+    ///
+    /// Every property is converted using its `Variant` type: strings (including `uint64`s
+    /// and datetimes) become JSON strings, numbers and booleans keep their types,
+    /// nulls become `null` and arrays become JSON arrays.
+    ///
+    /// ```edition2018
+    /// # use wmi::*;
+    /// # let con = WMIConnection::new(COMLibrary::new().unwrap().into()).unwrap();
+    /// # #[cfg(feature = "serde_json")]
+    /// let json = con.exec_query_to_json("SELECT Name, ProcessId FROM Win32_Process").unwrap();
+    /// #
+    ///
+    #[cfg(feature = "serde_json")]
+    pub fn exec_query_to_json(&self, wql: &str) -> Result<serde_json::Value, Error> {
+        let rows: Vec<serde_json::Value> = self.raw_query(wql)?;
+
+        Ok(serde_json::Value::Array(rows))
+    }
+
+    /// Execute a free-text query, writing each result to `out` as a JSON object on its own line
+    /// ([JSON Lines]), which requires the `serde_json` feature.
+    ///
+    /// The results are written while enumerating (like `raw_query_iter`), so the entire result set
+    /// is never held in memory. Properties are converted like in `exec_query_to_json`.
+    ///
+    /// Returns the number of written lines.
+    ///
+    /// ```edition2018
+    /// # use wmi::*;
+    /// # let con = WMIConnection::new(COMLibrary::new().unwrap().into()).unwrap();
+    /// # #[cfg(feature = "serde_json")]
+    /// con.query_to_jsonl("SELECT Name, ProcessId FROM Win32_Process", std::io::stdout().lock()).unwrap();
+    /// #
+    ///
+    /// [JSON Lines]: https://jsonlines.org/
+    ///
+    #[cfg(feature = "serde_json")]
+    pub fn query_to_jsonl<W: std::io::Write>(&self, wql: &str, mut out: W) -> Result<usize, Error> {
+        let mut count = 0;
+
+        for row in self.raw_query_iter::<serde_json::Value>(wql)? {
+            serde_json::to_writer(&mut out, &row?)?;
+            out.write_all(b"\n")?;
+
+            count += 1;
+        }
+
+        out.flush()?;
+
+        Ok(count)
+    }
+
+    /// Execute a free-text query, returning the value of every property
+    /// together with its declared CIM type (for example, `uint64` properties are returned as
+    /// a `Variant::String` with a `CimType::UInt64` type).
+    ///
+    /// ```edition2018
+    /// # use wmi::*;
+    /// # let con = WMIConnection::new(COMLibrary::new().unwrap().into()).unwrap();
+    /// let results = con.raw_query_with_types("SELECT TotalPhysicalMemory FROM Win32_ComputerSystem").unwrap();
+    /// #
+    ///
+    pub fn raw_query_with_types(
+        &self,
+        query: impl AsRef<str>,
+    ) -> Result<Vec<TypedProperties>, Error> {
+        let query = query.as_ref();
+
+        let enumerator = self
+            .exec_query_native_wrapper(query)
+            .map_err(|e| self.query_error(query, e))?;
+
+        let mut property_names = PropertyNamesCache::new();
+
+        enumerator
+            .map(|item| {
+                let wbem_class_obj = item.map_err(|e| self.query_error(query, e))?;
+
+                property_names
+                    .list_properties(&wbem_class_obj)?
+                    .iter()
+                    .map(|name| {
+                        let value_and_type = wbem_class_obj.get_property_with_type(name)?;
+
+                        Ok((name.clone(), value_and_type))
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    /// Add the query and the namespace to an error which occurred while executing the query.
+    ///
+    /// WMI errors become a [`WMIError::Query`](crate::utils::WMIError::Query), so they can still
+    /// be downcast to a `WMIError` (with the original error as its `source`).
+    ///
+    fn query_error(&self, query: &str, err: Error) -> Error {
+        match err.downcast::<WMIError>() {
+            Ok(source) => WMIError::Query {
+                query: query.to_owned(),
+                namespace: self.namespace().to_owned(),
+                source: Box::new(source),
+            }
+            .into(),
+            // Errors which didn't come from WMI (like a query with an embedded null).
+            Err(err) => {
+                let context = format!(
+                    "query {:?} in namespace {} failed: {}",
+                    query,
+                    self.namespace(),
+                    err
+                );
+
+                err.context(context).into()
+            }
+        }
+    }
+
+    /// Like `raw_query`, but also return how long executing the query and enumerating
+    /// its results took (see [`QueryMetrics`]).
+    ///
+    /// ```edition2018
+    /// # use wmi::*;
+    /// # use std::collections::HashMap;
+    /// # let con = WMIConnection::new(COMLibrary::new().unwrap().into()).unwrap();
+    /// let (processes, metrics) = con
+    ///     .query_with_metrics::<HashMap<String, Variant>>("SELECT Name FROM Win32_Process")
+    ///     .unwrap();
+    ///
+    /// println!("{} processes in {:?}", metrics.object_count, metrics.enumeration_duration);
+    /// #
+    ///
+    pub fn query_with_metrics<T>(
+        &self,
+        query: impl AsRef<str>,
+    ) -> Result<(Vec<T>, QueryMetrics), Error>
+    where
+        T: de::DeserializeOwned,
+    {
+        let started_at = Instant::now();
+
+        let results = self.raw_query_iter(query)?;
+
+        let exec_duration = started_at.elapsed();
+
+        let results: Vec<T> = results.collect::<Result<_, _>>()?;
+
+        let metrics = QueryMetrics {
+            exec_duration,
+            enumeration_duration: started_at.elapsed() - exec_duration,
+            object_count: results.len(),
+        };
+
+        Ok((results, metrics))
+    }
+
+    /// Like `raw_query`, but objects which fail to deserialize are skipped instead of failing
+    /// the entire query. The deserialization errors are returned alongside the successful results.
+    ///
+    /// Errors from the query itself (like an invalid query) still fail the entire call.
+    ///
+    pub fn raw_query_lenient<T>(
+        &self,
+        query: impl AsRef<str>,
+    ) -> Result<(Vec<T>, Vec<Error>), Error>
+    where
+        T: de::DeserializeOwned,
+    {
+        let query = query.as_ref();
+
+        let enumerator = self
+            .exec_query_native_wrapper(query)
+            .map_err(|e| self.query_error(query, e))?;
+
+        let mut results = vec![];
+        let mut errors = vec![];
+
+        for item in enumerator {
+            let wbem_class_obj = item.map_err(|e| self.query_error(query, e))?;
+
+            match from_wbem_class_obj(&wbem_class_obj) {
+                Ok(value) => results.push(value),
+                Err(e) => errors.push(Error::from(e)),
+            }
+        }
+
+        Ok((results, errors))
+    }
+
+    /// Query all the objects of type T.
+    ///
+    /// The query selects `FROM` T's class (and not from one of its base classes),
+    /// so a struct named after a subclass (like `Win32_Processor`, a `CIM_LogicalDevice`)
+    /// only gets the objects of that subclass (and of its own subclasses, see `shallow_query`).
+    ///
+    /// ```edition2018
+    /// # use wmi::*;
+    /// # use serde::Deserialize;
+    /// # let con = WMIConnection::new(COMLibrary::new().unwrap().into()).unwrap();
+    /// #[derive(Deserialize)]
+    /// struct Win32_OperatingSystem {
+    ///     Name: String,
+    /// }
+    /// con.query::<Win32_OperatingSystem>();
+    /// #
+    ///
+    pub fn query<T>(&self) -> Result<Vec<T>, Error>
+    where
+        T: de::DeserializeOwned,
+    {
+        let query_text = build_query::<T>(None, None);
+
+        self.raw_query(&query_text)
+    }
+
+    /// Like `query`, but using the given options (for example, to pass a [`WMIContext`]).
+    ///
+    pub fn query_with_options<T>(&self, options: &QueryOptions) -> Result<Vec<T>, Error>
+    where
+        T: de::DeserializeOwned,
+    {
+        let query_text = build_query::<T>(None, None);
+
+        self.raw_query_with_options(&query_text, options)
+    }
+
+    /// Query the first `n` objects of type T.
+    ///
+    /// WQL has no `LIMIT`, so the results are enumerated lazily and the enumerator
+    /// is released after the first `n` objects (without retrieving the rest of them).
+    ///
+    /// ```edition2018
+    /// # use wmi::*;
+    /// # use serde::Deserialize;
+    /// # let con = WMIConnection::new(COMLibrary::new().unwrap().into()).unwrap();
+    /// #[derive(Deserialize)]
+    /// struct Win32_Process {
+    ///     Name: String,
+    /// }
+    /// let processes = con.query_with_limit::<Win32_Process>(5).unwrap();
+    /// #
+    ///
+    pub fn query_with_limit<T>(&self, n: usize) -> Result<Vec<T>, Error>
+    where
+        T: de::DeserializeOwned,
+    {
+        let query_text = build_query::<T>(None, None);
+
+        self.raw_query_iter(&query_text)?.take(n).collect()
+    }
+
+    /// Query all the objects of type T, using the class and property names of its
+    /// [`WMIClass`](crate::WMIClass) implementation (instead of inferring them from `serde`).
+    ///
+    /// ```edition2018
+    /// # use wmi::*;
+    /// # use serde::Deserialize;
+    /// # let con = WMIConnection::new(COMLibrary::new().unwrap().into()).unwrap();
+    /// #[derive(Deserialize)]
+    /// #[serde(rename_all = "PascalCase")]
+    /// struct OperatingSystem {
+    ///     caption: String,
+    /// }
+    ///
+    /// // Usually generated using `#[derive(WMIClass)]` (with the `derive` feature).
+    /// impl WMIClass for OperatingSystem {
+    ///     fn wmi_class_name() -> &'static str {
+    ///         "Win32_OperatingSystem"
+    ///     }
+    ///
+    ///     fn wmi_property_names() -> &'static [&'static str] {
+    ///         &["Caption"]
+    ///     }
+    /// }
+    ///
+    /// let os = con.query_wmi_class::<OperatingSystem>().unwrap();
+    /// #
+    ///
+    pub fn query_wmi_class<T>(&self) -> Result<Vec<T>, Error>
+    where
+        T: WMIClass + de::DeserializeOwned,
+    {
+        let query_text =
+            build_query_with_fields(T::wmi_class_name(), T::wmi_property_names(), None, None);
+
+        self.raw_query(&query_text)
+    }
+
+    /// Query all the objects of type T, skipping objects which fail to deserialize
+    /// (see `raw_query_lenient`).
+    ///
+    /// ```edition2018
+    /// # use wmi::*;
+    /// # use serde::Deserialize;
+    /// # let con = WMIConnection::new(COMLibrary::new().unwrap().into()).unwrap();
+    /// #[derive(Deserialize)]
+    /// struct Win32_Service {
+    ///     Name: String,
+    ///     PathName: String,
+    /// }
+    /// let (services, errors) = con.query_lenient::<Win32_Service>().unwrap();
+    /// #
+    ///
+    pub fn query_lenient<T>(&self) -> Result<(Vec<T>, Vec<Error>), Error>
+    where
+        T: de::DeserializeOwned,
+    {
+        let query_text = build_query::<T>(None, None);
+
+        self.raw_query_lenient(&query_text)
+    }
+
+    /// Query all the objects of type T, while filtering according to `filters`.
+    ///
+    pub fn filtered_query<T>(&self, filters: &HashMap<String, FilterValue>) -> Result<Vec<T>, Error>
+    where
+        T: de::DeserializeOwned,
+    {
+        let query_text = build_query::<T>(Some(filters), None);
+
+        self.raw_query(&query_text)
+    }
+
+    /// Query all the objects of class `class_name`, deserializing them into T
+    /// (instead of inferring the class from T's name).
+    ///
+    /// The selected fields are still T's fields, so the class must have matching properties.
+    ///
+    /// ```edition2018
+    /// # use wmi::*;
+    /// # use serde::Deserialize;
+    /// # let con = WMIConnection::new(COMLibrary::new().unwrap().into()).unwrap();
+    /// #[derive(Deserialize)]
+    /// struct Named {
+    ///     Name: String,
+    /// }
+    /// let disks = con.query_with_class::<Named>("Win32_LogicalDisk").unwrap();
+    /// let processes = con.query_with_class::<Named>("Win32_Process").unwrap();
+    /// #
+    ///
+    pub fn query_with_class<T>(&self, class_name: &str) -> Result<Vec<T>, Error>
+    where
+        T: de::DeserializeOwned,
+    {
+        let query_text = build_query_for_class::<T>(class_name, None, None);
+
+        self.raw_query(&query_text)
+    }
+
+    /// Like `filtered_query`, but querying the objects of class `class_name`
+    /// (see `query_with_class`).
+    ///
+    pub fn filtered_query_with_class<T>(
+        &self,
+        class_name: &str,
+        filters: &HashMap<String, FilterValue>,
+    ) -> Result<Vec<T>, Error>
+    where
+        T: de::DeserializeOwned,
+    {
+        let query_text = build_query_for_class::<T>(class_name, Some(filters), None);
+
+        self.raw_query(&query_text)
+    }
+
+    /// Query only the objects which are direct instances of T's class.
+    ///
+    /// By default, WMI queries are "deep": querying a class (like `CIM_LogicalDisk`) also returns
+    /// the instances of all of its subclasses (like `Win32_LogicalDisk`).
+    /// A shallow query adds a `WHERE __CLASS = ...` condition, so instances of subclasses are excluded.
+    ///
+    /// ```edition2018
+    /// # use wmi::*;
+    /// # use serde::Deserialize;
+    /// # let con = WMIConnection::new(COMLibrary::new().unwrap().into()).unwrap();
+    /// #[derive(Deserialize)]
+    /// struct CIM_LogicalDisk {
+    ///     Name: String,
+    /// }
+    /// // All the instances are actually `Win32_LogicalDisk`s, so this will be empty.
+    /// let disks = con.shallow_query::<CIM_LogicalDisk>().unwrap();
+    /// #
+    ///
+    pub fn shallow_query<T>(&self) -> Result<Vec<T>, Error>
+    where
+        T: de::DeserializeOwned,
+    {
+        let (name, _) = struct_name_and_fields::<T>();
+
+        let mut filters = HashMap::new();
+
+        filters.insert("__CLASS".to_owned(), FilterValue::Str(name));
+
+        self.filtered_query(&filters)
+    }
+
+    /// Query all the objects of type T, while filtering according to `filters`
+    /// and sorting by `field`.
+    ///
+    /// Note that most WMI providers do not support `ORDER BY`,
+    /// in which case a [`WMIError::OrderByNotSupported`] error is returned
+    /// (instead of silently ignoring the ordering).
+    ///
+    pub fn ordered_query<T>(
+        &self,
+        filters: Option<&HashMap<String, FilterValue>>,
+        field: &str,
+        order: SortOrder,
+    ) -> Result<Vec<T>, Error>
+    where
+        T: de::DeserializeOwned,
+    {
+        let query_text = build_query::<T>(filters, Some((field, &order)));
+
+        self.raw_query(&query_text)
+            .map_err(|e| match e.downcast::<WMIError>() {
+                Ok(source) => match source.without_context() {
+                    WMIError::HResultError { hres } if *hres == WBEM_E_INVALID_QUERY as HRESULT => {
+                        WMIError::OrderByNotSupported {
+                            source: Box::new(source),
+                        }
+                        .into()
+                    }
+                    _ => source.into(),
+                },
+                Err(e) => e,
+            })
+    }
+
+    /// Query the objects of type T which are associated with the object at `object_path`,
+    /// using an `ASSOCIATORS OF` query whose `ResultClass` is inferred from T's name.
+    ///
+    /// ```edition2018
+    /// # use wmi::*;
+    /// # use serde::Deserialize;
+    /// # let con = WMIConnection::new(COMLibrary::new().unwrap().into()).unwrap();
+    /// #[derive(Deserialize)]
+    /// struct Win32_DiskPartition {
+    ///     Name: String,
+    /// }
+    /// let partitions = con.associators_of::<Win32_DiskPartition>(r#"\\.\root\cimv2:Win32_DiskDrive.DeviceID="\\\\.\\PHYSICALDRIVE0""#);
+    /// #
+    ///
+    pub fn associators_of<T>(&self, object_path: &str) -> Result<Vec<T>, Error>
+    where
+        T: de::DeserializeOwned,
+    {
+        let (name, _) = struct_name_and_fields::<T>();
+
+        let query_text = build_associators_query(object_path, name);
+
+        self.raw_query(&query_text)
+    }
+
+    /// Return the object paths (`__PATH`) of the objects of class `class_name`
+    /// which match the (optional) WQL `filter`, without retrieving any of their other properties.
+    ///
+    /// This is much cheaper than a full query when only the identifiers are needed
+    /// (for example, to call a method on each of the objects).
+    ///
+    /// ```edition2018
+    /// # use wmi::*;
+    /// # let con = WMIConnection::new(COMLibrary::new().unwrap().into()).unwrap();
+    /// let paths = con.query_raw_paths("Win32_Service", Some("State = 'Running'")).unwrap();
+    /// #
+    ///
+    pub fn query_raw_paths(
+        &self,
+        class_name: &str,
+        filter: Option<&str>,
+    ) -> Result<Vec<String>, Error> {
+        let query_text = build_paths_query(class_name, filter);
+
+        let options = QueryOptions {
+            ensure_locatable: true,
+            ..Default::default()
+        };
+
+        let enumerator = self
+            .exec_query_native_wrapper_with_options(&query_text, &options)
+            .map_err(|e| self.query_error(&query_text, e))?;
+
+        enumerator
+            .map(|item| {
+                let wbem_class_obj = item.map_err(|e| self.query_error(&query_text, e))?;
+
+                match wbem_class_obj.get_property("__PATH")? {
+                    Variant::String(path) => Ok(path),
+                    other => Err(format_err!(
+                        "Expected __PATH to be a string, got {:?}",
+                        other
+                    )),
+                }
+            })
+            .collect()
+    }
+
+    /// Query a single object of type T (useful for classes like `Win32_OperatingSystem`,
+    /// which have exactly one instance).
+    /// If non are found, a [`WMIError::NoResults`](crate::utils::WMIError::NoResults) is returned.
+    /// If more than one object is found, all but the first are ignored.
+    ///
+    /// ```edition2018
+    /// # use wmi::*;
+    /// # use serde::Deserialize;
+    /// # let con = WMIConnection::new(COMLibrary::new().unwrap().into()).unwrap();
+    /// #[derive(Deserialize)]
+    /// struct Win32_OperatingSystem {
+    ///     Name: String,
+    /// }
+    /// let os = con.query_one::<Win32_OperatingSystem>().unwrap();
+    /// #
+    ///
+    pub fn query_one<T>(&self) -> Result<T, Error>
+    where
+        T: de::DeserializeOwned,
+    {
+        self.query_opt()?
+            .ok_or_else(|| Error::from(WMIError::NoResults))
+    }
+
+    /// Query a single object of type T, returning `None` if non are found.
+    /// If more than one object is found, all but the first are ignored.
+    ///
+    pub fn query_opt<T>(&self) -> Result<Option<T>, Error>
+    where
+        T: de::DeserializeOwned,
+    {
+        let query_text = build_query::<T>(None, None);
+
+        let mut enumerator = self
+            .exec_query_native_wrapper(&query_text)
+            .map_err(|e| self.query_error(&query_text, e))?;
+
+        match enumerator.next() {
+            None => Ok(None),
+            Some(item) => {
+                let wbem_class_obj = item.map_err(|e| self.query_error(&query_text, e))?;
+
+                Ok(Some(from_wbem_class_obj(&wbem_class_obj)?))
+            }
+        }
+    }
+
+    /// Get a single object of type T (same as `query_one`).
+    /// If non are found, an error is returned.
+    /// If more than one object is found, all but the first are ignored.
+    ///
+    /// ```edition2018
+    /// # use wmi::*;
+    /// # use serde::Deserialize;
+    /// # let con = WMIConnection::new(COMLibrary::new().unwrap().into()).unwrap();
+    /// #[derive(Deserialize)]
+    /// struct Win32_OperatingSystem {
+    ///     Name: String,
+    /// }
+    /// let os = con.get::<Win32_OperatingSystem>();
+    /// #
+    ///
+    pub fn get<T>(&self) -> Result<T, Error>
+    where
+        T: de::DeserializeOwned,
+    {
+        self.query_one()
+    }
+}
+
+#[allow(non_snake_case)]
+#[allow(non_camel_case_types)]
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::Deserialize;
+    use std::collections::{BTreeMap, HashMap};
+
+    use crate::tests::fixtures::*;
+    use crate::tests::{make_object, MockEnumerator, ServicesSpy};
+    use crate::COMLibrary;
+    use winapi::shared::winerror::S_OK;
+    use winapi::um::wbemcli::WBEM_E_INVALID_CLASS;
+
+    #[test]
+    fn it_works() {
+        let wmi_con = wmi_con();
+
+        let enumerator = wmi_con
+            .exec_query_native_wrapper("SELECT * FROM Win32_OperatingSystem")
+            .unwrap();
+
+        for res in enumerator {
+            let w = res.unwrap();
+            let mut props = w.list_properties().unwrap();
+
+            props.sort();
+
+            assert_eq!(props.len(), 64);
+            assert_eq!(props[..2], ["BootDevice", "BuildNumber"]);
+            assert_eq!(props[props.len() - 2..], ["Version", "WindowsDirectory"])
+        }
+    }
+
+    #[test]
+    fn it_fails_gracefully() {
+        let wmi_con = wmi_con();
+
+        let enumerator = wmi_con
+            .exec_query_native_wrapper("SELECT NoSuchField FROM Win32_OperatingSystem")
+            .unwrap();
+
+        for res in enumerator {
+            dbg!(&res);
+            assert!(res.is_err())
+        }
+    }
+
+    #[test]
+    fn it_fails_gracefully_with_invalid_sql() {
+        let wmi_con = wmi_con();
+
+        let enumerator = wmi_con.exec_query_native_wrapper("42").unwrap();
+
+        // Show how to detect which error had occurred.
+        for res in enumerator {
+            match res {
+                Ok(_) => assert!(false),
+                Err(e) => {
+                    let cause = e.as_fail();
+
+                    if let Some(wmi_err) = cause.downcast_ref::<WMIError>() {
+                        match wmi_err {
+                            WMIError::HResultError { hres } => {
+                                assert_eq!(*hres, WBEM_E_INVALID_QUERY as HRESULT);
+                            }
+                            _ => assert!(false),
+                        }
+                    } else {
+                        assert!(false);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn it_fails_early_with_invalid_class_in_sync_mode() {
+        let wmi_con = wmi_con();
+
+        let res = wmi_con.exec_query_sync_native_wrapper("SELECT * FROM NoSuchClass");
+
+        match res {
+            Ok(_) => assert!(false),
+            Err(e) => match e.as_fail().downcast_ref::<WMIError>() {
+                Some(WMIError::HResultError { hres }) => {
+                    assert_eq!(*hres, WBEM_E_INVALID_CLASS as HRESULT);
+                }
+                _ => assert!(false),
+            },
+        }
+    }
+
+    #[test]
+    fn it_fails_with_a_null_enumerator() {
+        let wmi_con = wmi_con();
+
+        // A provider which returns a success code from `ExecQuery`, but no enumerator.
+        let res = wmi_con.wrap_exec_query_result(S_OK, ptr::null_mut(), WBEM_FLAG_FORWARD_ONLY);
+
+        match res.err().unwrap().downcast_ref::<WMIError>() {
+            Some(WMIError::NullEnumerator) => {}
+            _ => assert!(false),
+        }
+
+        // A query without results still returns an (empty) enumerator.
+        let enumerator = wmi_con
+            .exec_query_native_wrapper("SELECT * FROM Win32_Process WHERE ProcessId = 1")
+            .unwrap();
+
+        assert_eq!(enumerator.count(), 0);
+    }
+
+    #[test]
+    fn it_queries_with_limit() {
+        let wmi_con = wmi_con();
+
+        #[derive(Deserialize, Debug)]
+        struct Win32_Process {
+            Name: String,
+        }
+
+        let all: Vec<Win32_Process> = wmi_con.query().unwrap();
+        assert!(all.len() > 3);
+
+        let limited: Vec<Win32_Process> = wmi_con.query_with_limit(3).unwrap();
+        assert_eq!(limited.len(), 3);
+
+        let none: Vec<Win32_Process> = wmi_con.query_with_limit(0).unwrap();
+        assert!(none.is_empty());
+    }
+
+    #[test]
+    fn it_validates_queries() {
+        let wmi_con = wmi_con();
+
+        wmi_con
+            .validate_query("SELECT Name FROM Win32_Process")
+            .unwrap();
+
+        let hres = |query| {
+            let err = wmi_con.validate_query(query).unwrap_err();
+
+            match err.as_fail().downcast_ref::<WMIError>() {
+                Some(WMIError::Query {
+                    query: err_query,
+                    source,
+                    ..
+                }) => {
+                    assert_eq!(err_query, query);
+
+                    match **source {
+                        WMIError::HResultError { hres } => hres,
+                        _ => panic!("Unexpected error {:?}", err),
+                    }
+                }
+                _ => panic!("Unexpected error {:?}", err),
+            }
+        };
+
+        assert_eq!(
+            hres("SELECT * FROM NoSuchClass"),
+            WBEM_E_INVALID_CLASS as HRESULT
+        );
+        assert_eq!(hres("SELECT * FROM"), WBEM_E_INVALID_QUERY as HRESULT);
+    }
+
+    #[test]
+    fn it_can_get_an_object_by_path() {
+        let wmi_con = wmi_con();
+
+        let disk = wmi_con
+            .get_object(r#"Win32_LogicalDisk.DeviceID="C:""#)
+            .unwrap();
+
+        assert_eq!(
+            disk.get_property("DeviceID").unwrap(),
+            Variant::String("C:".into())
+        );
+    }
+
+    #[test]
+    fn it_fails_with_invalid_object_path() {
+        let wmi_con = wmi_con();
+
+        let path = r#"Win32_LogicalDisk.DeviceID="C:"#;
+
+        let err = wmi_con.get_object(path).unwrap_err();
+
+        match err.as_fail().downcast_ref::<WMIError>() {
+            Some(WMIError::InvalidObjectPath { path: err_path }) => assert_eq!(err_path, path),
+            _ => assert!(false),
+        }
+
+        assert_eq!(
+            format!("{}", err),
+            r#"Invalid object path: "Win32_LogicalDisk.DeviceID=\"C:""#
+        );
+    }
+
+    #[test]
+    fn it_can_get_several_objects() {
+        let wmi_con = wmi_con();
+
+        let mut paths = wmi_con
+            .query_raw_paths("Win32_Service", Some("Name = 'Winmgmt' OR Name = 'lmhosts'"))
+            .unwrap();
+
+        paths.push(r#"Win32_LogicalDisk.DeviceID="C:""#.to_owned());
+        paths.push("Win32_OperatingSystem=@".to_owned());
+        paths.push(r#"Win32_LogicalDisk.DeviceID="C:"#.to_owned());
+
+        let objects = wmi_con.get_objects(&paths);
+
+        assert_eq!(objects.len(), 5);
+
+        for object in &objects[..4] {
+            assert!(object.as_ref().unwrap().inner.is_some());
+        }
+
+        assert_eq!(
+            objects[2].as_ref().unwrap().get_property("DeviceID").unwrap(),
+            Variant::String("C:".into())
+        );
+
+        match objects[4].as_ref().unwrap_err().as_fail().downcast_ref::<WMIError>() {
+            Some(WMIError::InvalidObjectPath { .. }) => {}
+            _ => assert!(false),
+        }
+    }
+
+    #[test]
+    fn it_can_get_several_objects_with_proxy_blanket_per_call() {
+        let wmi_con = wmi_con().with_proxy_blanket_per_call(true);
+
+        let paths = vec![
+            "Win32_OperatingSystem=@".to_owned(),
+            r#"Win32_LogicalDisk.DeviceID="C:""#.to_owned(),
+        ];
+
+        for object in wmi_con.get_objects(&paths) {
+            assert!(object.unwrap().inner.is_some());
+        }
+    }
+
+    #[test]
+    fn it_returns_path_of_projected_query_with_ensure_locatable() {
+        let wmi_con = wmi_con();
+
+        let options = QueryOptions {
+            ensure_locatable: true,
+            ..Default::default()
+        };
+
+        let enumerator = wmi_con
+            .exec_query_native_wrapper_with_options("SELECT Name FROM Win32_Process", &options)
+            .unwrap();
+
+        for res in enumerator {
+            let w = res.unwrap();
+
+            match w.get_property("__PATH").unwrap() {
+                Variant::String(path) => assert!(path.contains("Win32_Process.Handle=")),
+                _ => assert!(false),
+            }
+        }
+    }
+
+    #[test]
+    fn it_gets_a_class_prototype() {
+        let wmi_con = wmi_con();
+
+        let class = wmi_con.get_class("Win32_OperatingSystem").unwrap();
+
+        let properties = class.list_properties().unwrap();
+
+        assert!(properties.iter().any(|name| name == "Caption"));
+        assert!(properties.iter().any(|name| name == "LastBootUpTime"));
+
+        let (value, cim_type) = class.get_property_with_type("LastBootUpTime").unwrap();
+
+        assert_eq!(value, Variant::Null);
+        assert_eq!(cim_type, CimType::DateTime);
+
+        assert!(wmi_con.get_class("Win32_NoSuchClass").is_err());
+    }
+
+    #[test]
+    fn it_includes_query_and_namespace_in_errors() {
+        let wmi_con = wmi_con();
+
+        let res: Result<Vec<HashMap<String, Variant>>, _> =
+            wmi_con.raw_query("SELECT * FROM NoSuchClass");
+
+        let err = res.unwrap_err();
+
+        assert_eq!(
+            format!("{}", err),
+            r#"query "SELECT * FROM NoSuchClass" in namespace ROOT\CIMV2 failed: HRESULT Call failed with: 0x80041010"#
+        );
+
+        match err.downcast_ref::<WMIError>() {
+            Some(WMIError::Query {
+                query,
+                namespace,
+                source,
+            }) => {
+                assert_eq!(query, "SELECT * FROM NoSuchClass");
+                assert_eq!(namespace, "ROOT\\CIMV2");
+
+                match **source {
+                    WMIError::HResultError { hres } => {
+                        assert_eq!(hres, WBEM_E_INVALID_CLASS as HRESULT)
+                    }
+                    _ => assert!(false),
+                }
+            }
+            _ => assert!(false),
+        }
+
+        assert!(err.downcast_ref::<WMIError>().unwrap().is_not_found());
+    }
+
+    #[test]
+    fn it_can_query_a_struct() {
+        let wmi_con = wmi_con();
+
+        #[derive(Deserialize, Debug)]
+        struct Win32_OperatingSystem {
+            Caption: String,
+        }
+
+        let results = wmi_con.query::<Win32_OperatingSystem>().unwrap();
+
+        for os in results {
+            assert_eq!(os.Caption, "Microsoft Windows 10 Pro");
+        }
+    }
+
+    #[test]
+    fn it_fails_gracefully_when_querying_a_struct() {
+        let wmi_con = wmi_con();
+
+        #[derive(Deserialize, Debug)]
         struct Win32_OperatingSystem {
             NoSuchField: String,
         }
 
-        let result = wmi_con.query::<Win32_OperatingSystem>();
+        let result = wmi_con.query::<Win32_OperatingSystem>();
+
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn it_skips_objects_which_fail_to_deserialize_in_lenient_mode() {
+        let wmi_con = wmi_con();
+
+        // Some services (like `LSM`) have a `null` PathName, which can't be deserialized into a `String`.
+        #[derive(Deserialize, Debug)]
+        struct Win32_Service {
+            Name: String,
+            PathName: String,
+        }
+
+        let (services, errors) = wmi_con.query_lenient::<Win32_Service>().unwrap();
+
+        assert!(services.iter().any(|service| service.Name == "lmhosts"));
+        assert!(services.iter().all(|service| service.Name != "LSM"));
+
+        assert!(errors.len() >= 1);
+        assert_eq!(
+            format!("{}", errors[0]),
+            "Failed to deserialize property PathName: invalid type: Option value, expected a string"
+        );
+    }
+
+    #[test]
+    fn it_finds_selected_system_properties() {
+        assert_eq!(
+            selected_system_properties("SELECT __PATH, Name FROM Win32_Service"),
+            ["__PATH"]
+        );
+        assert_eq!(
+            selected_system_properties("select Name,__CLASS , __RELPATH from Win32_Service"),
+            ["__CLASS", "__RELPATH"]
+        );
+        assert!(selected_system_properties("SELECT * FROM Win32_Service").is_empty());
+        assert!(selected_system_properties("ASSOCIATORS OF {Win32_Service.Name='x'}").is_empty());
+    }
+
+    #[test]
+    fn it_desr_selected_system_properties() {
+        let wmi_con = wmi_con();
+
+        #[derive(Deserialize, Debug)]
+        struct Win32_Service {
+            #[serde(rename = "__PATH")]
+            path: String,
+            Name: String,
+        }
+
+        let services: Vec<Win32_Service> = wmi_con
+            .raw_query("SELECT __PATH, Name FROM Win32_Service WHERE Name = 'Winmgmt'")
+            .unwrap();
+
+        assert_eq!(services[0].Name, "Winmgmt");
+        assert!(services[0].path.ends_with(r#"Win32_Service.Name="Winmgmt""#));
+
+        let services: Vec<HashMap<String, Variant>> = wmi_con
+            .raw_query("SELECT __PATH, Name FROM Win32_Service WHERE Name = 'Winmgmt'")
+            .unwrap();
+
+        assert!(matches!(services[0]["__PATH"], Variant::String(_)));
+        assert_eq!(services[0]["Name"], Variant::String("Winmgmt".into()));
+    }
+
+    #[test]
+    fn it_returns_query_metrics() {
+        let wmi_con = wmi_con();
+
+        let started_at = Instant::now();
+
+        let (processes, metrics) = wmi_con
+            .query_with_metrics::<HashMap<String, Variant>>("SELECT Name FROM Win32_Process")
+            .unwrap();
+
+        let elapsed = started_at.elapsed();
 
-        assert!(result.is_err());
+        assert!(!processes.is_empty());
+        assert_eq!(metrics.object_count, processes.len());
+        assert!(metrics.enumeration_duration > Duration::from_secs(0));
+        assert!(metrics.exec_duration + metrics.enumeration_duration <= elapsed);
     }
 
     #[test]
@@ -323,7 +1728,7 @@ mod tests {
             Caption: String,
         }
 
-        let query = build_query::<Win32_OperatingSystem>(None);
+        let query = build_query::<Win32_OperatingSystem>(None, None);
         let select_part = r#"SELECT Caption FROM Win32_OperatingSystem "#.to_owned();
 
         assert_eq!(query, select_part);
@@ -343,13 +1748,93 @@ mod tests {
         filters.insert("C3".to_string(), FilterValue::Number(42));
         filters.insert("C4".to_string(), FilterValue::Bool(false));
 
-        let query = build_query::<Win32_OperatingSystem>(Some(&filters));
+        let query = build_query::<Win32_OperatingSystem>(Some(&filters), None);
         let select_part = r#"SELECT Caption FROM Win32_OperatingSystem "#.to_owned();
         let where_part = r#"WHERE C1 = "a" AND C2 = "b" AND C3 = 42 AND C4 = false"#;
 
         assert_eq!(query, select_part + where_part);
     }
 
+    #[test]
+    fn it_builds_correct_query_with_order_by() {
+        #[derive(Deserialize, Debug)]
+        struct Win32_Process {
+            Name: String,
+        }
+
+        let query = build_query::<Win32_Process>(None, Some(("Name", &SortOrder::Descending)));
+
+        assert_eq!(query, "SELECT Name FROM Win32_Process ORDER BY Name DESC");
+
+        let mut filters = HashMap::new();
+
+        filters.insert("Name".to_string(), FilterValue::Str("cargo.exe"));
+
+        let query = build_query::<Win32_Process>(
+            Some(&filters),
+            Some(("ProcessId", &SortOrder::Ascending)),
+        );
+
+        assert_eq!(
+            query,
+            r#"SELECT Name FROM Win32_Process WHERE Name = "cargo.exe" ORDER BY ProcessId ASC"#
+        );
+    }
+
+    #[test]
+    fn it_keeps_the_wmi_error_when_order_by_is_not_supported() {
+        let wmi_con = wmi_con();
+
+        #[derive(Deserialize, Debug)]
+        struct Win32_Process {
+            Name: String,
+        }
+
+        let err = wmi_con
+            .ordered_query::<Win32_Process>(None, "Name", SortOrder::Ascending)
+            .unwrap_err();
+
+        let wmi_err = err.downcast_ref::<WMIError>().unwrap();
+
+        match wmi_err {
+            WMIError::OrderByNotSupported { .. } => {}
+            _ => assert!(false),
+        }
+
+        match wmi_err.without_context() {
+            WMIError::HResultError { hres } => assert_eq!(*hres, WBEM_E_INVALID_QUERY as HRESULT),
+            _ => assert!(false),
+        }
+
+        assert!(err.to_string().contains("ORDER BY"));
+    }
+
+    #[test]
+    fn it_excludes_subclass_instances_in_shallow_query() {
+        let wmi_con = wmi_con();
+
+        #[derive(Deserialize, Debug)]
+        struct CIM_LogicalDisk {
+            Name: String,
+        }
+
+        #[derive(Deserialize, Debug)]
+        struct Win32_LogicalDisk {
+            Name: String,
+        }
+
+        // All logical disks are instances of the `Win32_LogicalDisk` subclass.
+        let deep_results = wmi_con.query::<CIM_LogicalDisk>().unwrap();
+        let shallow_results = wmi_con.shallow_query::<CIM_LogicalDisk>().unwrap();
+
+        assert!(deep_results.len() >= 1);
+        assert_eq!(shallow_results.len(), 0);
+
+        let shallow_results = wmi_con.shallow_query::<Win32_LogicalDisk>().unwrap();
+
+        assert_eq!(shallow_results.len(), deep_results.len());
+    }
+
     #[test]
     fn it_can_filter() {
         let wmi_con = wmi_con();
@@ -446,6 +1931,75 @@ mod tests {
         }
     }
 
+    #[test]
+    fn it_can_query_into_btree_map() {
+        let wmi_con = wmi_con();
+
+        let results: Vec<BTreeMap<String, Variant>> = wmi_con
+            .raw_query("SELECT * FROM Win32_OperatingSystem")
+            .unwrap();
+
+        for res in results {
+            let keys: Vec<&String> = res.keys().collect();
+
+            let mut sorted_keys = keys.clone();
+            sorted_keys.sort();
+
+            assert_eq!(keys, sorted_keys);
+            assert_eq!(keys[0], "BootDevice");
+            assert_eq!(
+                *res.get("Caption").unwrap(),
+                Variant::String("Microsoft Windows 10 Pro".into())
+            );
+        }
+    }
+
+    #[test]
+    fn it_builds_correct_associators_query() {
+        let query = build_associators_query(
+            r#"Win32_DiskDrive.DeviceID="\\\\.\\PHYSICALDRIVE0""#,
+            "Win32_DiskPartition",
+        );
+
+        assert_eq!(
+            query,
+            r#"ASSOCIATORS OF {Win32_DiskDrive.DeviceID="\\\\.\\PHYSICALDRIVE0"} WHERE ResultClass = Win32_DiskPartition"#
+        );
+    }
+
+    #[test]
+    fn it_can_query_associators_into_a_struct() {
+        let wmi_con = wmi_con();
+
+        #[derive(Deserialize, Debug)]
+        struct Win32_DiskPartition {
+            Name: String,
+        }
+
+        let enumerator = wmi_con
+            .exec_query_native_wrapper("SELECT * FROM Win32_DiskDrive")
+            .unwrap();
+
+        for res in enumerator {
+            let disk_drive = res.unwrap();
+
+            let path = match disk_drive.get_property("__PATH").unwrap() {
+                Variant::String(path) => path,
+                _ => panic!("Expected __PATH to be a string"),
+            };
+
+            let partitions = wmi_con
+                .associators_of::<Win32_DiskPartition>(&path)
+                .unwrap();
+
+            assert!(partitions.len() >= 1);
+
+            for partition in partitions {
+                assert!(partition.Name.starts_with("Disk #"));
+            }
+        }
+    }
+
     #[test]
     fn con_get_return_a_single_object() {
         let wmi_con = wmi_con();
@@ -460,4 +2014,377 @@ mod tests {
         assert_ne!(proc.Name, "");
     }
 
+    #[test]
+    fn it_can_query_one_object() {
+        let wmi_con = wmi_con();
+
+        #[derive(Deserialize, Debug)]
+        struct Win32_OperatingSystem {
+            Caption: String,
+        }
+
+        let os = wmi_con.query_one::<Win32_OperatingSystem>().unwrap();
+        assert_eq!(os.Caption, "Microsoft Windows 10 Pro");
+
+        let os = wmi_con.query_opt::<Win32_OperatingSystem>().unwrap();
+        assert_eq!(os.unwrap().Caption, "Microsoft Windows 10 Pro");
+    }
+
+    #[test]
+    fn it_fails_to_query_one_object_without_results() {
+        let wmi_con = wmi_con();
+
+        // There are no tape drives on the test machine.
+        #[derive(Deserialize, Debug)]
+        struct Win32_TapeDrive {
+            Name: String,
+        }
+
+        let err = wmi_con.query_one::<Win32_TapeDrive>().unwrap_err();
+
+        match err.as_fail().downcast_ref::<WMIError>() {
+            Some(WMIError::NoResults) => {}
+            _ => assert!(false),
+        }
+
+        let res = wmi_con.query_opt::<Win32_TapeDrive>().unwrap();
+        assert!(res.is_none());
+    }
+
+    #[test]
+    fn it_builds_correct_paths_query() {
+        assert_eq!(
+            build_paths_query("Win32_Service", None),
+            "SELECT __PATH FROM Win32_Service"
+        );
+        assert_eq!(
+            build_paths_query("Win32_Service", Some("Name = 'Winmgmt'")),
+            "SELECT __PATH FROM Win32_Service WHERE Name = 'Winmgmt'"
+        );
+    }
+
+    #[test]
+    fn it_can_query_raw_paths() {
+        let wmi_con = wmi_con();
+
+        let paths = wmi_con.query_raw_paths("Win32_Service", None).unwrap();
+
+        assert!(paths.len() > 1);
+
+        for path in &paths {
+            assert!(path.contains("Win32_Service.Name="), "{}", path);
+        }
+
+        let paths = wmi_con
+            .query_raw_paths("Win32_Service", Some("Name = 'Winmgmt'"))
+            .unwrap();
+
+        assert_eq!(paths.len(), 1);
+        assert!(paths[0].ends_with(r#"root\cimv2:Win32_Service.Name="Winmgmt""#));
+    }
+
+    #[test]
+    fn it_omits_forward_only_flag_for_bidirectional_queries() {
+        let options = QueryOptions::default();
+        assert_eq!(
+            options.flags(),
+            WBEM_FLAG_FORWARD_ONLY | WBEM_FLAG_RETURN_IMMEDIATELY
+        );
+
+        let options = QueryOptions {
+            bidirectional: true,
+            ..Default::default()
+        };
+        assert_eq!(options.flags(), WBEM_FLAG_RETURN_IMMEDIATELY);
+    }
+
+    #[test]
+    fn it_reads_value_map_of_amended_class() {
+        let wmi_con = wmi_con();
+
+        let class = wmi_con
+            .get_object_with_amended_qualifiers("Win32_LogicalDisk")
+            .unwrap();
+
+        let drive_types = class.value_map("DriveType").unwrap();
+
+        assert!(drive_types.contains(&("2".to_owned(), "Removable Disk".to_owned())));
+        assert!(drive_types.contains(&("3".to_owned(), "Local Disk".to_owned())));
+    }
+
+    #[cfg(feature = "serde_json")]
+    #[test]
+    fn it_can_query_to_json() {
+        let wmi_con = wmi_con();
+
+        let json = wmi_con
+            .exec_query_to_json(
+                "SELECT Caption, Primary, NumberOfProcesses FROM Win32_OperatingSystem",
+            )
+            .unwrap();
+
+        let rows = json.as_array().unwrap();
+
+        assert_eq!(rows.len(), 1);
+        assert_eq!(rows[0]["Caption"], "Microsoft Windows 10 Pro");
+        assert_eq!(rows[0]["Primary"], true);
+        assert!(rows[0]["NumberOfProcesses"].as_u64().unwrap() > 0);
+    }
+
+    #[cfg(feature = "serde_json")]
+    #[test]
+    fn it_can_query_to_jsonl() {
+        let wmi_con = wmi_con();
+
+        let mut out = vec![];
+
+        let count = wmi_con
+            .query_to_jsonl("SELECT Name, ProcessId FROM Win32_Process", &mut out)
+            .unwrap();
+
+        let output = String::from_utf8(out).unwrap();
+        let lines: Vec<&str> = output.lines().collect();
+
+        assert!(count > 1);
+        assert_eq!(lines.len(), count);
+        assert!(output.ends_with('\n'));
+
+        for line in lines {
+            let row: serde_json::Value = serde_json::from_str(line).unwrap();
+
+            assert!(row["Name"].is_string());
+            assert!(row["ProcessId"].as_u64().is_some());
+        }
+    }
+
+    #[test]
+    fn it_can_iterate_raw_query_results() {
+        let wmi_con = wmi_con();
+
+        let results: Vec<HashMap<String, Variant>> = wmi_con
+            .raw_query_iter("SELECT Name FROM Win32_Process")
+            .unwrap()
+            .map(|res| res.unwrap())
+            .collect();
+
+        assert!(results.len() > 1);
+
+        for process in &results {
+            assert!(process.contains_key("Name"));
+        }
+    }
+
+    #[test]
+    fn it_releases_the_enumerator_when_breaking_out_of_iteration() {
+        let wmi_con = wmi_con();
+
+        let mock = MockEnumerator::new(vec![
+            make_object(&[("Name", Variant::String("A".into()))]),
+            make_object(&[("Name", Variant::String("B".into()))]),
+        ]);
+
+        let enumerator = QueryResultEnumerator::new(&wmi_con, mock.as_ptr());
+
+        let mut count = 0;
+
+        for fixture in wmi_con.deserialize_results::<HashMap<String, Variant>>(
+            "SELECT Name FROM WMIRS_Fixture".to_owned(),
+            enumerator,
+            &QueryOptions::default(),
+        ) {
+            assert_eq!(fixture.unwrap()["Name"], Variant::String("A".into()));
+            count += 1;
+
+            if count == 1 {
+                break;
+            }
+        }
+
+        assert_eq!(count, 1);
+        assert_eq!(mock.release_count(), 1);
+    }
+
+    #[test]
+    fn it_adds_direct_read_flag() {
+        let options = QueryOptions {
+            direct_read: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            options.flags(),
+            WBEM_FLAG_FORWARD_ONLY | WBEM_FLAG_RETURN_IMMEDIATELY | WBEM_FLAG_DIRECT_READ
+        );
+    }
+
+    #[test]
+    fn it_returns_only_the_exact_class_with_direct_read() {
+        let wmi_con = wmi_con();
+
+        #[derive(Deserialize, Debug)]
+        struct File {
+            __CLASS: String,
+        }
+
+        // `CIM_DataFile` is concrete, and this folder has both its own instances (`desktop.ini`)
+        // and instances of its `Win32_ShortcutFile` subclass (the `.lnk` files).
+        let query = r#"SELECT __CLASS FROM CIM_DataFile WHERE Drive = "C:" AND Path = "\\ProgramData\\Microsoft\\Windows\\Start Menu\\Programs\\Administrative Tools\\""#;
+
+        let all: Vec<File> = wmi_con.raw_query(query).unwrap();
+
+        // Without direct read, the instances of the subclasses are returned.
+        assert!(all.iter().any(|f| f.__CLASS == "Win32_ShortcutFile"));
+
+        let options = QueryOptions {
+            direct_read: true,
+            ..Default::default()
+        };
+
+        let direct: Vec<File> = wmi_con.raw_query_with_options(query, &options).unwrap();
+
+        assert!(!direct.is_empty());
+        assert!(direct.len() < all.len());
+
+        for file in &direct {
+            assert_eq!(file.__CLASS, "CIM_DataFile");
+        }
+    }
+
+    #[test]
+    fn it_queries_only_the_subclass_of_the_struct() {
+        let wmi_con = wmi_con();
+
+        #[derive(Deserialize, Debug)]
+        struct Win32_Processor {
+            __CLASS: String,
+            Name: String,
+        }
+
+        assert_eq!(
+            build_query::<Win32_Processor>(None, None),
+            "SELECT __CLASS,Name FROM Win32_Processor "
+        );
+
+        let processors: Vec<Win32_Processor> = wmi_con.query().unwrap();
+
+        assert!(!processors.is_empty());
+
+        for processor in &processors {
+            assert_eq!(processor.__CLASS, "Win32_Processor");
+        }
+    }
+
+    #[test]
+    fn it_queries_with_a_context() {
+        // Records the contexts passed to WMI by the connection.
+        let spy = unsafe { ServicesSpy::new(wmi_con().into_services_ptr()) };
+
+        let wmi_con = unsafe {
+            WMIConnection::from_services_ptr(
+                spy.as_ptr(),
+                ".",
+                "ROOT\\CIMV2",
+                COMLibrary::without_security().unwrap(),
+            )
+        }
+        .unwrap();
+
+        let context = WMIContext::new().unwrap();
+        context
+            .set_value("__ProviderArchitecture", &Variant::I4(64))
+            .unwrap();
+
+        let options = QueryOptions {
+            context: Some(context.clone()),
+            ..Default::default()
+        };
+
+        #[derive(Deserialize, Debug)]
+        struct Win32_OperatingSystem {
+            Caption: String,
+        }
+
+        let results: Vec<Win32_OperatingSystem> = wmi_con.query_with_options(&options).unwrap();
+
+        assert_eq!(results.len(), 1);
+        assert!(results[0].Caption.contains("Windows"));
+
+        let disk = wmi_con
+            .get_object_with_context(r#"Win32_LogicalDisk.DeviceID="C:""#, &context)
+            .unwrap();
+
+        assert_eq!(
+            disk.get_property("DeviceID").unwrap(),
+            Variant::String("C:".to_owned())
+        );
+
+        assert_eq!(spy.contexts(), [context.ctx(), context.ctx()]);
+    }
+
+    #[test]
+    fn it_builds_correct_query_for_another_class() {
+        #[derive(Deserialize)]
+        struct Named {
+            Name: String,
+            Caption: String,
+        }
+
+        let mut filters = HashMap::new();
+
+        filters.insert("Name".to_owned(), FilterValue::Str("cargo.exe"));
+
+        assert_eq!(
+            build_query_for_class::<Named>("Win32_Process", None, None),
+            "SELECT Name,Caption FROM Win32_Process "
+        );
+        assert_eq!(
+            build_query_for_class::<Named>("Win32_Process", Some(&filters), None),
+            "SELECT Name,Caption FROM Win32_Process WHERE Name = \"cargo.exe\""
+        );
+    }
+
+    #[test]
+    fn it_can_query_with_another_class() {
+        let wmi_con = wmi_con();
+
+        #[derive(Deserialize, Debug)]
+        struct OS {
+            Caption: String,
+        }
+
+        let results = wmi_con
+            .query_with_class::<OS>("Win32_OperatingSystem")
+            .unwrap();
+
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].Caption, "Microsoft Windows 10 Pro");
+
+        let mut filters = HashMap::new();
+
+        filters.insert("Caption".to_owned(), FilterValue::Str("Not an OS"));
+
+        let results = wmi_con
+            .filtered_query_with_class::<OS>("Win32_OperatingSystem", &filters)
+            .unwrap();
+
+        assert!(results.is_empty());
+    }
+
+    #[test]
+    fn it_can_query_with_types() {
+        let wmi_con = wmi_con();
+
+        let results = wmi_con
+            .raw_query_with_types("SELECT TotalPhysicalMemory, Name FROM Win32_ComputerSystem")
+            .unwrap();
+
+        let (memory, memory_type) = &results[0]["TotalPhysicalMemory"];
+
+        match memory {
+            Variant::String(s) => assert!(s.parse::<u64>().unwrap() > 0),
+            _ => assert!(false),
+        }
+
+        assert_eq!(*memory_type, CimType::UInt64);
+        assert_eq!(results[0]["Name"].1, CimType::String);
+    }
 }
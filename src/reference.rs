@@ -0,0 +1,81 @@
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::ops::Deref;
+
+/// The object path held by a reference property (a CIM `ref:` property,
+/// like the `Antecedent` and `Dependent` properties of association classes).
+///
+/// Reference properties are returned as strings, so a plain `String` field works as well;
+/// this type only makes the intent of the field clearer.
+///
+/// ```edition2018
+/// # use wmi::*;
+/// # use serde::Deserialize;
+/// # let con = WMIConnection::new(COMLibrary::new().unwrap().into()).unwrap();
+/// #[derive(Deserialize)]
+/// struct Win32_LogicalDiskToPartition {
+///     Antecedent: WMIRef,
+///     Dependent: WMIRef,
+/// }
+///
+/// let mappings: Vec<Win32_LogicalDiskToPartition> = con.query().unwrap();
+/// #
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct WMIRef(pub String);
+
+impl Deref for WMIRef {
+    type Target = str;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl AsRef<str> for WMIRef {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Display for WMIRef {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(&self.0, f)
+    }
+}
+
+#[allow(non_snake_case)]
+#[allow(non_camel_case_types)]
+#[cfg(test)]
+mod tests {
+    use super::WMIRef;
+    use crate::tests::fixtures::*;
+    use serde::Deserialize;
+
+    #[test]
+    fn it_desr_reference_properties_into_paths() {
+        let wmi_con = wmi_con();
+
+        #[derive(Deserialize, Debug)]
+        struct Win32_LogicalDiskToPartition {
+            Antecedent: String,
+            Dependent: WMIRef,
+        }
+
+        let mappings: Vec<Win32_LogicalDiskToPartition> = wmi_con.query().unwrap();
+
+        let system_disk = mappings
+            .iter()
+            .find(|mapping| {
+                mapping
+                    .Dependent
+                    .ends_with(r#"Win32_LogicalDisk.DeviceID="C:""#)
+            })
+            .unwrap();
+
+        assert!(system_disk
+            .Antecedent
+            .contains("Win32_DiskPartition.DeviceID="));
+    }
+}
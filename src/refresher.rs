@@ -0,0 +1,297 @@
+//! Cheaply re-reading a fixed set of objects (usually performance counters) using `IWbemRefresher`.
+//!
+//! Re-executing a query for every sample is relatively expensive. Instead, a [`Refresher`] is
+//! configured once with the classes to read, and every call to [`Refresher::refresh`] updates
+//! the objects of all of its enums in place.
+//!
+//! ```edition2018
+//! # use wmi::*;
+//! # use serde::Deserialize;
+//! # use std::{thread, time::Duration};
+//! # let wmi_con = WMIConnection::new(COMLibrary::new().unwrap().into()).unwrap();
+//! #[derive(Deserialize, Debug)]
+//! struct Win32_PerfRawData_PerfOS_Processor {
+//!     Name: String,
+//!     PercentProcessorTime: u64,
+//!     Timestamp_Sys100NS: u64,
+//! }
+//!
+//! let refresher = wmi_con.refresher().unwrap();
+//! let processors = refresher.add_enum("Win32_PerfRawData_PerfOS_Processor").unwrap();
+//!
+//! for _ in 0..3 {
+//!     refresher.refresh().unwrap();
+//!
+//!     let samples: Vec<Win32_PerfRawData_PerfOS_Processor> = processors.deserialize().unwrap();
+//!
+//!     thread::sleep(Duration::from_millis(100));
+//! }
+//! ```
+//!
+use crate::de::wbem_class_de::from_wbem_class_obj;
+use crate::result_enumerator::IWbemClassWrapper;
+use crate::{connection::WMIConnection, utils::check_hres};
+use failure::Error;
+use log::debug;
+use serde::de;
+use std::ptr::{self, NonNull};
+use widestring::WideCString;
+use winapi::{
+    shared::{
+        ntdef::{HRESULT, NULL},
+        wtypesbase::CLSCTX_INPROC_SERVER,
+    },
+    um::{
+        combaseapi::CoCreateInstance,
+        wbemcli::{
+            CLSID_WbemRefresher, IID_IWbemConfigureRefresher, IID_IWbemRefresher, IWbemClassObject,
+            IWbemConfigureRefresher, IWbemHiPerfEnum, IWbemObjectAccess, IWbemRefresher,
+            WBEM_E_BUFFER_TOO_SMALL,
+        },
+    },
+};
+
+/// A wrapper around `IWbemRefresher` (and its `IWbemConfigureRefresher` interface).
+///
+/// The refresher borrows the connection which created it, since its enums are read
+/// from the connection's namespace.
+///
+pub struct Refresher<'a> {
+    wmi_con: &'a WMIConnection,
+    p_refresher: Option<NonNull<IWbemRefresher>>,
+    p_config: Option<NonNull<IWbemConfigureRefresher>>,
+}
+
+impl<'a> Refresher<'a> {
+    pub fn new(wmi_con: &'a WMIConnection) -> Result<Self, Error> {
+        debug!("Calling CoCreateInstance for CLSID_WbemRefresher");
+
+        let mut p_refresher = NULL;
+
+        unsafe {
+            check_hres(CoCreateInstance(
+                &CLSID_WbemRefresher,
+                ptr::null_mut(),
+                CLSCTX_INPROC_SERVER,
+                &IID_IWbemRefresher,
+                &mut p_refresher,
+            ))?;
+        }
+
+        let mut refresher = Self {
+            wmi_con,
+            p_refresher: NonNull::new(p_refresher as *mut IWbemRefresher),
+            p_config: None,
+        };
+
+        let mut p_config = NULL;
+
+        unsafe {
+            check_hres(
+                (*refresher.refresher())
+                    .QueryInterface(&IID_IWbemConfigureRefresher, &mut p_config),
+            )?;
+        }
+
+        refresher.p_config = NonNull::new(p_config as *mut IWbemConfigureRefresher);
+
+        Ok(refresher)
+    }
+
+    /// Add all the instances of the given class (for example, `Win32_PerfRawData_PerfOS_Processor`)
+    /// to the refresher, using `IWbemConfigureRefresher::AddEnum`.
+    ///
+    /// The returned enum is empty until the first call to `refresh`.
+    ///
+    pub fn add_enum(&self, class_name: &str) -> Result<RefresherEnum, Error> {
+        let class_name = WideCString::from_str(class_name)?;
+
+        let mut p_enum = ptr::null_mut::<IWbemHiPerfEnum>();
+        let mut id = 0;
+
+        unsafe {
+            check_hres((*self.config()).AddEnum(
+                self.wmi_con.svc(),
+                class_name.as_ptr(),
+                0,
+                ptr::null_mut(),
+                &mut p_enum,
+                &mut id,
+            ))?;
+        }
+
+        debug!("Got refresher enum {:?} with id {}", p_enum, id);
+
+        Ok(RefresherEnum {
+            p_enum: NonNull::new(p_enum),
+            id,
+        })
+    }
+
+    /// Update the objects of every enum which was added to the refresher.
+    ///
+    pub fn refresh(&self) -> Result<(), Error> {
+        unsafe {
+            check_hres((*self.refresher()).Refresh(0))?;
+        }
+
+        Ok(())
+    }
+
+    fn refresher(&self) -> *mut IWbemRefresher {
+        self.p_refresher.unwrap().as_ptr()
+    }
+
+    fn config(&self) -> *mut IWbemConfigureRefresher {
+        self.p_config.unwrap().as_ptr()
+    }
+}
+
+impl<'a> Drop for Refresher<'a> {
+    fn drop(&mut self) {
+        if let Some(p_config) = self.p_config.take() {
+            unsafe {
+                (*p_config.as_ptr()).Release();
+            }
+        }
+
+        if let Some(p_refresher) = self.p_refresher.take() {
+            unsafe {
+                (*p_refresher.as_ptr()).Release();
+            }
+        }
+    }
+}
+
+/// The instances of a class which were added to a [`Refresher`] (a wrapper around `IWbemHiPerfEnum`).
+///
+pub struct RefresherEnum {
+    p_enum: Option<NonNull<IWbemHiPerfEnum>>,
+    id: i32,
+}
+
+impl RefresherEnum {
+    /// The id of the enum in its refresher.
+    ///
+    pub fn id(&self) -> i32 {
+        self.id
+    }
+
+    /// Return the objects of the enum, as of the last call to `refresh`.
+    ///
+    pub fn objects(&self) -> Result<Vec<IWbemClassWrapper>, Error> {
+        let p_enum = self.p_enum.unwrap().as_ptr();
+
+        let mut objects: Vec<*mut IWbemObjectAccess> = vec![];
+        let mut returned = 0;
+
+        // The first call (with an empty buffer) returns the number of objects.
+        loop {
+            let hres = unsafe {
+                (*p_enum).GetObjects(0, objects.len() as u32, objects.as_mut_ptr(), &mut returned)
+            };
+
+            if hres == WBEM_E_BUFFER_TOO_SMALL as HRESULT {
+                objects.resize(returned as usize, ptr::null_mut());
+                continue;
+            }
+
+            check_hres(hres)?;
+
+            break;
+        }
+
+        // `IWbemObjectAccess` derives from `IWbemClassObject`, and each returned object was `AddRef`ed.
+        Ok(objects
+            .into_iter()
+            .take(returned as usize)
+            .map(|p_obj| IWbemClassWrapper::new(NonNull::new(p_obj as *mut IWbemClassObject)))
+            .collect())
+    }
+
+    /// Return the objects of the enum (as of the last call to `refresh`), deserialized into T.
+    ///
+    pub fn deserialize<T>(&self) -> Result<Vec<T>, Error>
+    where
+        T: de::DeserializeOwned,
+    {
+        self.objects()?
+            .iter()
+            .map(|obj| Ok(from_wbem_class_obj(obj)?))
+            .collect()
+    }
+}
+
+impl Drop for RefresherEnum {
+    fn drop(&mut self) {
+        if let Some(p_enum) = self.p_enum.take() {
+            unsafe {
+                (*p_enum.as_ptr()).Release();
+            }
+        }
+    }
+}
+
+impl WMIConnection {
+    /// Create a [`Refresher`] for the namespace of this connection.
+    ///
+    pub fn refresher(&self) -> Result<Refresher<'_>, Error> {
+        Refresher::new(self)
+    }
+}
+
+#[allow(non_snake_case)]
+#[allow(non_camel_case_types)]
+#[cfg(test)]
+mod tests {
+    use crate::tests::fixtures::*;
+    use serde::Deserialize;
+    use std::thread;
+    use std::time::Duration;
+
+    #[derive(Deserialize, Debug)]
+    struct Win32_PerfRawData_PerfOS_Processor {
+        Name: String,
+        Timestamp_Sys100NS: u64,
+    }
+
+    fn total(
+        processors: Vec<Win32_PerfRawData_PerfOS_Processor>,
+    ) -> Win32_PerfRawData_PerfOS_Processor {
+        processors
+            .into_iter()
+            .find(|processor| processor.Name == "_Total")
+            .unwrap()
+    }
+
+    #[test]
+    fn it_refreshes_a_perf_class() {
+        let wmi_con = wmi_con();
+
+        let refresher = wmi_con.refresher().unwrap();
+        let processors = refresher
+            .add_enum("Win32_PerfRawData_PerfOS_Processor")
+            .unwrap();
+
+        refresher.refresh().unwrap();
+
+        let first = total(processors.deserialize().unwrap());
+
+        thread::sleep(Duration::from_millis(100));
+
+        refresher.refresh().unwrap();
+
+        let second = total(processors.deserialize().unwrap());
+
+        assert!(second.Timestamp_Sys100NS > first.Timestamp_Sys100NS);
+    }
+
+    #[test]
+    fn it_fails_to_add_a_missing_class() {
+        let wmi_con = wmi_con();
+
+        let refresher = wmi_con.refresher().unwrap();
+
+        assert!(refresher.add_enum("Win32_NoSuchClass").is_err());
+    }
+}
@@ -1,21 +1,60 @@
 use crate::{
-    connection::WMIConnection, safearray::safe_array_to_vec_of_strings, utils::check_hres,
+    cim_type::CimType,
+    connection::WMIConnection,
+    safearray::{safe_array_to_vec_of_strings, SafeArrayGuard},
+    utils::{check_hres, WMIError},
+    variant::NativeVariant,
+    Variant,
 };
-use failure::Error;
-use log::trace;
-use std::{ptr, ptr::NonNull};
+use failure::{bail, Error};
+use log::{debug, trace};
+use std::{collections::VecDeque, mem, ptr, ptr::NonNull, time::Instant};
+use widestring::WideCString;
 use winapi::{
-    shared::ntdef::NULL,
+    shared::ntdef::{HRESULT, NULL},
     um::{
-        oaidl::SAFEARRAY,
-        oleauto::SafeArrayDestroy,
+        oaidl::{SAFEARRAY, VARIANT},
+        oleauto::VariantClear,
         wbemcli::{
-            IEnumWbemClassObject, IWbemClassObject, WBEM_FLAG_ALWAYS, WBEM_FLAG_NONSYSTEM_ONLY,
+            IEnumWbemClassObject, IWbemClassObject, IWbemQualifierSet, CIMTYPE, WBEM_E_NOT_FOUND,
+            WBEM_FLAG_ALWAYS, WBEM_FLAG_NONSYSTEM_ONLY, WBEM_GENUS_CLASS, WBEM_GENUS_INSTANCE,
             WBEM_INFINITE,
         },
     },
 };
 
+/// The system properties (`__CLASS`, `__PATH` etc.) of a WMI object.
+///
+/// Path-related properties are `None` for objects which are not locatable
+/// (for example, the out-parameters of a method).
+///
+#[derive(Debug, Clone, PartialEq)]
+pub struct SystemProperties {
+    pub class: String,
+    pub path: Option<String>,
+    pub relpath: Option<String>,
+    pub namespace: Option<String>,
+    pub server: Option<String>,
+    pub superclass: Option<String>,
+    /// The class hierarchy, starting with the immediate superclass.
+    pub derivation: Vec<String>,
+    pub property_count: u32,
+    /// Whether the object is a class definition or an instance (`__GENUS`).
+    pub genus: Genus,
+}
+
+/// The kind of a WMI object, as reported by its `__GENUS` system property.
+///
+/// Queries like `SELECT * FROM meta_class` return class definitions instead of instances.
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Genus {
+    /// `WBEM_GENUS_CLASS` (`1`).
+    Class,
+    /// `WBEM_GENUS_INSTANCE` (`2`).
+    Instance,
+}
+
 /// A wrapper around a raw pointer to IWbemClassObject, which also takes care of releasing
 /// the object when dropped.
 ///
@@ -46,13 +85,326 @@ impl IWbemClassWrapper {
             ))
         }?;
 
-        let res = safe_array_to_vec_of_strings(p_names);
+        let p_names = SafeArrayGuard(p_names);
+
+        safe_array_to_vec_of_strings(p_names.0)
+    }
+
+    /// Return the value of a single property of the given object.
+    ///
+    pub fn get_property(&self, property_name: &str) -> Result<Variant, Error> {
+        let (property_value, _) = self.get_property_with_type(property_name)?;
+
+        Ok(property_value)
+    }
+
+    /// Return the value of a single property of the given object,
+    /// or `None` if the value is null or the object has no such property.
+    ///
+    pub fn get_property_opt(&self, property_name: &str) -> Result<Option<Variant>, Error> {
+        match self.get_property(property_name) {
+            Ok(Variant::Null) | Ok(Variant::Empty) => Ok(None),
+            Ok(value) => Ok(Some(value)),
+            Err(e) => match e.as_fail().downcast_ref::<WMIError>() {
+                Some(err) if err.is_not_found() => Ok(None),
+                _ => Err(e),
+            },
+        }
+    }
+
+    /// Return a property as a string (formatting numbers and bools),
+    /// or `default` if the value is null or the object has no such property.
+    ///
+    /// ```edition2018
+    /// # use wmi::*;
+    /// # let con = WMIConnection::new(COMLibrary::new().unwrap().into()).unwrap();
+    /// let os = con.get_object("Win32_OperatingSystem=@").unwrap();
+    ///
+    /// let caption = os.get_string_or("Caption", "Unknown").unwrap();
+    /// let memory = os.get_u64_or("TotalVisibleMemorySize", 0).unwrap();
+    /// #
+    ///
+    pub fn get_string_or(&self, property_name: &str, default: &str) -> Result<String, Error> {
+        match self.get_coerced_property(property_name, CimType::String)? {
+            Some(Variant::String(s)) => Ok(s),
+            _ => Ok(default.to_owned()),
+        }
+    }
+
+    /// Return a property as an `i64` (parsing strings, like the values of `sint64` properties),
+    /// or `default` if the value is null or the object has no such property.
+    ///
+    pub fn get_i64_or(&self, property_name: &str, default: i64) -> Result<i64, Error> {
+        match self.get_coerced_property(property_name, CimType::SInt64)? {
+            Some(Variant::I8(n)) => Ok(n),
+            _ => Ok(default),
+        }
+    }
+
+    /// Return a property as a `u64` (parsing strings, like the values of `uint64` properties),
+    /// or `default` if the value is null or the object has no such property.
+    ///
+    pub fn get_u64_or(&self, property_name: &str, default: u64) -> Result<u64, Error> {
+        match self.get_coerced_property(property_name, CimType::UInt64)? {
+            Some(Variant::UI8(n)) => Ok(n),
+            _ => Ok(default),
+        }
+    }
+
+    /// Return a property as an `f64`,
+    /// or `default` if the value is null or the object has no such property.
+    ///
+    pub fn get_f64_or(&self, property_name: &str, default: f64) -> Result<f64, Error> {
+        match self.get_coerced_property(property_name, CimType::Real64)? {
+            Some(Variant::R8(n)) => Ok(n),
+            _ => Ok(default),
+        }
+    }
+
+    /// Return a property as a `bool` (where a non-zero number is `true`),
+    /// or `default` if the value is null or the object has no such property.
+    ///
+    pub fn get_bool_or(&self, property_name: &str, default: bool) -> Result<bool, Error> {
+        match self.get_coerced_property(property_name, CimType::Boolean)? {
+            Some(Variant::Bool(b)) => Ok(b),
+            _ => Ok(default),
+        }
+    }
+
+    /// A present, non-null property, coerced using the rules of `Variant::coerce_to_cim_type`.
+    fn get_coerced_property(
+        &self,
+        property_name: &str,
+        cim_type: CimType,
+    ) -> Result<Option<Variant>, Error> {
+        match self.get_property_opt(property_name)? {
+            Some(value) => Ok(Some(value.coerce_to_cim_type(cim_type)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Return the value of a single property of the given object, and its declared CIM type.
+    ///
+    pub fn get_property_with_type(&self, property_name: &str) -> Result<(Variant, CimType), Error> {
+        let (property_value, cim_type) = self.get_native_property(property_name)?;
+
+        Ok((property_value.to_variant()?, CimType::from_raw(cim_type)))
+    }
+
+    /// Return the native value of a single property of the given object, and its raw CIM type.
+    ///
+    pub(crate) fn get_native_property(
+        &self,
+        property_name: &str,
+    ) -> Result<(NativeVariant, CIMTYPE), Error> {
+        let name_prop = WideCString::from_str(property_name)?;
+
+        let mut vt_prop: VARIANT = unsafe { mem::zeroed() };
+        let mut cim_type: CIMTYPE = 0;
+
+        let ptr = self.inner.unwrap().as_ptr();
+
+        unsafe {
+            check_hres((*ptr).Get(
+                name_prop.as_ptr() as *mut _,
+                0,
+                &mut vt_prop,
+                &mut cim_type,
+                ptr::null_mut(),
+            ))?;
+        }
+
+        Ok((NativeVariant(vt_prop), cim_type))
+    }
+
+    /// Set the value of a single property of the given object.
+    ///
+    pub fn put_property(&self, property_name: &str, value: &Variant) -> Result<(), Error> {
+        let name_prop = WideCString::from_str(property_name)?;
+
+        let mut vt_prop = value.to_variant()?;
+
+        let ptr = self.inner.unwrap().as_ptr();
+
+        let res = unsafe { check_hres((*ptr).Put(name_prop.as_ptr(), 0, &mut vt_prop, 0)) };
+
+        unsafe { VariantClear(&mut vt_prop) };
+
+        Ok(res?)
+    }
+
+    /// Create a new instance of the given class object
+    /// (for example, the in-parameters object of a method).
+    ///
+    pub fn spawn_instance(&self) -> Result<IWbemClassWrapper, Error> {
+        let mut p_instance = NULL as *mut IWbemClassObject;
+
+        let ptr = self.inner.unwrap().as_ptr();
+
+        unsafe {
+            check_hres((*ptr).SpawnInstance(0, &mut p_instance))?;
+        }
+
+        Ok(IWbemClassWrapper::new(NonNull::new(p_instance)))
+    }
+
+    /// Create an independent copy of the object (using `IWbemClassObject::Clone`),
+    /// including any embedded objects it holds.
+    ///
+    /// Unlike [`clone`](Clone::clone), which shares the same underlying object,
+    /// changes to the copy (using [`put_property`](IWbemClassWrapper::put_property)) are not visible in the original.
+    ///
+    pub fn deep_clone(&self) -> Result<IWbemClassWrapper, Error> {
+        let mut p_copy = NULL as *mut IWbemClassObject;
+
+        let ptr = self.inner.unwrap().as_ptr();
 
         unsafe {
-            check_hres(SafeArrayDestroy(p_names))?;
+            check_hres((*ptr).Clone(&mut p_copy))?;
         }
 
-        res
+        Ok(IWbemClassWrapper::new(NonNull::new(p_copy)))
+    }
+
+    /// Return the system properties of the given object.
+    ///
+    pub fn system_props(&self) -> Result<SystemProperties, Error> {
+        let class = match self.get_optional_string_property("__CLASS")? {
+            Some(class) => class,
+            None => bail!("Expected __CLASS to be set"),
+        };
+
+        let derivation = match self.get_property("__DERIVATION")? {
+            Variant::Null | Variant::Empty => vec![],
+            derivation => derivation.try_into_vec()?,
+        };
+
+        let property_count = match self.get_property("__PROPERTY_COUNT")? {
+            Variant::I4(n) => n as u32,
+            other => bail!("Expected __PROPERTY_COUNT to be an I4, got {:?}", other),
+        };
+
+        let genus = match self.get_property("__GENUS")? {
+            Variant::I4(n) if n == WBEM_GENUS_CLASS as i32 => Genus::Class,
+            Variant::I4(n) if n == WBEM_GENUS_INSTANCE as i32 => Genus::Instance,
+            other => bail!("Expected __GENUS to be 1 or 2, got {:?}", other),
+        };
+
+        Ok(SystemProperties {
+            class,
+            path: self.get_optional_string_property("__PATH")?,
+            relpath: self.get_optional_string_property("__RELPATH")?,
+            namespace: self.get_optional_string_property("__NAMESPACE")?,
+            server: self.get_optional_string_property("__SERVER")?,
+            superclass: self.get_optional_string_property("__SUPERCLASS")?,
+            derivation,
+            property_count,
+            genus,
+        })
+    }
+
+    fn get_optional_string_property(&self, property_name: &str) -> Result<Option<String>, Error> {
+        match self.get_property(property_name)? {
+            Variant::String(s) => Ok(Some(s)),
+            Variant::Null | Variant::Empty => Ok(None),
+            other => bail!("Expected {} to be a string, got {:?}", property_name, other),
+        }
+    }
+
+    /// Return the `ValueMap` codes of a property and their matching `Values` strings
+    /// (for example, `("3", "Local Disk")` for `Win32_LogicalDisk.DriveType`).
+    ///
+    /// `Values` is an amended qualifier, so the object must be retrieved using
+    /// [`WMIConnection::get_object_with_amended_qualifiers`].
+    /// If the property has no `ValueMap`, the codes are the indices of the `Values`.
+    ///
+    pub fn value_map(&self, property_name: &str) -> Result<Vec<(String, String)>, Error> {
+        let values: Vec<String> = self
+            .get_property_qualifier(property_name, "Values")?
+            .try_into_vec()?;
+
+        let codes: Vec<String> = match self.get_property_qualifier(property_name, "ValueMap") {
+            Ok(value_map) => value_map.try_into_vec()?,
+            Err(e) => match e.as_fail().downcast_ref::<WMIError>() {
+                Some(WMIError::HResultError { hres }) if *hres == WBEM_E_NOT_FOUND as HRESULT => {
+                    (0..values.len()).map(|index| index.to_string()).collect()
+                }
+                _ => return Err(e),
+            },
+        };
+
+        if codes.len() != values.len() {
+            bail!(
+                "Expected the same number of ValueMap and Values qualifiers for {}, got {} and {}",
+                property_name,
+                codes.len(),
+                values.len()
+            );
+        }
+
+        Ok(codes.into_iter().zip(values).collect())
+    }
+
+    /// Return the value of a qualifier of a property of the given object
+    /// (for example, the `CounterType` qualifier of a performance counter property).
+    ///
+    pub fn get_property_qualifier(
+        &self,
+        property_name: &str,
+        qualifier_name: &str,
+    ) -> Result<Variant, Error> {
+        let name_prop = WideCString::from_str(property_name)?;
+        let name_qualifier = WideCString::from_str(qualifier_name)?;
+
+        let mut p_qualifier_set = NULL as *mut IWbemQualifierSet;
+
+        let ptr = self.inner.unwrap().as_ptr();
+
+        unsafe {
+            check_hres((*ptr).GetPropertyQualifierSet(
+                name_prop.as_ptr() as *mut _,
+                &mut p_qualifier_set,
+            ))?;
+        }
+
+        let mut vt_qualifier: VARIANT = unsafe { mem::zeroed() };
+
+        let res = unsafe {
+            check_hres((*p_qualifier_set).Get(
+                name_qualifier.as_ptr() as *mut _,
+                0,
+                &mut vt_qualifier,
+                ptr::null_mut(),
+            ))
+        };
+
+        unsafe {
+            (*p_qualifier_set).Release();
+        }
+
+        res?;
+
+        let qualifier_value = Variant::from_variant(vt_qualifier);
+
+        unsafe { VariantClear(&mut vt_qualifier) };
+
+        qualifier_value
+    }
+}
+
+/// Cloning a wrapper `AddRef`s the underlying `IWbemClassObject`,
+/// so a clone stays valid after the original is dropped
+/// (use [`deep_clone`](IWbemClassWrapper::deep_clone) for an independent copy).
+///
+impl Clone for IWbemClassWrapper {
+    fn clone(&self) -> Self {
+        if let Some(pcls_obj) = self.inner {
+            unsafe {
+                (*pcls_obj.as_ptr()).AddRef();
+            }
+        }
+
+        Self { inner: self.inner }
     }
 }
 
@@ -68,22 +420,234 @@ impl Drop for IWbemClassWrapper {
     }
 }
 
+/// Caches the property names of the objects of an enumeration, by their `__CLASS`.
+///
+/// The results of a query usually share a class (and the same selected properties),
+/// so `GetNames` is only called again when the class changes (for example, when a query
+/// for a superclass returns instances of several subclasses).
+///
+/// A cache should only be used with the objects of a single enumeration,
+/// since different queries for the same class can select different properties.
+///
+#[derive(Debug, Default)]
+pub struct PropertyNamesCache {
+    class: Option<String>,
+    names: Vec<String>,
+    system_properties: Vec<String>,
+    get_names_calls: usize,
+}
+
+impl PropertyNamesCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Also list the given system properties (like `__PATH`), which `GetNames` never returns,
+    /// for example because they were explicitly selected by the query.
+    ///
+    pub fn with_system_properties(mut self, system_properties: Vec<String>) -> Self {
+        self.system_properties = system_properties;
+        self
+    }
+
+    /// Return the names of all the properties of the given object,
+    /// which are the same as with [`IWbemClassWrapper::list_properties`]
+    /// (followed by the system properties given to `with_system_properties`).
+    ///
+    pub fn list_properties(
+        &mut self,
+        wbem_class_obj: &IWbemClassWrapper,
+    ) -> Result<&[String], Error> {
+        let class = match wbem_class_obj.get_property("__CLASS")? {
+            Variant::String(class) => Some(class),
+            _ => None,
+        };
+
+        // Objects without a class (which should not happen) are never cached.
+        if class.is_none() || class != self.class {
+            self.names = wbem_class_obj.list_properties()?;
+            self.names.extend(self.system_properties.iter().cloned());
+            self.get_names_calls += 1;
+            self.class = class;
+        }
+
+        Ok(&self.names)
+    }
+
+    /// The number of times `GetNames` was called (cache misses).
+    pub fn get_names_calls(&self) -> usize {
+        self.get_names_calls
+    }
+}
+
 pub struct QueryResultEnumerator<'a> {
     wmi_con: &'a WMIConnection,
     p_enumerator: Option<NonNull<IEnumWbemClassObject>>,
+    forward_only: bool,
+    batch_size: u32,
+    buffered: VecDeque<IWbemClassWrapper>,
+    started_at: Instant,
+    returned_count: usize,
+    summary_logged: bool,
 }
 
 impl<'a> QueryResultEnumerator<'a> {
+    /// Wrap an enumerator which was created using `WBEM_FLAG_FORWARD_ONLY` (which can't be `reset`).
     pub fn new(wmi_con: &'a WMIConnection, p_enumerator: *mut IEnumWbemClassObject) -> Self {
         Self {
             wmi_con,
             p_enumerator: NonNull::new(p_enumerator),
+            forward_only: true,
+            batch_size: 1,
+            buffered: VecDeque::new(),
+            started_at: Instant::now(),
+            returned_count: 0,
+            summary_logged: false,
         }
     }
+
+    pub(crate) fn forward_only(mut self, forward_only: bool) -> Self {
+        self.forward_only = forward_only;
+        self
+    }
+
+    /// Request up to `batch_size` objects in each call to `IEnumWbemClassObject::Next`
+    /// (instead of one at a time), which saves round trips for large (or remote) result sets.
+    /// The objects are still returned one by one.
+    ///
+    pub fn batch_size(mut self, batch_size: u32) -> Self {
+        self.batch_size = batch_size.max(1);
+        self
+    }
+
+    /// Rewind the enumerator to the first result, so the results can be iterated again
+    /// without executing the query again.
+    ///
+    /// This is only possible for bidirectional enumerators
+    /// (see [`QueryOptions::bidirectional`](crate::query::QueryOptions::bidirectional)),
+    /// since `WBEM_FLAG_FORWARD_ONLY` enumerators release each result after it's returned.
+    ///
+    pub fn reset(&mut self) -> Result<(), Error> {
+        if self.forward_only {
+            bail!("Can't reset a forward-only enumerator (use `QueryOptions::bidirectional`)");
+        }
+
+        if let Some(p_enumerator) = self.p_enumerator {
+            unsafe {
+                check_hres((*p_enumerator.as_ptr()).Reset())?;
+            }
+        }
+
+        self.buffered.clear();
+        self.returned_count = 0;
+        self.summary_logged = false;
+
+        Ok(())
+    }
+
+    /// Advance the enumerator, returning the next object without deserializing it
+    /// (for example, to call `IWbemClassObject` methods which aren't wrapped by this crate
+    /// using [`IWbemClassWrapper::inner`]).
+    ///
+    /// This is the same as `next`.
+    ///
+    pub fn next_raw(&mut self) -> Option<Result<IWbemClassWrapper, Error>> {
+        loop {
+            if let Some(pcls_wrapper) = self.buffered.pop_front() {
+                self.returned_count += 1;
+
+                trace!(
+                    "Got enumerator {:?} and obj {:?}",
+                    self.p_enumerator, pcls_wrapper.inner
+                );
+
+                return Some(Ok(pcls_wrapper));
+            }
+
+            let raw_enumerator_prt = self.p_enumerator?.as_ptr();
+
+            let res = fetch_batch(self.batch_size, &mut self.buffered, |objects| {
+                let mut return_value = 0;
+
+                unsafe {
+                    check_hres((*raw_enumerator_prt).Next(
+                        WBEM_INFINITE as i32,
+                        objects.len() as u32,
+                        objects.as_mut_ptr(),
+                        &mut return_value,
+                    ))?;
+                }
+
+                Ok(return_value)
+            });
+
+            match res {
+                Ok(true) => continue,
+                Ok(false) => {
+                    self.log_summary();
+
+                    return None;
+                }
+                Err(e) => return Some(Err(e)),
+            }
+        }
+    }
+
+    /// Stop the enumeration, releasing the enumerator (and any remaining results).
+    /// This is the same as dropping the enumerator.
+    ///
+    pub fn cancel(self) {}
+
+    /// Log the number of returned objects and the time since the query was executed
+    /// (once, when the enumerator is exhausted or dropped).
+    fn log_summary(&mut self) {
+        if self.summary_logged {
+            return;
+        }
+
+        self.summary_logged = true;
+
+        debug!(
+            "Enumerator {:?} returned {} objects in {:?}",
+            self.p_enumerator,
+            self.returned_count,
+            self.started_at.elapsed()
+        );
+    }
+}
+
+/// Fetch the next batch of (up to `batch_size`) objects into `buffered`, using `next`
+/// (a call to `IEnumWbemClassObject::Next`, returning the number of objects it returned).
+///
+/// `Next` can return fewer objects than requested even when more are available
+/// (for example, when a provider returns its results in chunks), so only a call which
+/// returns no objects marks the end of the enumeration, in which case `false` is returned.
+///
+fn fetch_batch(
+    batch_size: u32,
+    buffered: &mut VecDeque<IWbemClassWrapper>,
+    next: impl FnOnce(&mut [*mut IWbemClassObject]) -> Result<u32, Error>,
+) -> Result<bool, Error> {
+    let mut objects = vec![NULL as *mut IWbemClassObject; batch_size as usize];
+
+    let returned = next(&mut objects)? as usize;
+
+    // Never read past the objects which were requested.
+    let returned = returned.min(objects.len());
+
+    buffered.extend(
+        objects[..returned]
+            .iter()
+            .map(|&pcls_obj| IWbemClassWrapper::new(NonNull::new(pcls_obj))),
+    );
+
+    Ok(returned > 0)
 }
 
 impl<'a> Drop for QueryResultEnumerator<'a> {
     fn drop(&mut self) {
+        self.log_summary();
+
         if let Some(p_enumerator) = self.p_enumerator {
             unsafe {
                 (*p_enumerator.as_ptr()).Release();
@@ -96,39 +660,308 @@ impl<'a> Iterator for QueryResultEnumerator<'a> {
     type Item = Result<IWbemClassWrapper, Error>;
 
     fn next(&mut self) -> Option<Self::Item> {
-        let mut pcls_obj = NULL as *mut IWbemClassObject;
-        let mut return_value = 0;
+        self.next_raw()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::query::QueryOptions;
+    use crate::tests::fixtures::*;
+    use crate::tests::make_object;
+    use failure::format_err;
+
+    #[test]
+    fn it_returns_system_props() {
+        let wmi_con = wmi_con();
+
+        let os = wmi_con.get_object("Win32_OperatingSystem=@").unwrap();
+
+        let props = os.system_props().unwrap();
 
-        if self.p_enumerator.is_none() {
-            return None;
+        assert_eq!(props.class, "Win32_OperatingSystem");
+        assert_eq!(props.superclass.as_deref(), Some("CIM_OperatingSystem"));
+        assert_eq!(props.derivation.first().unwrap(), "CIM_OperatingSystem");
+        assert_eq!(props.relpath.as_deref(), Some("Win32_OperatingSystem=@"));
+        assert_eq!(props.namespace.unwrap().to_lowercase(), r#"root\cimv2"#);
+
+        let path = props.path.unwrap().to_lowercase();
+        assert!(path.ends_with(r#"root\cimv2:win32_operatingsystem=@"#));
+        assert!(props.server.is_some());
+        assert!(props.property_count > 0);
+        assert_eq!(props.genus, Genus::Instance);
+    }
+
+    #[test]
+    fn it_distinguishes_classes_from_instances() {
+        let wmi_con = wmi_con();
+
+        let class = wmi_con.get_object("Win32_OperatingSystem").unwrap();
+
+        assert_eq!(class.system_props().unwrap().genus, Genus::Class);
+
+        #[allow(non_snake_case)]
+        #[derive(serde::Deserialize, Debug)]
+        struct Win32_OperatingSystem {
+            __GENUS: i32,
         }
 
-        let raw_enumerator_prt = self.p_enumerator.unwrap().as_ptr();
+        let results: Vec<Win32_OperatingSystem> = wmi_con
+            .raw_query("SELECT * FROM Win32_OperatingSystem")
+            .unwrap();
 
-        let res = unsafe {
-            check_hres((*raw_enumerator_prt).Next(
-                WBEM_INFINITE as i32,
-                1,
-                &mut pcls_obj,
-                &mut return_value,
-            ))
-        };
+        assert_eq!(results[0].__GENUS, 2);
+    }
+
+    #[test]
+    fn it_caches_property_names_of_the_same_class() {
+        let wmi_con = wmi_con();
+
+        let enumerator = wmi_con
+            .exec_query_native_wrapper("SELECT * FROM Win32_Process")
+            .unwrap();
+
+        let mut cache = PropertyNamesCache::new();
+        let mut count = 0;
+
+        for obj in enumerator {
+            let obj = obj.unwrap();
+
+            assert_eq!(
+                cache.list_properties(&obj).unwrap(),
+                obj.list_properties().unwrap().as_slice()
+            );
+
+            count += 1;
+        }
+
+        assert!(count > 1);
+        assert_eq!(cache.get_names_calls(), 1);
+    }
+
+    #[test]
+    fn it_invalidates_cached_property_names_on_class_change() {
+        let wmi_con = wmi_con();
+
+        // Instances of many subclasses (`Win32_Processor`, `Win32_DiskDrive` etc.).
+        let enumerator = wmi_con
+            .exec_query_native_wrapper("SELECT * FROM CIM_LogicalDevice")
+            .unwrap();
+
+        let mut cache = PropertyNamesCache::new();
+        let mut count = 0;
+
+        for obj in enumerator {
+            let obj = obj.unwrap();
 
-        if let Err(e) = res {
-            return Some(Err(e.into()));
+            assert_eq!(
+                cache.list_properties(&obj).unwrap(),
+                obj.list_properties().unwrap().as_slice()
+            );
+
+            count += 1;
         }
 
-        if return_value == 0 {
-            return None;
+        assert!(cache.get_names_calls() > 1);
+        assert!(cache.get_names_calls() < count);
+    }
+
+    #[test]
+    fn it_returns_raw_objects() {
+        let wmi_con = wmi_con();
+
+        let mut enumerator = wmi_con
+            .exec_query_native_wrapper("SELECT Caption FROM Win32_OperatingSystem")
+            .unwrap();
+
+        let os = enumerator.next_raw().unwrap().unwrap();
+
+        assert!(os.inner.is_some());
+        assert_eq!(
+            os.get_property("Caption").unwrap(),
+            Variant::String("Microsoft Windows 10 Pro".into())
+        );
+
+        assert!(enumerator.next_raw().is_none());
+    }
+
+    #[test]
+    fn it_can_reset_a_bidirectional_enumerator() {
+        let wmi_con = wmi_con();
+
+        let options = QueryOptions {
+            bidirectional: true,
+            ..Default::default()
+        };
+
+        let mut enumerator = wmi_con
+            .exec_query_native_wrapper_with_options("SELECT Name FROM Win32_Processor", &options)
+            .unwrap();
+
+        let names = |enumerator: &mut QueryResultEnumerator| -> Vec<Variant> {
+            enumerator
+                .map(|obj| obj.unwrap().get_property("Name").unwrap())
+                .collect()
+        };
+
+        let first = names(&mut enumerator);
+
+        enumerator.reset().unwrap();
+
+        let second = names(&mut enumerator);
+
+        assert!(!first.is_empty());
+        assert_eq!(first, second);
+
+        enumerator.cancel();
+    }
+
+    #[test]
+    fn it_fails_to_reset_a_forward_only_enumerator() {
+        let wmi_con = wmi_con();
+
+        let mut enumerator = wmi_con
+            .exec_query_native_wrapper("SELECT Name FROM Win32_Processor")
+            .unwrap();
+
+        assert!(enumerator.reset().is_err());
+    }
+
+    #[test]
+    fn it_counts_returned_objects() {
+        let wmi_con = wmi_con();
+
+        let mut enumerator = wmi_con
+            .exec_query_native_wrapper("SELECT Name FROM Win32_Processor")
+            .unwrap();
+
+        let count = enumerator.by_ref().count();
+
+        assert!(count > 0);
+        assert_eq!(enumerator.returned_count, count);
+        assert!(enumerator.summary_logged);
+    }
+
+    #[test]
+    fn it_fetches_partial_batches_until_nothing_is_returned() {
+        // A provider which returns its results in uneven chunks.
+        let mut fills = vec![2, 1, 3, 0].into_iter();
+        let mut buffered = VecDeque::new();
+        let mut batches = 0;
+
+        while fetch_batch(3, &mut buffered, |objects| {
+            assert_eq!(objects.len(), 3);
+
+            Ok(fills.next().unwrap())
+        })
+        .unwrap()
+        {
+            batches += 1;
         }
 
-        trace!(
-            "Got enumerator {:?} and obj {:?}",
-            self.p_enumerator, pcls_obj
+        assert_eq!(batches, 3);
+        assert_eq!(buffered.len(), 6);
+        assert!(fills.next().is_none());
+    }
+
+    #[test]
+    fn it_ignores_objects_beyond_the_requested_batch() {
+        let mut buffered = VecDeque::new();
+
+        assert!(fetch_batch(2, &mut buffered, |_| Ok(5)).unwrap());
+        assert_eq!(buffered.len(), 2);
+
+        assert!(fetch_batch(2, &mut buffered, |_| Err(format_err!("Next failed"))).is_err());
+        assert_eq!(buffered.len(), 2);
+    }
+
+    #[test]
+    fn it_returns_all_objects_when_fetching_in_batches() {
+        let wmi_con = wmi_con();
+
+        let count = wmi_con
+            .exec_query_native_wrapper("SELECT Name FROM Win32_Process")
+            .unwrap()
+            .count();
+
+        let enumerator = wmi_con
+            .exec_query_native_wrapper("SELECT Name FROM Win32_Process")
+            .unwrap()
+            .batch_size(16);
+
+        let names: Vec<Variant> = enumerator
+            .map(|obj| obj.unwrap().get_property("Name").unwrap())
+            .collect();
+
+        // Processes can start or exit between the two queries.
+        assert!((names.len() as i64 - count as i64).abs() < 5);
+    }
+
+    #[test]
+    fn it_keeps_a_cloned_object_alive_after_the_original_is_dropped() {
+        let obj = make_object(&[("Name", Variant::String("A".into()))]);
+
+        let retained = obj.clone();
+        drop(obj);
+
+        assert_eq!(
+            retained.get_property("Name").unwrap(),
+            Variant::String("A".into())
+        );
+    }
+
+    #[test]
+    fn it_deep_clones_an_object() {
+        let obj = make_object(&[("Name", Variant::String("A".into()))]);
+
+        let shared = obj.clone();
+        let copy = obj.deep_clone().unwrap();
+        drop(obj);
+
+        copy.put_property("Name", &Variant::String("B".into()))
+            .unwrap();
+
+        assert_eq!(
+            shared.get_property("Name").unwrap(),
+            Variant::String("A".into())
+        );
+        assert_eq!(
+            copy.get_property("Name").unwrap(),
+            Variant::String("B".into())
         );
+    }
+
+    #[test]
+    fn it_gets_properties_or_defaults() {
+        let obj = make_object(&[
+            ("Name", Variant::String("A".into())),
+            ("Size", Variant::String("1024".into())),
+            ("Count", Variant::I4(3)),
+            ("Enabled", Variant::Bool(true)),
+            ("Description", Variant::Null),
+        ]);
+
+        // Present.
+        assert_eq!(obj.get_string_or("Name", "-").unwrap(), "A");
+        assert_eq!(obj.get_u64_or("Size", 0).unwrap(), 1024);
+        assert_eq!(obj.get_i64_or("Count", 0).unwrap(), 3);
+        assert_eq!(obj.get_string_or("Count", "-").unwrap(), "3");
+        assert_eq!(obj.get_f64_or("Count", 0.0).unwrap(), 3.0);
+        assert_eq!(obj.get_bool_or("Enabled", false).unwrap(), true);
+
+        // Null.
+        assert_eq!(obj.get_string_or("Description", "-").unwrap(), "-");
+        assert_eq!(obj.get_i64_or("Description", -1).unwrap(), -1);
+        assert_eq!(obj.get_property_opt("Description").unwrap(), None);
 
-        let pcls_wrapper = IWbemClassWrapper::new(NonNull::new(pcls_obj));
+        // Absent.
+        assert_eq!(obj.get_string_or("Missing", "-").unwrap(), "-");
+        assert_eq!(obj.get_u64_or("Missing", 7).unwrap(), 7);
+        assert_eq!(obj.get_bool_or("Missing", true).unwrap(), true);
+        assert_eq!(obj.get_property_opt("Missing").unwrap(), None);
 
-        Some(Ok(pcls_wrapper))
+        // Present, but can't be coerced.
+        assert!(obj.get_u64_or("Name", 0).is_err());
     }
 }
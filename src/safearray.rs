@@ -1,9 +1,9 @@
-use crate::utils::check_hres;
+use crate::utils::{bstr_to_string, check_hres};
+use crate::variant::variant_bool_to_bool;
 use crate::Variant;
-use failure::Error;
-use std::iter::Iterator;
+use failure::{bail, Error};
+use std::iter::{self, Iterator};
 use std::slice;
-use widestring::WideCStr;
 use winapi::{
     shared::wtypes::*,
     shared::{
@@ -12,8 +12,9 @@ use winapi::{
         winerror::HRESULT,
         wtypes::BSTR,
     },
-    um::{oaidl::SAFEARRAY, oleauto::{
+    um::{oaidl::{SAFEARRAY, VARIANT}, oleauto::{
         SafeArrayAccessData,
+        SafeArrayDestroy,
         SafeArrayUnaccessData,
         SafeArrayGetLBound,
         SafeArrayGetUBound,
@@ -36,10 +37,12 @@ pub struct SafeArrayAccessor<T> {
 /// which can have different drop behavior (GetNames require the caller to deallocate the array,
 /// while a WMI property must be deallocated via VariantClear).
 ///
-/// For this reason, we don't have a `struct SafeArray`.
+/// For this reason, we don't have a `struct SafeArray` (arrays owned by the caller
+/// can be destroyed using a [`SafeArrayGuard`]).
 ///
 /// However, accessing the data of the array must be done using a lock, which is the responsibility
-/// of this struct.
+/// of this struct. The lock is only released in `drop`, so it's also released on an early return
+/// (or a panic) while the data is being read.
 ///
 impl<T> SafeArrayAccessor<T> {
     /// Creates a new Accessor, locking the given array,
@@ -69,11 +72,32 @@ impl<T> SafeArrayAccessor<T> {
 
     /// Return a slice which can access the data of the array.
     pub fn as_slice(&self) -> &[T] {
-        // upper_bound can be -1, in which case the array is empty and we will return a 0 length slice.
-        let data_slice =
-            unsafe { slice::from_raw_parts(self.p_data, (self.upper_bound + 1) as usize) };
+        // The data pointer points to the element at `lower_bound`.
+        // `upper_bound` can be `lower_bound - 1`, in which case the array is empty and we will return a 0 length slice.
+        let len = (self.upper_bound - self.lower_bound + 1).max(0) as usize;
 
-        &data_slice[(self.lower_bound as usize)..]
+        unsafe { slice::from_raw_parts(self.p_data, len) }
+    }
+
+    /// Return an iterator over the elements of the array.
+    /// The array stays locked while the iterator (which borrows the accessor) is alive.
+    pub fn iter(&self) -> Iter<'_, T>
+    where
+        T: Copy,
+    {
+        self.as_slice().iter().copied()
+    }
+}
+
+/// An iterator over the elements of a locked array.
+pub type Iter<'a, T> = iter::Copied<slice::Iter<'a, T>>;
+
+impl<'a, T: Copy> IntoIterator for &'a SafeArrayAccessor<T> {
+    type Item = T;
+    type IntoIter = Iter<'a, T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
     }
 }
 
@@ -86,6 +110,23 @@ impl<T> Drop for SafeArrayAccessor<T> {
     }
 }
 
+/// Destroys an array owned by the caller (like the names returned by `GetNames`) when dropped,
+/// so the array is released even if converting it fails (or panics).
+///
+/// Any accessor of the array must be dropped first (a locked array can't be destroyed).
+///
+pub(crate) struct SafeArrayGuard(pub(crate) *mut SAFEARRAY);
+
+impl Drop for SafeArrayGuard {
+    fn drop(&mut self) {
+        if !self.0.is_null() {
+            unsafe {
+                let _result = check_hres(SafeArrayDestroy(self.0));
+            }
+        }
+    }
+}
+
 pub fn safe_array_to_vec_of_strings(arr: *mut SAFEARRAY) -> Result<Vec<String>, Error> {
     let items = safe_array_to_vec(arr, VT_BSTR)?;
 
@@ -100,29 +141,381 @@ pub fn safe_array_to_vec_of_strings(arr: *mut SAFEARRAY) -> Result<Vec<String>,
     Ok(string_items)
 }
 
+/// Copy the data of a `uint8[]` array (`VT_UI1` items) in bulk.
+///
+pub(crate) fn safe_array_to_vec_of_bytes(arr: *mut SAFEARRAY) -> Result<Vec<u8>, Error> {
+    let accessor = unsafe { SafeArrayAccessor::<u8>::new(arr)? };
+
+    Ok(accessor.as_slice().to_vec())
+}
+
 pub fn safe_array_to_vec(arr: *mut SAFEARRAY, item_type: u32) -> Result<Vec<Variant>, Error> {
     let mut items = vec![];
 
     match item_type {
+        VT_I2 => {
+            let accessor = unsafe { SafeArrayAccessor::<i16>::new(arr)? };
+
+            items.extend(accessor.iter().map(Variant::I2));
+        }
         VT_I4 => {
             let accessor = unsafe { SafeArrayAccessor::<i32>::new(arr)? };
 
-            for item in accessor.as_slice().iter() {
-                items.push(Variant::I4(*item))
+            for item in accessor.iter() {
+                items.push(Variant::I4(item))
+            }
+        }
+        VT_I8 => {
+            let accessor = unsafe { SafeArrayAccessor::<i64>::new(arr)? };
+
+            items.extend(accessor.iter().map(Variant::I8));
+        }
+        // There is no `Variant::UI4`, so the elements are widened to keep their value.
+        VT_UI4 => {
+            let accessor = unsafe { SafeArrayAccessor::<u32>::new(arr)? };
+
+            items.extend(accessor.iter().map(|item| Variant::UI8(item.into())));
+        }
+        VT_UI8 => {
+            let accessor = unsafe { SafeArrayAccessor::<u64>::new(arr)? };
+
+            items.extend(accessor.iter().map(Variant::UI8));
+        }
+        VT_R4 => {
+            let accessor = unsafe { SafeArrayAccessor::<f32>::new(arr)? };
+
+            items.extend(accessor.iter().map(Variant::R4));
+        }
+        VT_R8 => {
+            let accessor = unsafe { SafeArrayAccessor::<f64>::new(arr)? };
+
+            items.extend(accessor.iter().map(Variant::R8));
+        }
+        // Each element is a `VARIANT_BOOL`, which is either `0` or `-1`.
+        VT_BOOL => {
+            let accessor = unsafe { SafeArrayAccessor::<i16>::new(arr)? };
+
+            for item in accessor.iter() {
+                items.push(Variant::Bool(variant_bool_to_bool(item)?));
             }
         }
+        VT_UI1 => {
+            let bytes = safe_array_to_vec_of_bytes(arr)?;
+
+            items.extend(bytes.into_iter().map(Variant::UI1));
+        }
         VT_BSTR => {
             let accessor = unsafe { SafeArrayAccessor::<BSTR>::new(arr)? };
 
-            for item_bstr in accessor.as_slice().iter() {
-                let item: &WideCStr = unsafe { WideCStr::from_ptr_str(*item_bstr) };
+            for item_bstr in accessor.iter() {
+                let item = unsafe { bstr_to_string(item_bstr)? };
 
-                items.push(Variant::String(item.to_string()?));
+                items.push(Variant::String(item));
+            }
+        }
+        // Each element is a full `VARIANT`, so an element can be null even if the others are not.
+        VT_VARIANT => {
+            let accessor = unsafe { SafeArrayAccessor::<VARIANT>::new(arr)? };
+
+            for item_variant in accessor.iter() {
+                items.push(Variant::from_variant(item_variant)?);
             }
         }
         // TODO: Add support for all other types of arrays.
-        _ => unimplemented!(),
+        _ => bail!("Unsupported array VARTYPE {:#X}", item_type),
     }
 
     Ok(items)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::Deserialize;
+    use std::ptr;
+    use std::panic;
+    use winapi::um::oleauto::SafeArrayCreateVector;
+
+    fn create_array<T: Copy>(item_type: u32, lower_bound: i32, items: &[T]) -> *mut SAFEARRAY {
+        unsafe {
+            let arr = SafeArrayCreateVector(item_type as VARTYPE, lower_bound, items.len() as u32);
+            assert!(!arr.is_null());
+
+            let mut p_data = ptr::null_mut();
+            check_hres(SafeArrayAccessData(arr, &mut p_data)).unwrap();
+            ptr::copy_nonoverlapping(items.as_ptr(), p_data as *mut T, items.len());
+            check_hres(SafeArrayUnaccessData(arr)).unwrap();
+
+            arr
+        }
+    }
+
+    fn create_i4_array(lower_bound: i32, items: &[i32]) -> *mut SAFEARRAY {
+        create_array(VT_I4, lower_bound, items)
+    }
+
+    #[test]
+    fn it_iterates_over_items() {
+        let arr = create_i4_array(0, &[0, 33, 31, 158]);
+
+        {
+            let accessor = unsafe { SafeArrayAccessor::<i32>::new(arr).unwrap() };
+
+            let items: Vec<i32> = accessor.iter().collect();
+            assert_eq!(items, [0, 33, 31, 158]);
+
+            let items: Vec<i32> = (&accessor).into_iter().collect();
+            assert_eq!(items, [0, 33, 31, 158]);
+        }
+
+        unsafe { check_hres(SafeArrayDestroy(arr)).unwrap() };
+    }
+
+    #[test]
+    fn it_unlocks_the_array_when_reading_panics() {
+        let arr = create_i4_array(0, &[0, 33, 31, 158]);
+
+        let res = panic::catch_unwind(|| {
+            let accessor = unsafe { SafeArrayAccessor::<i32>::new(arr).unwrap() };
+
+            for item in accessor.iter() {
+                if item == 31 {
+                    panic!("Failed to process {}", item);
+                }
+            }
+        });
+
+        assert!(res.is_err());
+
+        // Fails with `DISP_E_ARRAYISLOCKED` if the lock was leaked.
+        unsafe { check_hres(SafeArrayDestroy(arr)).unwrap() };
+    }
+
+    #[test]
+    fn it_iterates_over_items_with_non_zero_lower_bound() {
+        let arr = create_i4_array(1, &[4, 2]);
+
+        {
+            let accessor = unsafe { SafeArrayAccessor::<i32>::new(arr).unwrap() };
+
+            let items: Vec<i32> = accessor.iter().collect();
+            assert_eq!(items, [4, 2]);
+        }
+
+        unsafe { check_hres(SafeArrayDestroy(arr)).unwrap() };
+    }
+
+    #[test]
+    fn it_iterates_over_empty_array() {
+        let arr = create_i4_array(0, &[]);
+
+        {
+            let accessor = unsafe { SafeArrayAccessor::<i32>::new(arr).unwrap() };
+
+            assert_eq!(accessor.iter().count(), 0);
+        }
+
+        unsafe { check_hres(SafeArrayDestroy(arr)).unwrap() };
+    }
+
+    #[test]
+    fn it_converts_variant_array_with_null_elements() {
+        let items = [Variant::I4(1), Variant::Null, Variant::I4(3)];
+
+        let arr = unsafe {
+            let arr = SafeArrayCreateVector(VT_VARIANT as VARTYPE, 0, items.len() as u32);
+            assert!(!arr.is_null());
+
+            let mut p_data = ptr::null_mut();
+            check_hres(SafeArrayAccessData(arr, &mut p_data)).unwrap();
+
+            for (index, item) in items.iter().enumerate() {
+                *(p_data as *mut VARIANT).add(index) = item.to_variant().unwrap();
+            }
+
+            check_hres(SafeArrayUnaccessData(arr)).unwrap();
+
+            arr
+        };
+
+        let variants = safe_array_to_vec(arr, VT_VARIANT).unwrap();
+
+        // Destroying the array also clears each of the elements.
+        unsafe { check_hres(SafeArrayDestroy(arr)).unwrap() };
+
+        assert_eq!(variants, items);
+
+        let values: Vec<Option<i32>> = Vec::deserialize(Variant::Array(variants)).unwrap();
+
+        assert_eq!(values, [Some(1), None, Some(3)]);
+    }
+
+    #[test]
+    fn it_converts_byte_array() {
+        let bytes = [0xDEu8, 0xAD, 0x00, 0xBE, 0xEF];
+
+        let arr = unsafe {
+            let arr = SafeArrayCreateVector(VT_UI1 as VARTYPE, 0, bytes.len() as u32);
+            assert!(!arr.is_null());
+
+            let mut p_data = ptr::null_mut();
+            check_hres(SafeArrayAccessData(arr, &mut p_data)).unwrap();
+            ptr::copy_nonoverlapping(bytes.as_ptr(), p_data as *mut u8, bytes.len());
+            check_hres(SafeArrayUnaccessData(arr)).unwrap();
+
+            arr
+        };
+
+        assert_eq!(safe_array_to_vec_of_bytes(arr).unwrap(), bytes);
+
+        let variant = Variant::Array(safe_array_to_vec(arr, VT_UI1).unwrap());
+
+        let mut vt: VARIANT = unsafe { std::mem::zeroed() };
+
+        unsafe {
+            let n2 = vt.n1.n2_mut();
+            n2.vt = (VT_ARRAY | VT_UI1) as VARTYPE;
+            *n2.n3.parray_mut() = arr;
+        }
+
+        let native = Variant::from_variant(vt).unwrap();
+
+        unsafe { check_hres(SafeArrayDestroy(arr)).unwrap() };
+
+        assert_eq!(variant.clone().into_bytes().unwrap(), bytes);
+        assert_eq!(Vec::<u8>::deserialize(variant).unwrap(), bytes);
+
+        assert_eq!(native, Variant::Bytes(bytes.to_vec()));
+        assert_eq!(native.as_bytes().unwrap(), bytes);
+        assert_eq!(Vec::<u8>::deserialize(native.clone()).unwrap(), bytes);
+        assert_eq!(native.into_bytes().unwrap(), bytes);
+    }
+
+    #[test]
+    fn it_converts_bool_array() {
+        let arr = create_array::<i16>(VT_BOOL, 0, &[-1, 0, -1]);
+
+        let variant = Variant::Array(safe_array_to_vec(arr, VT_BOOL).unwrap());
+
+        unsafe { check_hres(SafeArrayDestroy(arr)).unwrap() };
+
+        assert_eq!(
+            variant,
+            Variant::Array(vec![
+                Variant::Bool(true),
+                Variant::Bool(false),
+                Variant::Bool(true)
+            ])
+        );
+        assert_eq!(Vec::<bool>::deserialize(variant).unwrap(), [true, false, true]);
+
+        let arr = create_array::<i16>(VT_BOOL, 0, &[1]);
+
+        assert!(safe_array_to_vec(arr, VT_BOOL).is_err());
+
+        unsafe { check_hres(SafeArrayDestroy(arr)).unwrap() };
+    }
+
+    #[test]
+    fn it_converts_double_array() {
+        let arr = create_array(VT_R8, 0, &[1.5f64, -0.25, 1e100]);
+
+        let variant = Variant::Array(safe_array_to_vec(arr, VT_R8).unwrap());
+
+        unsafe { check_hres(SafeArrayDestroy(arr)).unwrap() };
+
+        assert_eq!(
+            variant,
+            Variant::Array(vec![
+                Variant::R8(1.5),
+                Variant::R8(-0.25),
+                Variant::R8(1e100)
+            ])
+        );
+        assert_eq!(Vec::<f64>::deserialize(variant).unwrap(), [1.5, -0.25, 1e100]);
+    }
+
+    #[test]
+    fn it_converts_short_array() {
+        let arr = create_array::<i16>(VT_I2, 0, &[-1, 2]);
+
+        let variant = Variant::Array(safe_array_to_vec(arr, VT_I2).unwrap());
+
+        unsafe { check_hres(SafeArrayDestroy(arr)).unwrap() };
+
+        assert_eq!(variant, Variant::Array(vec![Variant::I2(-1), Variant::I2(2)]));
+        assert_eq!(Vec::<i16>::deserialize(variant).unwrap(), [-1, 2]);
+    }
+
+    #[test]
+    fn it_converts_unsigned_int_array() {
+        let arr = create_array::<u32>(VT_UI4, 0, &[1, u32::MAX]);
+
+        let variant = Variant::Array(safe_array_to_vec(arr, VT_UI4).unwrap());
+
+        unsafe { check_hres(SafeArrayDestroy(arr)).unwrap() };
+
+        assert_eq!(
+            variant,
+            Variant::Array(vec![Variant::UI8(1), Variant::UI8(u32::MAX.into())])
+        );
+        assert_eq!(Vec::<u32>::deserialize(variant).unwrap(), [1, u32::MAX]);
+    }
+
+    #[test]
+    fn it_converts_long_array() {
+        let arr = create_array::<i64>(VT_I8, 0, &[i64::MIN, 8]);
+
+        let variant = Variant::Array(safe_array_to_vec(arr, VT_I8).unwrap());
+
+        unsafe { check_hres(SafeArrayDestroy(arr)).unwrap() };
+
+        assert_eq!(
+            variant,
+            Variant::Array(vec![Variant::I8(i64::MIN), Variant::I8(8)])
+        );
+        assert_eq!(Vec::<i64>::deserialize(variant).unwrap(), [i64::MIN, 8]);
+    }
+
+    #[test]
+    fn it_converts_unsigned_long_array() {
+        let arr = create_array::<u64>(VT_UI8, 0, &[u64::MAX, 8]);
+
+        let variant = Variant::Array(safe_array_to_vec(arr, VT_UI8).unwrap());
+
+        unsafe { check_hres(SafeArrayDestroy(arr)).unwrap() };
+
+        assert_eq!(
+            variant,
+            Variant::Array(vec![Variant::UI8(u64::MAX), Variant::UI8(8)])
+        );
+        assert_eq!(Vec::<u64>::deserialize(variant).unwrap(), [u64::MAX, 8]);
+    }
+
+    #[test]
+    fn it_fails_to_convert_unsupported_array() {
+        let arr = create_array::<u16>(VT_UI2, 0, &[1, 2]);
+
+        let err = safe_array_to_vec(arr, VT_UI2).unwrap_err();
+
+        unsafe { check_hres(SafeArrayDestroy(arr)).unwrap() };
+
+        assert_eq!(err.to_string(), "Unsupported array VARTYPE 0x12");
+    }
+
+    #[test]
+    fn it_converts_float_array() {
+        let arr = create_array(VT_R4, 0, &[0.5f32, -2.0]);
+
+        let variant = Variant::Array(safe_array_to_vec(arr, VT_R4).unwrap());
+
+        unsafe { check_hres(SafeArrayDestroy(arr)).unwrap() };
+
+        assert_eq!(
+            variant,
+            Variant::Array(vec![Variant::R4(0.5), Variant::R4(-2.0)])
+        );
+        assert_eq!(Vec::<f32>::deserialize(variant.clone()).unwrap(), [0.5, -2.0]);
+        assert_eq!(Vec::<f64>::deserialize(variant).unwrap(), [0.5, -2.0]);
+    }
+}
@@ -0,0 +1,4 @@
+pub mod variant_ser;
+pub mod wbem_class_ser;
+
+pub use wbem_class_ser::to_wbem_class_obj;
@@ -0,0 +1,254 @@
+use crate::error::Error;
+use crate::variant::Variant;
+use failure::format_err;
+use serde::ser::{self, Impossible, Serialize};
+
+/// Serialize a single (scalar) value into a `Variant`, which can then be `Put` into a WMI object.
+///
+/// WMI expects `uint32` properties as `VT_I4`, and 64-bit integers as strings,
+/// so the values are converted accordingly.
+///
+pub struct VariantSerializer;
+
+fn unsupported(kind: &str) -> Error {
+    Error::from(format_err!(
+        "Serializing {} into a Variant is not supported",
+        kind
+    ))
+}
+
+impl ser::Serializer for VariantSerializer {
+    type Ok = Variant;
+    type Error = Error;
+
+    type SerializeSeq = Impossible<Variant, Error>;
+    type SerializeTuple = Impossible<Variant, Error>;
+    type SerializeTupleStruct = Impossible<Variant, Error>;
+    type SerializeTupleVariant = Impossible<Variant, Error>;
+    type SerializeMap = Impossible<Variant, Error>;
+    type SerializeStruct = Impossible<Variant, Error>;
+    type SerializeStructVariant = Impossible<Variant, Error>;
+
+    fn serialize_bool(self, v: bool) -> Result<Self::Ok, Self::Error> {
+        Ok(Variant::Bool(v))
+    }
+
+    fn serialize_i8(self, v: i8) -> Result<Self::Ok, Self::Error> {
+        Ok(Variant::I2(v.into()))
+    }
+
+    fn serialize_i16(self, v: i16) -> Result<Self::Ok, Self::Error> {
+        Ok(Variant::I2(v))
+    }
+
+    fn serialize_i32(self, v: i32) -> Result<Self::Ok, Self::Error> {
+        Ok(Variant::I4(v))
+    }
+
+    fn serialize_i64(self, v: i64) -> Result<Self::Ok, Self::Error> {
+        Ok(Variant::String(v.to_string()))
+    }
+
+    fn serialize_u8(self, v: u8) -> Result<Self::Ok, Self::Error> {
+        Ok(Variant::UI1(v))
+    }
+
+    fn serialize_u16(self, v: u16) -> Result<Self::Ok, Self::Error> {
+        Ok(Variant::I4(v.into()))
+    }
+
+    fn serialize_u32(self, v: u32) -> Result<Self::Ok, Self::Error> {
+        Ok(Variant::I4(v as i32))
+    }
+
+    fn serialize_u64(self, v: u64) -> Result<Self::Ok, Self::Error> {
+        Ok(Variant::String(v.to_string()))
+    }
+
+    fn serialize_f32(self, v: f32) -> Result<Self::Ok, Self::Error> {
+        Ok(Variant::R4(v))
+    }
+
+    fn serialize_f64(self, v: f64) -> Result<Self::Ok, Self::Error> {
+        Ok(Variant::R8(v))
+    }
+
+    fn serialize_char(self, v: char) -> Result<Self::Ok, Self::Error> {
+        Ok(Variant::String(v.to_string()))
+    }
+
+    fn serialize_str(self, v: &str) -> Result<Self::Ok, Self::Error> {
+        Ok(Variant::String(v.to_owned()))
+    }
+
+    fn serialize_bytes(self, _v: &[u8]) -> Result<Self::Ok, Self::Error> {
+        Err(unsupported("bytes"))
+    }
+
+    fn serialize_none(self) -> Result<Self::Ok, Self::Error> {
+        Ok(Variant::Null)
+    }
+
+    fn serialize_some<T>(self, value: &T) -> Result<Self::Ok, Self::Error>
+    where
+        T: ?Sized + Serialize,
+    {
+        value.serialize(self)
+    }
+
+    fn serialize_unit(self) -> Result<Self::Ok, Self::Error> {
+        Ok(Variant::Null)
+    }
+
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<Self::Ok, Self::Error> {
+        Ok(Variant::Null)
+    }
+
+    fn serialize_unit_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+    ) -> Result<Self::Ok, Self::Error> {
+        Ok(Variant::String(variant.to_owned()))
+    }
+
+    fn serialize_newtype_struct<T>(
+        self,
+        _name: &'static str,
+        value: &T,
+    ) -> Result<Self::Ok, Self::Error>
+    where
+        T: ?Sized + Serialize,
+    {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T>(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        _value: &T,
+    ) -> Result<Self::Ok, Self::Error>
+    where
+        T: ?Sized + Serialize,
+    {
+        Err(unsupported("enum variants"))
+    }
+
+    fn serialize_seq(self, _len: Option<usize>) -> Result<Self::SerializeSeq, Self::Error> {
+        Err(unsupported("sequences"))
+    }
+
+    fn serialize_tuple(self, _len: usize) -> Result<Self::SerializeTuple, Self::Error> {
+        Err(unsupported("tuples"))
+    }
+
+    fn serialize_tuple_struct(
+        self,
+        _name: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeTupleStruct, Self::Error> {
+        Err(unsupported("tuple structs"))
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeTupleVariant, Self::Error> {
+        Err(unsupported("enum variants"))
+    }
+
+    fn serialize_map(self, _len: Option<usize>) -> Result<Self::SerializeMap, Self::Error> {
+        Err(unsupported("maps"))
+    }
+
+    fn serialize_struct(
+        self,
+        _name: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeStruct, Self::Error> {
+        Err(unsupported("nested structs"))
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeStructVariant, Self::Error> {
+        Err(unsupported("enum variants"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_serializes_scalars() {
+        assert_eq!(
+            true.serialize(VariantSerializer).unwrap(),
+            Variant::Bool(true)
+        );
+        assert_eq!(42u8.serialize(VariantSerializer).unwrap(), Variant::UI1(42));
+        assert_eq!(
+            (-2i16).serialize(VariantSerializer).unwrap(),
+            Variant::I2(-2)
+        );
+        assert_eq!(
+            (-4i32).serialize(VariantSerializer).unwrap(),
+            Variant::I4(-4)
+        );
+        assert_eq!(
+            "C:\\".serialize(VariantSerializer).unwrap(),
+            Variant::String("C:\\".into())
+        );
+    }
+
+    #[test]
+    fn it_serializes_floats_and_options() {
+        assert_eq!(
+            0.5f32.serialize(VariantSerializer).unwrap(),
+            Variant::R4(0.5)
+        );
+        assert_eq!(
+            (-1.25f64).serialize(VariantSerializer).unwrap(),
+            Variant::R8(-1.25)
+        );
+        assert_eq!(
+            Some(1i32).serialize(VariantSerializer).unwrap(),
+            Variant::I4(1)
+        );
+        assert_eq!(
+            None::<String>.serialize(VariantSerializer).unwrap(),
+            Variant::Null
+        );
+    }
+
+    #[test]
+    fn it_serializes_wmi_integer_representations() {
+        assert_eq!(
+            0x8000_0002u32.serialize(VariantSerializer).unwrap(),
+            Variant::I4(0x8000_0002u32 as i32)
+        );
+        assert_eq!(
+            u64::max_value().serialize(VariantSerializer).unwrap(),
+            Variant::String("18446744073709551615".into())
+        );
+        assert_eq!(
+            (-8i64).serialize(VariantSerializer).unwrap(),
+            Variant::String("-8".into())
+        );
+    }
+
+    #[test]
+    fn it_fails_to_serialize_sequences() {
+        assert!(vec![1, 2].serialize(VariantSerializer).is_err());
+    }
+}
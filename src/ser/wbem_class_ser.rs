@@ -0,0 +1,533 @@
+use crate::error::Error;
+use crate::result_enumerator::IWbemClassWrapper;
+use crate::ser::variant_ser::VariantSerializer;
+use failure::format_err;
+use serde::ser::{self, Impossible, Serialize};
+
+/// A serializer which writes the fields of a struct into the properties of a WMI object
+/// (the inverse of `de::wbem_class_de::Deserializer`).
+///
+pub struct Serializer<'a> {
+    pub wbem_class_obj: &'a IWbemClassWrapper,
+}
+
+impl<'a> Serializer<'a> {
+    pub fn to_wbem_class_obj(wbem_class_obj: &'a IWbemClassWrapper) -> Self {
+        Serializer { wbem_class_obj }
+    }
+}
+
+/// Write every field of `value` into the property with the same name in `wbem_class_obj`.
+///
+/// Only structs with scalar fields are supported: strings, integers, bools, floats
+/// and options (where `None` sets the property to null).
+/// Arrays and nested objects are not supported yet.
+///
+pub fn to_wbem_class_obj<T>(wbem_class_obj: &IWbemClassWrapper, value: &T) -> Result<(), Error>
+where
+    T: ?Sized + Serialize,
+{
+    let mut serializer = Serializer::to_wbem_class_obj(wbem_class_obj);
+
+    value.serialize(&mut serializer)
+}
+
+fn unsupported(kind: &str) -> Error {
+    Error::from(format_err!(
+        "Only structs can be serialized into a WMI object, got {}",
+        kind
+    ))
+}
+
+impl<'a, 'b> ser::SerializeStruct for &'b mut Serializer<'a> {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_field<T>(&mut self, key: &'static str, value: &T) -> Result<(), Self::Error>
+    where
+        T: ?Sized + Serialize,
+    {
+        let property_value = value.serialize(VariantSerializer)?;
+
+        self.wbem_class_obj.put_property(key, &property_value)?;
+
+        Ok(())
+    }
+
+    fn end(self) -> Result<Self::Ok, Self::Error> {
+        Ok(())
+    }
+}
+
+impl<'a, 'b> ser::Serializer for &'b mut Serializer<'a> {
+    type Ok = ();
+    type Error = Error;
+
+    type SerializeSeq = Impossible<(), Error>;
+    type SerializeTuple = Impossible<(), Error>;
+    type SerializeTupleStruct = Impossible<(), Error>;
+    type SerializeTupleVariant = Impossible<(), Error>;
+    type SerializeMap = Impossible<(), Error>;
+    type SerializeStruct = Self;
+    type SerializeStructVariant = Impossible<(), Error>;
+
+    fn serialize_struct(
+        self,
+        _name: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeStruct, Self::Error> {
+        Ok(self)
+    }
+
+    /// A struct without any fields does not change the object.
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<Self::Ok, Self::Error> {
+        Ok(())
+    }
+
+    fn serialize_bool(self, _v: bool) -> Result<Self::Ok, Self::Error> {
+        Err(unsupported("bool"))
+    }
+
+    fn serialize_i8(self, _v: i8) -> Result<Self::Ok, Self::Error> {
+        Err(unsupported("i8"))
+    }
+
+    fn serialize_i16(self, _v: i16) -> Result<Self::Ok, Self::Error> {
+        Err(unsupported("i16"))
+    }
+
+    fn serialize_i32(self, _v: i32) -> Result<Self::Ok, Self::Error> {
+        Err(unsupported("i32"))
+    }
+
+    fn serialize_i64(self, _v: i64) -> Result<Self::Ok, Self::Error> {
+        Err(unsupported("i64"))
+    }
+
+    fn serialize_u8(self, _v: u8) -> Result<Self::Ok, Self::Error> {
+        Err(unsupported("u8"))
+    }
+
+    fn serialize_u16(self, _v: u16) -> Result<Self::Ok, Self::Error> {
+        Err(unsupported("u16"))
+    }
+
+    fn serialize_u32(self, _v: u32) -> Result<Self::Ok, Self::Error> {
+        Err(unsupported("u32"))
+    }
+
+    fn serialize_u64(self, _v: u64) -> Result<Self::Ok, Self::Error> {
+        Err(unsupported("u64"))
+    }
+
+    fn serialize_f32(self, _v: f32) -> Result<Self::Ok, Self::Error> {
+        Err(unsupported("f32"))
+    }
+
+    fn serialize_f64(self, _v: f64) -> Result<Self::Ok, Self::Error> {
+        Err(unsupported("f64"))
+    }
+
+    fn serialize_char(self, _v: char) -> Result<Self::Ok, Self::Error> {
+        Err(unsupported("char"))
+    }
+
+    fn serialize_str(self, _v: &str) -> Result<Self::Ok, Self::Error> {
+        Err(unsupported("str"))
+    }
+
+    fn serialize_bytes(self, _v: &[u8]) -> Result<Self::Ok, Self::Error> {
+        Err(unsupported("bytes"))
+    }
+
+    fn serialize_none(self) -> Result<Self::Ok, Self::Error> {
+        Err(unsupported("None"))
+    }
+
+    fn serialize_some<T>(self, value: &T) -> Result<Self::Ok, Self::Error>
+    where
+        T: ?Sized + Serialize,
+    {
+        value.serialize(self)
+    }
+
+    fn serialize_unit(self) -> Result<Self::Ok, Self::Error> {
+        Err(unsupported("unit"))
+    }
+
+    fn serialize_unit_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+    ) -> Result<Self::Ok, Self::Error> {
+        Err(unsupported("an enum variant"))
+    }
+
+    fn serialize_newtype_struct<T>(
+        self,
+        _name: &'static str,
+        value: &T,
+    ) -> Result<Self::Ok, Self::Error>
+    where
+        T: ?Sized + Serialize,
+    {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T>(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        _value: &T,
+    ) -> Result<Self::Ok, Self::Error>
+    where
+        T: ?Sized + Serialize,
+    {
+        Err(unsupported("an enum variant"))
+    }
+
+    fn serialize_seq(self, _len: Option<usize>) -> Result<Self::SerializeSeq, Self::Error> {
+        Err(unsupported("a sequence"))
+    }
+
+    fn serialize_tuple(self, _len: usize) -> Result<Self::SerializeTuple, Self::Error> {
+        Err(unsupported("a tuple"))
+    }
+
+    fn serialize_tuple_struct(
+        self,
+        _name: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeTupleStruct, Self::Error> {
+        Err(unsupported("a tuple struct"))
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeTupleVariant, Self::Error> {
+        Err(unsupported("an enum variant"))
+    }
+
+    fn serialize_map(self, _len: Option<usize>) -> Result<Self::SerializeMap, Self::Error> {
+        Err(unsupported("a map"))
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeStructVariant, Self::Error> {
+        Err(unsupported("an enum variant"))
+    }
+}
+
+/// Collect the names of the fields of `value`, without serializing their values.
+///
+/// Like `to_wbem_class_obj`, only structs are supported.
+///
+pub(crate) fn field_names<T>(value: &T) -> Result<Vec<&'static str>, Error>
+where
+    T: ?Sized + Serialize,
+{
+    let mut collector = FieldNames(vec![]);
+
+    value.serialize(&mut collector)?;
+
+    Ok(collector.0)
+}
+
+struct FieldNames(Vec<&'static str>);
+
+impl ser::SerializeStruct for &mut FieldNames {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_field<T>(&mut self, key: &'static str, _value: &T) -> Result<(), Self::Error>
+    where
+        T: ?Sized + Serialize,
+    {
+        self.0.push(key);
+
+        Ok(())
+    }
+
+    fn end(self) -> Result<Self::Ok, Self::Error> {
+        Ok(())
+    }
+}
+
+impl ser::Serializer for &mut FieldNames {
+    type Ok = ();
+    type Error = Error;
+
+    type SerializeSeq = Impossible<(), Error>;
+    type SerializeTuple = Impossible<(), Error>;
+    type SerializeTupleStruct = Impossible<(), Error>;
+    type SerializeTupleVariant = Impossible<(), Error>;
+    type SerializeMap = Impossible<(), Error>;
+    type SerializeStruct = Self;
+    type SerializeStructVariant = Impossible<(), Error>;
+
+    fn serialize_struct(
+        self,
+        _name: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeStruct, Self::Error> {
+        Ok(self)
+    }
+
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<Self::Ok, Self::Error> {
+        Ok(())
+    }
+
+    fn serialize_bool(self, _v: bool) -> Result<Self::Ok, Self::Error> {
+        Err(unsupported("bool"))
+    }
+
+    fn serialize_i8(self, _v: i8) -> Result<Self::Ok, Self::Error> {
+        Err(unsupported("i8"))
+    }
+
+    fn serialize_i16(self, _v: i16) -> Result<Self::Ok, Self::Error> {
+        Err(unsupported("i16"))
+    }
+
+    fn serialize_i32(self, _v: i32) -> Result<Self::Ok, Self::Error> {
+        Err(unsupported("i32"))
+    }
+
+    fn serialize_i64(self, _v: i64) -> Result<Self::Ok, Self::Error> {
+        Err(unsupported("i64"))
+    }
+
+    fn serialize_u8(self, _v: u8) -> Result<Self::Ok, Self::Error> {
+        Err(unsupported("u8"))
+    }
+
+    fn serialize_u16(self, _v: u16) -> Result<Self::Ok, Self::Error> {
+        Err(unsupported("u16"))
+    }
+
+    fn serialize_u32(self, _v: u32) -> Result<Self::Ok, Self::Error> {
+        Err(unsupported("u32"))
+    }
+
+    fn serialize_u64(self, _v: u64) -> Result<Self::Ok, Self::Error> {
+        Err(unsupported("u64"))
+    }
+
+    fn serialize_f32(self, _v: f32) -> Result<Self::Ok, Self::Error> {
+        Err(unsupported("f32"))
+    }
+
+    fn serialize_f64(self, _v: f64) -> Result<Self::Ok, Self::Error> {
+        Err(unsupported("f64"))
+    }
+
+    fn serialize_char(self, _v: char) -> Result<Self::Ok, Self::Error> {
+        Err(unsupported("char"))
+    }
+
+    fn serialize_str(self, _v: &str) -> Result<Self::Ok, Self::Error> {
+        Err(unsupported("str"))
+    }
+
+    fn serialize_bytes(self, _v: &[u8]) -> Result<Self::Ok, Self::Error> {
+        Err(unsupported("bytes"))
+    }
+
+    fn serialize_none(self) -> Result<Self::Ok, Self::Error> {
+        Err(unsupported("None"))
+    }
+
+    fn serialize_some<T>(self, value: &T) -> Result<Self::Ok, Self::Error>
+    where
+        T: ?Sized + Serialize,
+    {
+        value.serialize(self)
+    }
+
+    fn serialize_unit(self) -> Result<Self::Ok, Self::Error> {
+        Err(unsupported("unit"))
+    }
+
+    fn serialize_unit_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+    ) -> Result<Self::Ok, Self::Error> {
+        Err(unsupported("an enum variant"))
+    }
+
+    fn serialize_newtype_struct<T>(
+        self,
+        _name: &'static str,
+        value: &T,
+    ) -> Result<Self::Ok, Self::Error>
+    where
+        T: ?Sized + Serialize,
+    {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T>(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        _value: &T,
+    ) -> Result<Self::Ok, Self::Error>
+    where
+        T: ?Sized + Serialize,
+    {
+        Err(unsupported("an enum variant"))
+    }
+
+    fn serialize_seq(self, _len: Option<usize>) -> Result<Self::SerializeSeq, Self::Error> {
+        Err(unsupported("a sequence"))
+    }
+
+    fn serialize_tuple(self, _len: usize) -> Result<Self::SerializeTuple, Self::Error> {
+        Err(unsupported("a tuple"))
+    }
+
+    fn serialize_tuple_struct(
+        self,
+        _name: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeTupleStruct, Self::Error> {
+        Err(unsupported("a tuple struct"))
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeTupleVariant, Self::Error> {
+        Err(unsupported("an enum variant"))
+    }
+
+    fn serialize_map(self, _len: Option<usize>) -> Result<Self::SerializeMap, Self::Error> {
+        Err(unsupported("a map"))
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeStructVariant, Self::Error> {
+        Err(unsupported("an enum variant"))
+    }
+}
+
+#[allow(non_snake_case)]
+#[allow(non_camel_case_types)]
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::connection::WMIConnection;
+    use crate::de::wbem_class_de::from_wbem_class_obj;
+    use crate::variant::Variant;
+    use serde::{Deserialize, Serialize};
+
+    use crate::tests::fixtures::*;
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    struct Sample {
+        Name: String,
+        Count: i32,
+        Flags: u8,
+        Level: i16,
+        Enabled: bool,
+        Ratio: f64,
+        Scale: f32,
+        Description: Option<String>,
+    }
+
+    /// Define a new class, and create its properties using `sample`.
+    fn new_class(wmi_con: &WMIConnection, sample: &Sample) -> IWbemClassWrapper {
+        let class = new_class_obj(wmi_con, "WMIRS_Sample");
+
+        to_wbem_class_obj(&class, sample).unwrap();
+
+        class
+    }
+
+    #[test]
+    fn it_round_trips_a_struct() {
+        let wmi_con = wmi_con();
+
+        let sample = Sample {
+            Name: "wmi-rs".into(),
+            Count: -42,
+            Flags: 7,
+            Level: 3,
+            Enabled: true,
+            Ratio: 0.25,
+            Scale: 1.5,
+            Description: Some("A sample".into()),
+        };
+
+        let class = new_class(&wmi_con, &sample);
+        let instance = class.spawn_instance().unwrap();
+
+        to_wbem_class_obj(&instance, &sample).unwrap();
+
+        let res: Sample = from_wbem_class_obj(&instance).unwrap();
+
+        assert_eq!(res, sample);
+    }
+
+    #[test]
+    fn it_round_trips_a_null_property() {
+        let wmi_con = wmi_con();
+
+        let mut sample = Sample {
+            Name: "wmi-rs".into(),
+            Count: 1,
+            Flags: 0,
+            Level: 0,
+            Enabled: false,
+            Ratio: 0.0,
+            Scale: 0.0,
+            Description: Some("A sample".into()),
+        };
+
+        let class = new_class(&wmi_con, &sample);
+        let instance = class.spawn_instance().unwrap();
+
+        sample.Description = None;
+
+        to_wbem_class_obj(&instance, &sample).unwrap();
+
+        assert_eq!(instance.get_property("Description").unwrap(), Variant::Null);
+
+        let res: Sample = from_wbem_class_obj(&instance).unwrap();
+
+        assert_eq!(res, sample);
+    }
+
+    #[test]
+    fn it_fails_to_serialize_a_non_struct() {
+        let wmi_con = wmi_con();
+
+        let class = wmi_con.get_object("Win32_OperatingSystem").unwrap();
+        let instance = class.spawn_instance().unwrap();
+
+        assert!(to_wbem_class_obj(&instance, &42).is_err());
+        assert!(to_wbem_class_obj(&instance, &vec!["a"]).is_err());
+    }
+}
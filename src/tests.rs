@@ -1,5 +1,343 @@
+use crate::result_enumerator::IWbemClassWrapper;
+use crate::utils::check_hres;
+use crate::variant::Variant;
 use crate::COMLibrary;
 use crate::WMIConnection;
+use std::cell::{Cell, RefCell};
+use std::collections::VecDeque;
+use std::mem;
+use std::ptr::{self, NonNull};
+use widestring::WideCString;
+use winapi::ctypes::c_void;
+use winapi::shared::guiddef::REFIID;
+use winapi::shared::minwindef::ULONG;
+use winapi::shared::ntdef::{HRESULT, LONG};
+use winapi::shared::winerror::{E_NOINTERFACE, E_NOTIMPL, S_FALSE, S_OK};
+use winapi::shared::wtypes::{BSTR, VARTYPE, VT_UNKNOWN};
+use winapi::um::oaidl::VARIANT;
+use winapi::um::oleauto::VariantClear;
+use winapi::um::unknwnbase::{IUnknown, IUnknownVtbl};
+use winapi::um::wbemcli::{
+    IEnumWbemClassObject, IEnumWbemClassObjectVtbl, IWbemCallResult, IWbemClassObject,
+    IWbemContext, IWbemObjectSink, IWbemServices, IWbemServicesVtbl, CIM_OBJECT, CIM_STRING,
+};
+
+thread_local! {
+    // Keeps COM initialized on the thread for as long as the objects created by `make_object` are used.
+    static FIXTURE_CON: WMIConnection = fixtures::wmi_con();
+}
+
+/// Create an in-memory object with the given properties, which can be deserialized
+/// (for example, using [`from_wbem_class_obj`](crate::de::wbem_class_de::from_wbem_class_obj))
+/// without querying a live class.
+///
+/// The object's class is `WMIRS_Fixture`, unless a `__CLASS` property is given.
+/// The type of each property is inferred from its value, except for `Null`s
+/// which are declared as `string` properties. Arrays are not supported.
+///
+/// The object is spawned from a class created using a live WMI service, so this
+/// needs COM and the WMI service to be available (like the rest of the tests).
+/// This module is only built for tests and with the `test` feature, which is why
+/// the example isn't run as a doctest.
+///
+/// ```edition2018,ignore
+/// # use wmi::*;
+/// use wmi::de::wbem_class_de::from_wbem_class_obj;
+/// use wmi::tests::make_object;
+/// use serde::Deserialize;
+///
+/// #[derive(Deserialize)]
+/// struct Fixture {
+///     Name: String,
+/// }
+///
+/// let obj = make_object(&[("Name", Variant::String("A".into()))]);
+/// let fixture: Fixture = from_wbem_class_obj(&obj).unwrap();
+///
+/// assert_eq!(fixture.Name, "A");
+/// ```
+pub fn make_object(properties: &[(&str, Variant)]) -> IWbemClassWrapper {
+    FIXTURE_CON.with(|wmi_con| {
+        let obj = fixtures::new_class_obj(wmi_con, "WMIRS_Fixture");
+
+        for (name, value) in properties {
+            match value {
+                Variant::Null | Variant::Empty => put_null_property(&obj, name),
+                value => obj.put_property(name, value).unwrap(),
+            }
+        }
+
+        obj
+    })
+}
+
+/// Create an in-memory event object (of class `WMIRS_Event`), whose `TargetInstance`
+/// is the given object (embedded like in an `__InstanceCreationEvent`).
+///
+pub fn make_event(target_instance: &IWbemClassWrapper) -> IWbemClassWrapper {
+    FIXTURE_CON.with(|wmi_con| {
+        let event = fixtures::new_class_obj(wmi_con, "WMIRS_Event");
+
+        let name = WideCString::from_str("TargetInstance").unwrap();
+        let mut vt_prop: VARIANT = unsafe { mem::zeroed() };
+
+        // `Put` copies the value (and references the object), so the variant isn't cleared.
+        unsafe {
+            let n2 = vt_prop.n1.n2_mut();
+            n2.vt = VT_UNKNOWN as VARTYPE;
+            *n2.n3.punkVal_mut() = target_instance.inner.unwrap().as_ptr() as *mut IUnknown;
+
+            check_hres((*event.inner.unwrap().as_ptr()).Put(
+                name.as_ptr(),
+                0,
+                &mut vt_prop,
+                CIM_OBJECT as i32,
+            ))
+            .unwrap();
+        }
+
+        event
+    })
+}
+
+/// A fake `IEnumWbemClassObject`, which returns the given objects one by one
+/// and counts how many times it was released, so tests can check that
+/// an enumerator is released exactly once (without a live query).
+///
+/// The mock is owned by the test (releasing it doesn't free it), so it must outlive
+/// any `QueryResultEnumerator` using its pointer.
+///
+#[repr(C)]
+pub struct MockEnumerator {
+    // Must be the first field, so a pointer to the mock is a valid `IEnumWbemClassObject`.
+    vtbl: *const IEnumWbemClassObjectVtbl,
+    objects: RefCell<VecDeque<IWbemClassWrapper>>,
+    release_count: Cell<u32>,
+}
+
+static MOCK_ENUMERATOR_VTBL: IEnumWbemClassObjectVtbl = IEnumWbemClassObjectVtbl {
+    parent: IUnknownVtbl {
+        QueryInterface: mock_query_interface,
+        AddRef: mock_add_ref,
+        Release: mock_release,
+    },
+    Reset: mock_reset,
+    Next: mock_next,
+    NextAsync: mock_next_async,
+    Clone: mock_clone,
+    Skip: mock_skip,
+};
+
+impl MockEnumerator {
+    pub fn new(objects: Vec<IWbemClassWrapper>) -> Box<Self> {
+        Box::new(Self {
+            vtbl: &MOCK_ENUMERATOR_VTBL,
+            objects: RefCell::new(objects.into()),
+            release_count: Cell::new(0),
+        })
+    }
+
+    pub fn as_ptr(&self) -> *mut IEnumWbemClassObject {
+        self as *const Self as *mut IEnumWbemClassObject
+    }
+
+    /// How many times `Release` was called.
+    pub fn release_count(&self) -> u32 {
+        self.release_count.get()
+    }
+}
+
+unsafe fn mock_from<'a, T>(this: *mut T) -> &'a MockEnumerator {
+    &*(this as *const MockEnumerator)
+}
+
+unsafe extern "system" fn mock_query_interface(
+    _this: *mut IUnknown,
+    _riid: REFIID,
+    ppv: *mut *mut c_void,
+) -> HRESULT {
+    *ppv = ptr::null_mut();
+
+    E_NOINTERFACE
+}
+
+unsafe extern "system" fn mock_add_ref(_this: *mut IUnknown) -> ULONG {
+    1
+}
+
+unsafe extern "system" fn mock_release(this: *mut IUnknown) -> ULONG {
+    let mock = mock_from(this);
+
+    mock.release_count.set(mock.release_count.get() + 1);
+
+    1
+}
+
+unsafe extern "system" fn mock_reset(_this: *mut IEnumWbemClassObject) -> HRESULT {
+    E_NOTIMPL
+}
+
+/// Return (at most) one object per call, passing its reference to the caller.
+unsafe extern "system" fn mock_next(
+    this: *mut IEnumWbemClassObject,
+    _timeout: LONG,
+    count: ULONG,
+    objects: *mut *mut IWbemClassObject,
+    returned: *mut ULONG,
+) -> HRESULT {
+    let mock = mock_from(this);
+
+    match mock.objects.borrow_mut().pop_front() {
+        Some(obj) if count > 0 => {
+            *objects = obj.inner.unwrap().as_ptr();
+            *returned = 1;
+            mem::forget(obj);
+
+            S_OK
+        }
+        _ => {
+            *returned = 0;
+
+            S_FALSE
+        }
+    }
+}
+
+unsafe extern "system" fn mock_next_async(
+    _this: *mut IEnumWbemClassObject,
+    _count: ULONG,
+    _sink: *mut IWbemObjectSink,
+) -> HRESULT {
+    E_NOTIMPL
+}
+
+unsafe extern "system" fn mock_clone(
+    _this: *mut IEnumWbemClassObject,
+    _enum: *mut *mut IEnumWbemClassObject,
+) -> HRESULT {
+    E_NOTIMPL
+}
+
+unsafe extern "system" fn mock_skip(
+    _this: *mut IEnumWbemClassObject,
+    _timeout: LONG,
+    _count: ULONG,
+) -> HRESULT {
+    E_NOTIMPL
+}
+
+/// Wraps a real `IWbemServices`, recording the context passed to each `ExecQuery`
+/// and `GetObject` call (which are then forwarded to it), so tests can check that
+/// a context is passed to WMI.
+///
+/// Only `AddRef`, `Release`, `ExecQuery` and `GetObject` can be called on the spy:
+/// the rest of its vtable is copied from the wrapped pointer, and expects it as `this`.
+/// Like [`MockEnumerator`], the spy must outlive any connection using its pointer.
+///
+#[repr(C)]
+pub struct ServicesSpy {
+    // Must be the first field, so a pointer to the spy is a valid `IWbemServices`.
+    vtbl: *const IWbemServicesVtbl,
+    inner: *mut IWbemServices,
+    contexts: RefCell<Vec<*mut IWbemContext>>,
+    // Owns the vtable `vtbl` points to.
+    _vtbl: Box<IWbemServicesVtbl>,
+}
+
+impl ServicesSpy {
+    /// Wrap `inner`, without taking a reference to it
+    /// (the reference is passed on, to whoever releases the spy).
+    ///
+    /// # Safety
+    ///
+    /// `inner` must be a valid `IWbemServices` pointer, which outlives the spy.
+    ///
+    pub unsafe fn new(inner: *mut IWbemServices) -> Box<Self> {
+        let mut vtbl = Box::new(ptr::read((*inner).lpVtbl));
+
+        vtbl.parent.QueryInterface = mock_query_interface;
+        vtbl.parent.AddRef = spy_add_ref;
+        vtbl.parent.Release = spy_release;
+        vtbl.GetObject = spy_get_object;
+        vtbl.ExecQuery = spy_exec_query;
+
+        Box::new(Self {
+            vtbl: &*vtbl,
+            inner,
+            contexts: RefCell::new(vec![]),
+            _vtbl: vtbl,
+        })
+    }
+
+    pub fn as_ptr(&self) -> *mut IWbemServices {
+        self as *const Self as *mut IWbemServices
+    }
+
+    /// The context of every `ExecQuery` and `GetObject` call, in order (null when none was given).
+    pub fn contexts(&self) -> Vec<*mut IWbemContext> {
+        self.contexts.borrow().clone()
+    }
+}
+
+unsafe fn spy_from<'a, T>(this: *mut T) -> &'a ServicesSpy {
+    &*(this as *const ServicesSpy)
+}
+
+unsafe extern "system" fn spy_add_ref(this: *mut IUnknown) -> ULONG {
+    (*spy_from(this).inner).AddRef()
+}
+
+unsafe extern "system" fn spy_release(this: *mut IUnknown) -> ULONG {
+    (*spy_from(this).inner).Release()
+}
+
+unsafe extern "system" fn spy_get_object(
+    this: *mut IWbemServices,
+    object_path: BSTR,
+    flags: LONG,
+    ctx: *mut IWbemContext,
+    object: *mut *mut IWbemClassObject,
+    call_result: *mut *mut IWbemCallResult,
+) -> HRESULT {
+    let spy = spy_from(this);
+
+    spy.contexts.borrow_mut().push(ctx);
+
+    (*spy.inner).GetObject(object_path, flags, ctx, object, call_result)
+}
+
+unsafe extern "system" fn spy_exec_query(
+    this: *mut IWbemServices,
+    query_language: BSTR,
+    query: BSTR,
+    flags: LONG,
+    ctx: *mut IWbemContext,
+    enumerator: *mut *mut IEnumWbemClassObject,
+) -> HRESULT {
+    let spy = spy_from(this);
+
+    spy.contexts.borrow_mut().push(ctx);
+
+    (*spy.inner).ExecQuery(query_language, query, flags, ctx, enumerator)
+}
+
+/// A property can't be created using a null value without also declaring its type.
+fn put_null_property(obj: &IWbemClassWrapper, name: &str) {
+    let name = WideCString::from_str(name).unwrap();
+    let mut vt_prop = Variant::Null.to_variant().unwrap();
+
+    unsafe {
+        check_hres((*obj.inner.unwrap().as_ptr()).Put(
+            name.as_ptr(),
+            0,
+            &mut vt_prop,
+            CIM_STRING as i32,
+        ))
+        .unwrap();
+
+        VariantClear(&mut vt_prop);
+    }
+}
 
 pub mod fixtures {
     use super::*;
@@ -17,4 +355,28 @@ pub mod fixtures {
 
         wmi_con
     }
+
+    /// Define a new (empty) class, whose properties can be created using `put_property`.
+    pub fn new_class_obj(wmi_con: &WMIConnection, class_name: &str) -> IWbemClassWrapper {
+        let mut p_class = ptr::null_mut::<IWbemClassObject>();
+
+        unsafe {
+            check_hres((*wmi_con.svc()).GetObject(
+                ptr::null_mut(),
+                0,
+                ptr::null_mut(),
+                &mut p_class,
+                ptr::null_mut(),
+            ))
+            .unwrap();
+        }
+
+        let class = IWbemClassWrapper::new(NonNull::new(p_class));
+
+        class
+            .put_property("__CLASS", &Variant::String(class_name.into()))
+            .unwrap();
+
+        class
+    }
 }
@@ -1,17 +1,432 @@
-use failure::Fail;
-use winapi::shared::ntdef::HRESULT;
+use failure::{format_err, Error};
+use std::convert::TryFrom;
+use thiserror::Error as ThisError;
+use widestring::WideStr;
+use winapi::shared::{
+    ntdef::HRESULT,
+    winerror::{E_OUTOFMEMORY, RPC_E_DISCONNECTED},
+    wtypes::BSTR,
+};
+use winapi::um::oleauto::SysStringLen;
+use winapi::um::wbemcli::{
+    WBEM_E_INVALID_CLASS, WBEM_E_INVALID_OBJECT_PATH, WBEM_E_NOT_FOUND, WBEM_E_OUT_OF_MEMORY,
+    WBEM_E_PROVIDER_LOAD_FAILURE, WBEM_E_PROVIDER_NOT_FOUND, WBEM_E_SERVER_TOO_BUSY,
+    WBEM_E_TIMED_OUT, WBEM_E_TRANSPORT_FAILURE,
+};
 
-#[derive(Debug, Fail)]
+// `HRESULT_FROM_WIN32(RPC_S_SERVER_UNAVAILABLE)` and `HRESULT_FROM_WIN32(RPC_S_CALL_FAILED)`.
+const RPC_SERVER_UNAVAILABLE: u32 = 0x8007_06BA;
+const RPC_CALL_FAILED: u32 = 0x8007_06BE;
+
+/// Errors originating from WMI calls (and from parsing WMI data).
+///
+/// This implements `std::error::Error` (and is `Send + Sync + 'static`),
+/// so it can be boxed into a `Box<dyn std::error::Error + Send + Sync>`,
+/// while still converting into a `failure::Error` via `?`.
+///
+#[derive(Debug, ThisError)]
 pub enum WMIError {
-    #[fail(display = "HRESULT Call failed with: {:#X}", hres)]
+    #[error("HRESULT Call failed with: {hres:#X}")]
     HResultError { hres: HRESULT },
+    #[error(
+        "The WMI provider for this class is not registered (WBEM_E_PROVIDER_NOT_FOUND). \
+         The WMI repository might be damaged, try running `winmgmt /verifyrepository`"
+    )]
+    ProviderNotFound,
+    #[error(
+        "The WMI provider for this class failed to load (WBEM_E_PROVIDER_LOAD_FAILURE). \
+         The WMI repository might be damaged, try running `winmgmt /verifyrepository`"
+    )]
+    ProviderLoadFailure,
+    #[error("No results returned")]
+    NoResults,
+    #[error("The query succeeded, but WMI did not return an enumerator for its results")]
+    NullEnumerator,
+    #[error("Invalid object path: {path:?}")]
+    InvalidObjectPath { path: String },
+    /// A query failed, with the error of the underlying WMI call as the `source`.
+    #[error("query {query:?} in namespace {namespace} failed: {source}")]
+    Query {
+        query: String,
+        namespace: String,
+        source: Box<WMIError>,
+    },
+    /// An `ORDER BY` query was rejected as invalid, which most providers do for any ordering,
+    /// with the error of the query as the `source`.
+    #[error("{source} (the provider might not support ORDER BY)")]
+    OrderByNotSupported { source: Box<WMIError> },
+    /// A property could not be read, or its value could not be deserialized into its field,
+    /// with the underlying error (for example, naming the value and the expected type) as the `source`.
+    #[error("Failed to deserialize property {field}: {source}")]
+    Deserialization {
+        field: String,
+        source: Box<dyn std::error::Error + Send + Sync>,
+    },
+    #[error("Failed to parse datetime: {0}")]
+    ParseDatetimeError(#[from] chrono::format::ParseError),
+    #[error("Failed to parse datetime UTC offset: {0}")]
+    ParseDatetimeOffsetError(#[from] std::num::ParseIntError),
+}
+
+impl WMIError {
+    /// Map a failed `HRESULT` to an error, using a dedicated variant for the codes which
+    /// indicate a configuration problem (rather than an error in the query itself).
+    pub fn from_hres(hres: HRESULT) -> Self {
+        match hres as u32 {
+            WBEM_E_PROVIDER_NOT_FOUND => WMIError::ProviderNotFound,
+            WBEM_E_PROVIDER_LOAD_FAILURE => WMIError::ProviderLoadFailure,
+            _ => WMIError::HResultError { hres },
+        }
+    }
+
+    /// The error of the underlying WMI call: the `source` of a [`Query`](WMIError::Query)
+    /// (or an [`OrderByNotSupported`](WMIError::OrderByNotSupported)) error, or this error itself.
+    pub fn without_context(&self) -> &WMIError {
+        match self {
+            WMIError::Query { source, .. } => source.without_context(),
+            WMIError::OrderByNotSupported { source } => source.without_context(),
+            other => other,
+        }
+    }
+
+    /// Whether the error is (likely) transient, so the same call might succeed if retried:
+    /// the WMI service or a provider is busy or out of memory, or the RPC connection failed.
+    ///
+    /// A `Query` error is classified by its `source`.
+    pub fn is_retryable(&self) -> bool {
+        match self.without_context() {
+            WMIError::HResultError { hres } => match *hres as u32 {
+                WBEM_E_SERVER_TOO_BUSY
+                | WBEM_E_OUT_OF_MEMORY
+                | WBEM_E_TIMED_OUT
+                | WBEM_E_TRANSPORT_FAILURE
+                | RPC_SERVER_UNAVAILABLE
+                | RPC_CALL_FAILED => true,
+                _ => *hres == E_OUTOFMEMORY || *hres == RPC_E_DISCONNECTED,
+            },
+            _ => false,
+        }
+    }
+
+    /// Whether the error means the requested object (or class) doesn't exist,
+    /// including a malformed object path and a query which returned no results.
+    ///
+    /// A `Query` error is classified by its `source`.
+    pub fn is_not_found(&self) -> bool {
+        match self.without_context() {
+            WMIError::NoResults | WMIError::InvalidObjectPath { .. } => true,
+            WMIError::HResultError { hres } => matches!(
+                *hres as u32,
+                WBEM_E_NOT_FOUND | WBEM_E_INVALID_OBJECT_PATH | WBEM_E_INVALID_CLASS
+            ),
+            _ => false,
+        }
+    }
 }
 
 pub fn check_hres(hres: HRESULT) -> Result<(), WMIError> {
     if hres < 0 {
         dbg!(hres);
-        return Err(WMIError::HResultError { hres });
+        return Err(WMIError::from_hres(hres));
     }
 
     Ok(())
 }
+
+/// Convert a `BSTR` to a `String`.
+///
+/// The length is read from the `BSTR`'s length prefix (using `SysStringLen`),
+/// so embedded nulls are preserved. A null `BSTR` is an empty string.
+///
+/// Invalid UTF-16 (like an unpaired surrogate) is replaced with `U+FFFD`,
+/// so a single malformed value doesn't fail an entire query.
+///
+/// # Safety
+///
+/// `bstr` must be either null or a valid `BSTR`.
+pub unsafe fn bstr_to_string(bstr: BSTR) -> Result<String, Error> {
+    if bstr.is_null() {
+        return Ok(String::new());
+    }
+
+    let len = unsafe { SysStringLen(bstr) } as usize;
+    let wide_str: &WideStr = unsafe { WideStr::from_ptr(bstr, len) };
+
+    Ok(wide_str.to_string_lossy())
+}
+
+/// Parse a `BSTR` of decimal digits (like the value of a `uint64` property)
+/// directly into a `u64`, without allocating a `String`.
+///
+/// # Safety
+///
+/// `bstr` must be either null or a valid `BSTR`.
+pub unsafe fn bstr_to_u64(bstr: BSTR) -> Result<u64, Error> {
+    let chars = unsafe { bstr_chars(bstr) };
+
+    parse_u64(chars).ok_or_else(|| {
+        format_err!(
+            "Expected an unsigned integer, got {:?}",
+            String::from_utf16_lossy(chars)
+        )
+    })
+}
+
+/// Parse a `BSTR` of decimal digits, with an optional leading `-`
+/// (like the value of a `sint64` property) directly into an `i64`, without allocating a `String`.
+///
+/// # Safety
+///
+/// `bstr` must be either null or a valid `BSTR`.
+pub unsafe fn bstr_to_i64(bstr: BSTR) -> Result<i64, Error> {
+    let chars = unsafe { bstr_chars(bstr) };
+
+    let value = match chars.split_first() {
+        Some((&sign, digits)) if sign == u16::from(b'-') => parse_u64(digits).and_then(|n| {
+            if n == i64::MIN as u64 {
+                Some(i64::MIN)
+            } else {
+                i64::try_from(n).ok().map(|n| -n)
+            }
+        }),
+        _ => parse_u64(chars).and_then(|n| i64::try_from(n).ok()),
+    };
+
+    value.ok_or_else(|| {
+        format_err!(
+            "Expected an integer, got {:?}",
+            String::from_utf16_lossy(chars)
+        )
+    })
+}
+
+unsafe fn bstr_chars<'a>(bstr: BSTR) -> &'a [u16] {
+    if bstr.is_null() {
+        return &[];
+    }
+
+    let len = unsafe { SysStringLen(bstr) } as usize;
+
+    unsafe { std::slice::from_raw_parts(bstr, len) }
+}
+
+fn parse_u64(chars: &[u16]) -> Option<u64> {
+    if chars.is_empty() {
+        return None;
+    }
+
+    chars.iter().try_fold(0u64, |value, &c| {
+        let digit = c.checked_sub(u16::from(b'0')).filter(|&digit| digit <= 9)?;
+
+        value.checked_mul(10)?.checked_add(u64::from(digit))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::datetime::WMIDateTime;
+    use winapi::um::oleauto::{SysAllocStringLen, SysFreeString};
+    use winapi::um::wbemcli::{WBEM_E_INVALID_QUERY, WBEM_E_NOT_FOUND};
+
+    #[test]
+    fn it_can_be_boxed_as_std_error() {
+        let err: Box<dyn std::error::Error + Send + Sync + 'static> =
+            Box::new(WMIError::HResultError {
+                hres: WBEM_E_INVALID_CLASS as HRESULT,
+            });
+
+        assert_eq!(format!("{}", err), "HRESULT Call failed with: 0x80041010");
+        assert!(err.source().is_none());
+    }
+
+    #[test]
+    fn it_chains_the_source_of_a_serde_error() {
+        let err = "20190113200517.50000x+060"
+            .parse::<WMIDateTime>()
+            .unwrap_err();
+
+        let err: Box<dyn std::error::Error + Send + Sync + 'static> =
+            Box::new(crate::error::Error::from(err));
+
+        let wmi_err = err.source().unwrap();
+
+        assert!(wmi_err.downcast_ref::<WMIError>().is_some());
+
+        let parse_err = wmi_err.source().unwrap();
+
+        assert!(parse_err
+            .downcast_ref::<chrono::format::ParseError>()
+            .is_some());
+    }
+
+    #[test]
+    fn it_maps_provider_not_found() {
+        let err = check_hres(WBEM_E_PROVIDER_NOT_FOUND as HRESULT).unwrap_err();
+
+        match err {
+            WMIError::ProviderNotFound => {}
+            _ => assert!(false),
+        }
+
+        assert!(format!("{}", err).contains("winmgmt /verifyrepository"));
+    }
+
+    #[test]
+    fn it_maps_provider_load_failure() {
+        let err = check_hres(WBEM_E_PROVIDER_LOAD_FAILURE as HRESULT).unwrap_err();
+
+        match err {
+            WMIError::ProviderLoadFailure => {}
+            _ => assert!(false),
+        }
+
+        assert!(format!("{}", err).contains("winmgmt /verifyrepository"));
+    }
+
+    #[test]
+    fn it_maps_other_hresults_to_hresult_error() {
+        let err = check_hres(WBEM_E_INVALID_CLASS as HRESULT).unwrap_err();
+
+        match err {
+            WMIError::HResultError { hres } => assert_eq!(hres, WBEM_E_INVALID_CLASS as HRESULT),
+            _ => assert!(false),
+        }
+
+        assert!(check_hres(0).is_ok());
+    }
+
+    #[test]
+    fn it_classifies_retryable_and_not_found_errors() {
+        let from = |hres: u32| WMIError::from_hres(hres as HRESULT);
+
+        for &hres in [
+            WBEM_E_SERVER_TOO_BUSY,
+            WBEM_E_OUT_OF_MEMORY,
+            WBEM_E_TRANSPORT_FAILURE,
+            RPC_SERVER_UNAVAILABLE,
+            E_OUTOFMEMORY as u32,
+            RPC_E_DISCONNECTED as u32,
+        ]
+        .iter()
+        {
+            assert!(from(hres).is_retryable(), "{:#X}", hres);
+            assert!(!from(hres).is_not_found(), "{:#X}", hres);
+        }
+
+        for &hres in [
+            WBEM_E_NOT_FOUND,
+            WBEM_E_INVALID_OBJECT_PATH,
+            WBEM_E_INVALID_CLASS,
+        ]
+        .iter()
+        {
+            assert!(from(hres).is_not_found(), "{:#X}", hres);
+            assert!(!from(hres).is_retryable(), "{:#X}", hres);
+        }
+
+        assert!(WMIError::NoResults.is_not_found());
+        assert!(WMIError::InvalidObjectPath { path: "".into() }.is_not_found());
+
+        assert!(!from(WBEM_E_INVALID_QUERY).is_retryable());
+        assert!(!from(WBEM_E_INVALID_QUERY).is_not_found());
+        assert!(!WMIError::ProviderNotFound.is_retryable());
+
+        let query_err = |source: WMIError| WMIError::Query {
+            query: "SELECT * FROM Win32_NoSuchClass".into(),
+            namespace: "ROOT\\CIMV2".into(),
+            source: Box::new(source),
+        };
+
+        assert!(query_err(from(WBEM_E_INVALID_CLASS)).is_not_found());
+        assert!(query_err(from(WBEM_E_SERVER_TOO_BUSY)).is_retryable());
+
+        match query_err(WMIError::ProviderNotFound).without_context() {
+            WMIError::ProviderNotFound => {}
+            _ => assert!(false),
+        }
+    }
+
+    #[test]
+    fn it_decodes_bstr_with_embedded_null() {
+        let chars: Vec<u16> = "ab\0cd".encode_utf16().collect();
+
+        let bstr = unsafe { SysAllocStringLen(chars.as_ptr(), chars.len() as u32) };
+
+        let s = unsafe { bstr_to_string(bstr) }.unwrap();
+
+        unsafe { SysFreeString(bstr) };
+
+        assert_eq!(s, "ab\0cd");
+        assert_eq!(s.len(), 5);
+    }
+
+    #[test]
+    fn it_decodes_null_bstr_as_empty_string() {
+        let s = unsafe { bstr_to_string(std::ptr::null_mut()) }.unwrap();
+
+        assert_eq!(s, "");
+    }
+
+    #[test]
+    fn it_decodes_bstr_with_non_ascii_chars() {
+        let chars: Vec<u16> = "Système d'exploitation – 日本語 🦀"
+            .encode_utf16()
+            .collect();
+
+        let bstr = unsafe { SysAllocStringLen(chars.as_ptr(), chars.len() as u32) };
+
+        let s = unsafe { bstr_to_string(bstr) }.unwrap();
+
+        unsafe { SysFreeString(bstr) };
+
+        assert_eq!(s, "Système d'exploitation – 日本語 🦀");
+    }
+
+    #[test]
+    fn it_decodes_bstr_with_unpaired_surrogate_lossily() {
+        // An unpaired high surrogate between two valid characters.
+        let chars: Vec<u16> = vec![0x61, 0xD800, 0x62];
+
+        let bstr = unsafe { SysAllocStringLen(chars.as_ptr(), chars.len() as u32) };
+
+        let s = unsafe { bstr_to_string(bstr) }.unwrap();
+
+        unsafe { SysFreeString(bstr) };
+
+        assert_eq!(s, "a\u{FFFD}b");
+    }
+
+    fn bstr(s: &str) -> BSTR {
+        let chars: Vec<u16> = s.encode_utf16().collect();
+
+        unsafe { SysAllocStringLen(chars.as_ptr(), chars.len() as u32) }
+    }
+
+    #[test]
+    fn it_parses_bstr_digits_into_integers() {
+        let cases = [
+            ("0", Some(0), Some(0)),
+            ("17179869184", Some(17_179_869_184), Some(17_179_869_184)),
+            ("18446744073709551615", Some(u64::max_value()), None),
+            ("-9223372036854775808", None, Some(i64::min_value())),
+            ("-42", None, Some(-42)),
+            ("18446744073709551616", None, None),
+            ("", None, None),
+            ("12a", None, None),
+            ("-", None, None),
+        ];
+
+        for &(s, expected_u64, expected_i64) in cases.iter() {
+            let b = bstr(s);
+
+            let unsigned = unsafe { bstr_to_u64(b) }.ok();
+            let signed = unsafe { bstr_to_i64(b) }.ok();
+
+            unsafe { SysFreeString(b) };
+
+            assert_eq!(unsigned, expected_u64, "{:?}", s);
+            assert_eq!(signed, expected_i64, "{:?}", s);
+        }
+    }
+}
@@ -1,15 +1,46 @@
-use crate::safearray::safe_array_to_vec;
-use failure::{bail, Error};
-use widestring::WideCStr;
+use crate::cim_type::CimType;
+#[cfg(target_os = "windows")]
+use crate::{
+    result_enumerator::IWbemClassWrapper,
+    safearray::{safe_array_to_vec, safe_array_to_vec_of_bytes},
+    utils::{bstr_to_string, check_hres},
+};
+use failure::{bail, format_err, Error};
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::fmt::{self, Display};
+use std::hash::{Hash, Hasher};
+use std::mem;
+#[cfg(target_os = "windows")]
+use std::ptr::{self, NonNull};
+#[cfg(target_os = "windows")]
+use widestring::WideString;
+#[cfg(target_os = "windows")]
 use winapi::{
-    shared::wtypes::*,
-    um::{oaidl::SAFEARRAY, oaidl::VARIANT},
+    shared::{ntdef::PVOID, wtypes::*},
+    um::{
+        oaidl::SAFEARRAY,
+        oaidl::VARIANT,
+        oleauto::{SysAllocStringLen, VariantClear},
+        wbemcli::IWbemClassObject,
+    },
+    Interface,
 };
 
 // See: https://msdn.microsoft.com/en-us/library/cc237864.aspx
+#[cfg(target_os = "windows")]
 const VARIANT_FALSE: i16 = 0x0000;
 
-#[derive(Debug, PartialEq, Hash)]
+/// A value of a WMI property.
+///
+/// The conversions from and to native `VARIANT`s are only available on Windows,
+/// but the type itself (and deserializing from it) can be used anywhere (see the `mock` feature).
+///
+/// `Variant` implements `Eq` and `Hash` (so it can be used in a `HashSet` or as a map key).
+/// To keep these consistent, floats are compared and hashed by their bit patterns:
+/// a `NaN` is equal to itself (if it has the same bits), and `0.0` is not equal to `-0.0`.
+///
+#[derive(Debug, Clone)]
 pub enum Variant {
     Empty,
     Null,
@@ -20,18 +51,34 @@ pub enum Variant {
     I4(i32),
     I8(i64),
 
+    R4(f32),
+    R8(f64),
+
     Bool(bool),
 
     UI1(u8),
     UI8(u64),
 
+    /// A `VT_ERROR` value, holding the `SCODE` of a property which the provider failed to compute
+    /// (for example, a performance counter which errored).
+    Error(i32),
+
     Array(Vec<Variant>),
+
+    /// A `uint8[]` array, which is copied out of the native `SAFEARRAY` in bulk
+    /// (instead of being converted element by element).
+    Bytes(Vec<u8>),
 }
 
 impl Variant {
+    #[cfg(target_os = "windows")]
     pub fn from_variant(vt: VARIANT) -> Result<Variant, Error> {
         let variant_type: VARTYPE = unsafe { vt.n1.n2().vt };
 
+        if variant_type as u32 & VT_BYREF == VT_BYREF {
+            return Self::from_byref_variant(vt, variant_type as u32 & !VT_BYREF);
+        }
+
         // variant_type has two 'forms':
         // 1. A simple type like `VT_BSTR` .
         // 2. An array of certain type like `VT_ARRAY | VT_BSTR`.
@@ -40,6 +87,10 @@ impl Variant {
 
             let item_type = variant_type as u32 & VT_TYPEMASK;
 
+            if item_type == VT_UI1 {
+                return Ok(Variant::Bytes(safe_array_to_vec_of_bytes(*array)?));
+            }
+
             return Ok(Variant::Array(safe_array_to_vec(*array, item_type as u32)?));
         }
 
@@ -50,9 +101,7 @@ impl Variant {
             VT_BSTR => {
                 let bstr_ptr: &BSTR = unsafe { vt.n1.n2().n3.bstrVal() };
 
-                let prop_val: &WideCStr = unsafe { WideCStr::from_ptr_str(*bstr_ptr) };
-
-                let property_value_as_string = prop_val.to_string()?;
+                let property_value_as_string = unsafe { bstr_to_string(*bstr_ptr)? };
 
                 Variant::String(property_value_as_string)
             }
@@ -66,20 +115,41 @@ impl Variant {
 
                 Variant::I4(*num)
             }
+            VT_I8 => {
+                let num: &i64 = unsafe { vt.n1.n2().n3.llVal() };
+
+                Variant::I8(*num)
+            }
+            VT_UI8 => {
+                let num: &u64 = unsafe { vt.n1.n2().n3.ullVal() };
+
+                Variant::UI8(*num)
+            }
+            VT_R4 => {
+                let num: &f32 = unsafe { vt.n1.n2().n3.fltVal() };
+
+                Variant::R4(*num)
+            }
+            VT_R8 => {
+                let num: &f64 = unsafe { vt.n1.n2().n3.dblVal() };
+
+                Variant::R8(*num)
+            }
             VT_BOOL => {
                 let value: &i16 = unsafe { vt.n1.n2().n3.boolVal() };
 
-                match *value {
-                    VARIANT_FALSE => Variant::Bool(false),
-                    VARIANT_TRUE => Variant::Bool(true),
-                    _ => bail!("Invalid bool value: {:#X}", value),
-                }
+                Variant::Bool(variant_bool_to_bool(*value)?)
             }
             VT_UI1 => {
                 let num: &i8 = unsafe { vt.n1.n2().n3.cVal() };
 
                 Variant::UI1(*num as u8)
             }
+            VT_ERROR => {
+                let scode: &i32 = unsafe { vt.n1.n2().n3.scode() };
+
+                Variant::Error(*scode)
+            }
             VT_EMPTY => Variant::Empty,
             VT_NULL => Variant::Null,
             _ => bail!(
@@ -90,4 +160,1049 @@ impl Variant {
 
         Ok(variant_value)
     }
+
+    /// Convert a `VT_BYREF` variant, which holds a pointer to the value (instead of the value).
+    ///
+    #[cfg(target_os = "windows")]
+    fn from_byref_variant(vt: VARIANT, item_type: u32) -> Result<Variant, Error> {
+        let ptr: &PVOID = unsafe { vt.n1.n2().n3.byref() };
+
+        if ptr.is_null() {
+            bail!(
+                "Expected a pointer for by-reference variant type {:#X}, got null",
+                item_type | VT_BYREF
+            );
+        }
+
+        let variant_value = match item_type {
+            VT_BSTR => {
+                let bstr_ptr: &*mut BSTR = unsafe { vt.n1.n2().n3.pbstrVal() };
+
+                Variant::String(unsafe { bstr_to_string(**bstr_ptr)? })
+            }
+            VT_I2 => {
+                let num: &*mut i16 = unsafe { vt.n1.n2().n3.piVal() };
+
+                Variant::I2(unsafe { **num })
+            }
+            VT_I4 => {
+                let num: &*mut i32 = unsafe { vt.n1.n2().n3.plVal() };
+
+                Variant::I4(unsafe { **num })
+            }
+            VT_I8 => {
+                let num: &*mut i64 = unsafe { vt.n1.n2().n3.pllVal() };
+
+                Variant::I8(unsafe { **num })
+            }
+            VT_R8 => {
+                let num: &*mut f64 = unsafe { vt.n1.n2().n3.pdblVal() };
+
+                Variant::R8(unsafe { **num })
+            }
+            VT_BOOL => {
+                let value: &*mut i16 = unsafe { vt.n1.n2().n3.pboolVal() };
+
+                Variant::Bool(variant_bool_to_bool(unsafe { **value })?)
+            }
+            VT_VARIANT => {
+                let inner: &*mut VARIANT = unsafe { vt.n1.n2().n3.pvarVal() };
+
+                Variant::from_variant(unsafe { **inner })?
+            }
+            _ => bail!(
+                "Converting from by-reference variant type {:#X} is not supported",
+                item_type | VT_BYREF
+            ),
+        };
+
+        Ok(variant_value)
+    }
+
+    /// Convert this value into a native `VARIANT` (for example, to `Put` it into an object).
+    ///
+    /// The caller owns the returned `VARIANT`, and must free it using `VariantClear`.
+    /// Converting arrays is not supported yet.
+    ///
+    #[cfg(target_os = "windows")]
+    pub fn to_variant(&self) -> Result<VARIANT, Error> {
+        let mut vt: VARIANT = unsafe { mem::zeroed() };
+
+        let n2 = unsafe { vt.n1.n2_mut() };
+
+        match self {
+            Variant::Empty => n2.vt = VT_EMPTY as VARTYPE,
+            Variant::Null => n2.vt = VT_NULL as VARTYPE,
+            Variant::String(s) => {
+                let wide_str = WideString::from_str(s);
+
+                let bstr = unsafe { SysAllocStringLen(wide_str.as_ptr(), wide_str.len() as u32) };
+
+                if bstr.is_null() {
+                    bail!("Failed to allocate a BSTR for {:?}", s);
+                }
+
+                n2.vt = VT_BSTR as VARTYPE;
+                unsafe { *n2.n3.bstrVal_mut() = bstr };
+            }
+            Variant::I2(n) => {
+                n2.vt = VT_I2 as VARTYPE;
+                unsafe { *n2.n3.iVal_mut() = *n };
+            }
+            Variant::I4(n) => {
+                n2.vt = VT_I4 as VARTYPE;
+                unsafe { *n2.n3.lVal_mut() = *n };
+            }
+            Variant::I8(n) => {
+                n2.vt = VT_I8 as VARTYPE;
+                unsafe { *n2.n3.llVal_mut() = *n };
+            }
+            Variant::R4(n) => {
+                n2.vt = VT_R4 as VARTYPE;
+                unsafe { *n2.n3.fltVal_mut() = *n };
+            }
+            Variant::R8(n) => {
+                n2.vt = VT_R8 as VARTYPE;
+                unsafe { *n2.n3.dblVal_mut() = *n };
+            }
+            Variant::Bool(b) => {
+                n2.vt = VT_BOOL as VARTYPE;
+                unsafe { *n2.n3.boolVal_mut() = if *b { VARIANT_TRUE } else { VARIANT_FALSE } };
+            }
+            Variant::UI1(n) => {
+                n2.vt = VT_UI1 as VARTYPE;
+                unsafe { *n2.n3.bVal_mut() = *n };
+            }
+            Variant::UI8(n) => {
+                n2.vt = VT_UI8 as VARTYPE;
+                unsafe { *n2.n3.ullVal_mut() = *n };
+            }
+            Variant::Error(scode) => {
+                n2.vt = VT_ERROR as VARTYPE;
+                unsafe { *n2.n3.scode_mut() = *scode };
+            }
+            Variant::Array(_) | Variant::Bytes(_) => {
+                bail!("Converting arrays into a VARIANT is not implemented yet")
+            }
+        }
+
+        Ok(vt)
+    }
+
+    /// Return the bytes of a `uint8[]` array variant (or `None` if this isn't an array of bytes).
+    ///
+    /// Besides `Bytes`, an `Array` whose elements are all `UI1` is also accepted.
+    ///
+    /// ```edition2018
+    /// # use wmi::Variant;
+    /// let arr = Variant::Bytes(vec![0xCA, 0xFE]);
+    ///
+    /// assert_eq!(arr.into_bytes().unwrap(), [0xCA, 0xFE]);
+    /// ```
+    pub fn into_bytes(self) -> Option<Vec<u8>> {
+        match self {
+            Variant::Bytes(bytes) => Some(bytes),
+            Variant::Array(items) => items
+                .into_iter()
+                .map(|item| match item {
+                    Variant::UI1(byte) => Some(byte),
+                    _ => None,
+                })
+                .collect(),
+            _ => None,
+        }
+    }
+
+    /// Borrow the bytes of a `uint8[]` array variant (or `None` if this isn't a `Bytes` variant).
+    ///
+    /// Unlike [`into_bytes`](Variant::into_bytes), an `Array` of `UI1`s is not accepted:
+    /// its elements aren't stored as contiguous bytes, so there is nothing to borrow.
+    /// Use `into_bytes` (or `clone().into_bytes()`) to convert it instead.
+    ///
+    /// ```edition2018
+    /// # use wmi::Variant;
+    /// let arr = Variant::Bytes(vec![0xCA, 0xFE]);
+    ///
+    /// assert_eq!(arr.as_bytes(), Some(&[0xCA, 0xFE][..]));
+    /// assert_eq!(Variant::UI1(0xCA).as_bytes(), None);
+    ///
+    /// let arr = Variant::Array(vec![Variant::UI1(0xCA), Variant::UI1(0xFE)]);
+    ///
+    /// assert_eq!(arr.as_bytes(), None);
+    /// assert_eq!(arr.into_bytes().unwrap(), [0xCA, 0xFE]);
+    /// ```
+    pub fn as_bytes(&self) -> Option<&[u8]> {
+        match self {
+            Variant::Bytes(bytes) => Some(bytes),
+            _ => None,
+        }
+    }
+
+    /// Join the elements of an array variant (formatted using `Display`) with the given separator,
+    /// or return `None` if this isn't an array.
+    ///
+    /// ```edition2018
+    /// # use wmi::Variant;
+    /// let arr = Variant::Array(vec![Variant::String("a".into()), Variant::String("b".into())]);
+    ///
+    /// assert_eq!(arr.display_array_with(" | ").unwrap(), "a | b");
+    /// assert_eq!(Variant::I4(1).display_array_with(" | "), None);
+    /// ```
+    pub fn display_array_with(&self, sep: &str) -> Option<String> {
+        match self {
+            Variant::Array(items) => Some(
+                items
+                    .iter()
+                    .map(|item| item.to_string())
+                    .collect::<Vec<_>>()
+                    .join(sep),
+            ),
+            Variant::Bytes(bytes) => Some(
+                bytes
+                    .iter()
+                    .map(|byte| byte.to_string())
+                    .collect::<Vec<_>>()
+                    .join(sep),
+            ),
+            _ => None,
+        }
+    }
+
+    /// Convert an array variant into a `Vec<T>`, converting each of the elements.
+    ///
+    /// An error is returned if this isn't an array, or if any of the elements can't be converted
+    /// (in which case the error will include the index of every offending element).
+    ///
+    /// ```edition2018
+    /// # use wmi::Variant;
+    /// let arr = Variant::Array(vec![Variant::String("a".into()), Variant::String("b".into())]);
+    ///
+    /// let strings: Vec<String> = arr.try_into_vec().unwrap();
+    ///
+    /// assert_eq!(strings, ["a", "b"]);
+    /// ```
+    pub fn try_into_vec<T>(self) -> Result<Vec<T>, Error>
+    where
+        T: TryFrom<Variant>,
+        T::Error: Display,
+    {
+        let items = self.into_items()?;
+
+        let mut converted = Vec::with_capacity(items.len());
+        let mut errors = vec![];
+
+        for (index, item) in items.into_iter().enumerate() {
+            match T::try_from(item) {
+                Ok(item) => converted.push(item),
+                Err(e) => errors.push(format!("element {}: {}", index, e)),
+            }
+        }
+
+        if !errors.is_empty() {
+            return Err(format_err!(
+                "Failed to convert array elements: {}",
+                errors.join(", ")
+            ));
+        }
+
+        Ok(converted)
+    }
+
+    /// Zip two parallel array variants (like the `Names` and `Values` of some classes)
+    /// into a map. The keys must all be strings.
+    ///
+    /// An error is returned if either one isn't an array, or if their lengths don't match.
+    ///
+    /// ```edition2018
+    /// # use wmi::Variant;
+    /// let keys = Variant::Array(vec![Variant::String("a".into()), Variant::String("b".into())]);
+    /// let values = Variant::Array(vec![Variant::I4(1), Variant::I4(2)]);
+    ///
+    /// let map = Variant::zip_into_map(keys, values).unwrap();
+    ///
+    /// assert_eq!(map["b"], Variant::I4(2));
+    /// ```
+    pub fn zip_into_map(keys: Variant, values: Variant) -> Result<HashMap<String, Variant>, Error> {
+        let keys: Vec<String> = keys.try_into_vec()?;
+
+        let values = values.into_items()?;
+
+        if keys.len() != values.len() {
+            bail!(
+                "Expected the same number of keys and values, got {} keys and {} values",
+                keys.len(),
+                values.len()
+            );
+        }
+
+        Ok(keys.into_iter().zip(values).collect())
+    }
+
+    fn into_items(self) -> Result<Vec<Variant>, Error> {
+        match self {
+            Variant::Array(items) => Ok(items),
+            Variant::Bytes(bytes) => Ok(bytes.into_iter().map(Variant::UI1).collect()),
+            other => bail!("Expected an array variant, got {:?}", other),
+        }
+    }
+
+    /// Convert this value to match the given declared type (for example, from `class_schema`).
+    ///
+    /// The rules are:
+    /// * `Null` and `Empty` are returned as is, for any type.
+    /// * `Error` fails for any type.
+    /// * Integer types accept integers, bools (as `0` or `1`) and strings containing an integer,
+    ///   and fail if the value is out of range. Since there are no unsigned 16/32 bit variants,
+    ///   `uint16` and `char16` become `I4` and `uint32` becomes `I8`, and `sint8` becomes `I2`.
+    ///   A negative `I4` is accepted for `uint32` (this is how WMI returns large values).
+    /// * `uint64` and `sint64` (which WMI returns as strings) become `UI8` and `I8`.
+    /// * `real32` and `real64` accept floats, integers and strings containing a number.
+    /// * `boolean` accepts bools, integers (where anything but `0` is `true`),
+    ///   and the strings `"true"` and `"false"` (ignoring case) or a string containing an integer.
+    /// * `string`, `datetime` and `reference` accept any non-array value, formatted as a string.
+    /// * Arrays are converted element-wise.
+    /// * `object` (and empty or unknown types) return the value as is.
+    ///
+    /// ```edition2018
+    /// # use wmi::Variant;
+    /// # use wmi::cim_type::CimType;
+    /// let size = Variant::String("42".into()).coerce_to_cim_type(CimType::UInt64).unwrap();
+    ///
+    /// assert_eq!(size, Variant::UI8(42));
+    /// ```
+    pub fn coerce_to_cim_type(self, cim_type: CimType) -> Result<Variant, Error> {
+        match (self, cim_type) {
+            (value @ Variant::Null, _) | (value @ Variant::Empty, _) => Ok(value),
+            (Variant::Error(scode), cim_type) => {
+                bail!("Cannot coerce an error ({:#X}) to {:?}", scode, cim_type)
+            }
+            (Variant::Array(items), CimType::Array(item_type)) => items
+                .into_iter()
+                .map(|item| item.coerce_to_cim_type((*item_type).clone()))
+                .collect::<Result<_, _>>()
+                .map(Variant::Array),
+            (value @ Variant::Bytes(_), CimType::Array(item_type))
+                if *item_type == CimType::UInt8 =>
+            {
+                Ok(value)
+            }
+            (value @ Variant::Bytes(_), cim_type @ CimType::Array(_)) => {
+                Variant::Array(value.into_items()?).coerce_to_cim_type(cim_type)
+            }
+            (value, CimType::Array(item_type)) => {
+                bail!("Cannot coerce {:?} to an array of {:?}", value, item_type)
+            }
+            (value, CimType::Empty) | (value, CimType::Object) | (value, CimType::Unknown(_)) => {
+                Ok(value)
+            }
+            (Variant::Array(items), cim_type) => {
+                bail!("Cannot coerce an array {:?} to {:?}", items, cim_type)
+            }
+            (Variant::Bytes(bytes), cim_type) => {
+                bail!("Cannot coerce an array {:?} to {:?}", bytes, cim_type)
+            }
+            (Variant::I4(n), CimType::UInt32) if n < 0 => Ok(Variant::I8(n as u32 as i64)),
+            (value, CimType::String) | (value, CimType::DateTime) | (value, CimType::Reference) => {
+                Ok(Variant::String(value.coerce_to_string()))
+            }
+            (value, CimType::Boolean) => {
+                let coerced = match &value {
+                    Variant::Bool(b) => Some(*b),
+                    Variant::String(s) if s.trim().eq_ignore_ascii_case("true") => Some(true),
+                    Variant::String(s) if s.trim().eq_ignore_ascii_case("false") => Some(false),
+                    other => other.coerce_to_integer().map(|n| n != 0),
+                };
+
+                coerced.map(Variant::Bool).ok_or_else(|| {
+                    format_err!("Cannot coerce {:?} to {:?}", value, CimType::Boolean)
+                })
+            }
+            (value, CimType::Real32) => match value.coerce_to_float() {
+                Some(n) => Ok(Variant::R4(n as f32)),
+                None => bail!("Cannot coerce {:?} to {:?}", value, CimType::Real32),
+            },
+            (value, CimType::Real64) => match value.coerce_to_float() {
+                Some(n) => Ok(Variant::R8(n)),
+                None => bail!("Cannot coerce {:?} to {:?}", value, CimType::Real64),
+            },
+            (value, cim_type) => {
+                let coerced = value.coerce_to_integer().and_then(|n| match cim_type {
+                    CimType::SInt8 => i8::try_from(n).ok().map(|n| Variant::I2(n as i16)),
+                    CimType::UInt8 => u8::try_from(n).ok().map(Variant::UI1),
+                    CimType::SInt16 => i16::try_from(n).ok().map(Variant::I2),
+                    CimType::UInt16 | CimType::Char16 => {
+                        u16::try_from(n).ok().map(|n| Variant::I4(n as i32))
+                    }
+                    CimType::SInt32 => i32::try_from(n).ok().map(Variant::I4),
+                    CimType::UInt32 => u32::try_from(n).ok().map(|n| Variant::I8(n as i64)),
+                    CimType::SInt64 => i64::try_from(n).ok().map(Variant::I8),
+                    CimType::UInt64 => u64::try_from(n).ok().map(Variant::UI8),
+                    _ => None,
+                });
+
+                coerced.ok_or_else(|| format_err!("Cannot coerce {:?} to {:?}", value, cim_type))
+            }
+        }
+    }
+
+    fn coerce_to_integer(&self) -> Option<i128> {
+        match self {
+            Variant::String(s) => s.trim().parse().ok(),
+            Variant::I2(n) => Some(*n as i128),
+            Variant::I4(n) => Some(*n as i128),
+            Variant::I8(n) => Some(*n as i128),
+            Variant::Bool(b) => Some(*b as i128),
+            Variant::UI1(n) => Some(*n as i128),
+            Variant::UI8(n) => Some(*n as i128),
+            _ => None,
+        }
+    }
+
+    fn coerce_to_float(&self) -> Option<f64> {
+        match self {
+            Variant::String(s) => s.trim().parse().ok(),
+            Variant::R4(n) => Some(*n as f64),
+            Variant::R8(n) => Some(*n),
+            other => other.coerce_to_integer().map(|n| n as f64),
+        }
+    }
+
+    fn coerce_to_string(self) -> String {
+        match self {
+            Variant::String(s) => s,
+            Variant::I2(n) => n.to_string(),
+            Variant::I4(n) => n.to_string(),
+            Variant::I8(n) => n.to_string(),
+            Variant::R4(n) => n.to_string(),
+            Variant::R8(n) => n.to_string(),
+            Variant::Bool(b) => b.to_string(),
+            Variant::UI1(n) => n.to_string(),
+            Variant::UI8(n) => n.to_string(),
+            Variant::Empty
+            | Variant::Null
+            | Variant::Error(_)
+            | Variant::Array(_)
+            | Variant::Bytes(_) => String::new(),
+        }
+    }
+}
+
+/// Values are formatted without their type: `Empty` and `Null` as an empty string,
+/// and arrays as their elements separated by `, ` (see `display_array_with`).
+///
+impl fmt::Display for Variant {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Variant::Empty | Variant::Null => Ok(()),
+            Variant::String(s) => f.write_str(s),
+            Variant::I2(n) => write!(f, "{}", n),
+            Variant::I4(n) => write!(f, "{}", n),
+            Variant::I8(n) => write!(f, "{}", n),
+            Variant::R4(n) => write!(f, "{}", n),
+            Variant::R8(n) => write!(f, "{}", n),
+            Variant::Bool(b) => write!(f, "{}", b),
+            Variant::UI1(n) => write!(f, "{}", n),
+            Variant::UI8(n) => write!(f, "{}", n),
+            Variant::Error(scode) => write!(f, "error {:#X}", scode),
+            Variant::Array(_) | Variant::Bytes(_) => {
+                f.write_str(&self.display_array_with(", ").unwrap())
+            }
+        }
+    }
+}
+
+#[cfg(target_os = "windows")]
+pub(crate) fn variant_bool_to_bool(value: i16) -> Result<bool, Error> {
+    match value {
+        VARIANT_FALSE => Ok(false),
+        VARIANT_TRUE => Ok(true),
+        _ => bail!("Invalid bool value: {:#X}", value),
+    }
+}
+
+/// A native `VARIANT` (for example, a property value returned by `Get`),
+/// which is cleared when dropped.
+///
+/// This allows converting the value lazily, depending on how it's used
+/// (for example, parsing a `uint64` string directly into a `u64`).
+///
+#[cfg(target_os = "windows")]
+pub(crate) struct NativeVariant(pub(crate) VARIANT);
+
+#[cfg(target_os = "windows")]
+impl NativeVariant {
+    /// A `VT_EMPTY` value.
+    pub(crate) fn empty() -> Self {
+        NativeVariant(unsafe { mem::zeroed() })
+    }
+
+    pub(crate) fn to_variant(&self) -> Result<Variant, Error> {
+        Variant::from_variant(self.0)
+    }
+
+    /// The `BSTR` held by this value, if it's a `VT_BSTR`.
+    pub(crate) fn as_bstr(&self) -> Option<BSTR> {
+        let variant_type: VARTYPE = unsafe { self.0.n1.n2().vt };
+
+        if variant_type as u32 == VT_BSTR {
+            Some(unsafe { *self.0.n1.n2().n3.bstrVal() })
+        } else {
+            None
+        }
+    }
+
+    /// The embedded object held by this value (like the `TargetInstance` of an event),
+    /// if it's a non-null `VT_UNKNOWN`.
+    ///
+    /// The returned object holds its own reference (from `QueryInterface`),
+    /// so it stays valid after this value and the object it was read from are dropped.
+    pub(crate) fn as_object(&self) -> Result<Option<IWbemClassWrapper>, Error> {
+        let variant_type: VARTYPE = unsafe { self.0.n1.n2().vt };
+
+        if variant_type as u32 != VT_UNKNOWN {
+            return Ok(None);
+        }
+
+        let p_unknown = unsafe { *self.0.n1.n2().n3.punkVal() };
+
+        if p_unknown.is_null() {
+            return Ok(None);
+        }
+
+        let mut p_object = ptr::null_mut();
+
+        unsafe {
+            check_hres((*p_unknown).QueryInterface(&IWbemClassObject::uuidof(), &mut p_object))?;
+        }
+
+        Ok(Some(IWbemClassWrapper::new(NonNull::new(
+            p_object as *mut IWbemClassObject,
+        ))))
+    }
+}
+
+#[cfg(target_os = "windows")]
+impl Drop for NativeVariant {
+    fn drop(&mut self) {
+        unsafe { VariantClear(&mut self.0) };
+    }
+}
+
+impl PartialEq for Variant {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Variant::Empty, Variant::Empty) => true,
+            (Variant::Null, Variant::Null) => true,
+            (Variant::String(a), Variant::String(b)) => a == b,
+            (Variant::I2(a), Variant::I2(b)) => a == b,
+            (Variant::I4(a), Variant::I4(b)) => a == b,
+            (Variant::I8(a), Variant::I8(b)) => a == b,
+            (Variant::R4(a), Variant::R4(b)) => a.to_bits() == b.to_bits(),
+            (Variant::R8(a), Variant::R8(b)) => a.to_bits() == b.to_bits(),
+            (Variant::Bool(a), Variant::Bool(b)) => a == b,
+            (Variant::UI1(a), Variant::UI1(b)) => a == b,
+            (Variant::UI8(a), Variant::UI8(b)) => a == b,
+            (Variant::Error(a), Variant::Error(b)) => a == b,
+            (Variant::Array(a), Variant::Array(b)) => a == b,
+            (Variant::Bytes(a), Variant::Bytes(b)) => a == b,
+            _ => false,
+        }
+    }
+}
+
+impl Eq for Variant {}
+
+impl Hash for Variant {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        mem::discriminant(self).hash(state);
+
+        match self {
+            Variant::Empty | Variant::Null => {}
+            Variant::String(s) => s.hash(state),
+            Variant::I2(n) => n.hash(state),
+            Variant::I4(n) => n.hash(state),
+            Variant::I8(n) => n.hash(state),
+            Variant::R4(n) => n.to_bits().hash(state),
+            Variant::R8(n) => n.to_bits().hash(state),
+            Variant::Bool(b) => b.hash(state),
+            Variant::UI1(n) => n.hash(state),
+            Variant::UI8(n) => n.hash(state),
+            Variant::Error(scode) => scode.hash(state),
+            Variant::Array(items) => items.hash(state),
+            Variant::Bytes(bytes) => bytes.hash(state),
+        }
+    }
+}
+
+macro_rules! impl_try_from_variant {
+    ($target_type:ty, $variant_type:ident) => {
+        impl TryFrom<Variant> for $target_type {
+            type Error = Error;
+
+            fn try_from(value: Variant) -> Result<$target_type, Self::Error> {
+                match value {
+                    Variant::$variant_type(item) => Ok(item),
+                    other => Err(format_err!(
+                        "Expected a {} variant, got {:?}",
+                        stringify!($variant_type),
+                        other
+                    )),
+                }
+            }
+        }
+    };
+}
+
+impl_try_from_variant!(String, String);
+impl_try_from_variant!(i16, I2);
+impl_try_from_variant!(i32, I4);
+impl_try_from_variant!(i64, I8);
+impl_try_from_variant!(f32, R4);
+impl_try_from_variant!(f64, R8);
+impl_try_from_variant!(bool, Bool);
+impl_try_from_variant!(u8, UI1);
+impl_try_from_variant!(u64, UI8);
+
+/// Convert a raw `VARIANT` (for example, one obtained by calling a COM method directly
+/// using [`WMIConnection::svc`](crate::WMIConnection::svc)) into a `Variant`.
+///
+/// The value is copied, so the caller still owns the `VARIANT` (and must clear it).
+///
+/// # Safety
+///
+/// Although this is a safe trait, the `VARIANT` must be valid:
+/// its type must match its value (a `VT_BSTR` must point to a valid `BSTR`, and so on),
+/// since the pointers it contains are read during the conversion.
+///
+#[cfg(target_os = "windows")]
+impl TryFrom<&VARIANT> for Variant {
+    type Error = Error;
+
+    fn try_from(vt: &VARIANT) -> Result<Variant, Error> {
+        Variant::from_variant(*vt)
+    }
+}
+
+#[cfg(all(test, target_os = "windows"))]
+mod tests {
+    use super::*;
+    use serde::Deserialize;
+    use std::collections::HashSet;
+    use std::mem;
+    use winapi::um::oleauto::{SysFreeString, VariantClear};
+    use winapi::um::wbemcli::WBEM_E_FAILED;
+
+    #[test]
+    fn it_converts_native_64bit_variants() {
+        let mut vt: VARIANT = unsafe { mem::zeroed() };
+
+        unsafe {
+            let n2 = vt.n1.n2_mut();
+            n2.vt = VT_I8 as VARTYPE;
+            *n2.n3.llVal_mut() = -42;
+        }
+
+        let variant = Variant::from_variant(vt).unwrap();
+        assert_eq!(variant, Variant::I8(-42));
+
+        let num = i64::deserialize(variant).unwrap();
+        assert_eq!(num, -42);
+
+        let mut vt: VARIANT = unsafe { mem::zeroed() };
+
+        unsafe {
+            let n2 = vt.n1.n2_mut();
+            n2.vt = VT_UI8 as VARTYPE;
+            *n2.n3.ullVal_mut() = u64::max_value();
+        }
+
+        let variant = Variant::from_variant(vt).unwrap();
+        assert_eq!(variant, Variant::UI8(u64::max_value()));
+
+        let num = u64::deserialize(variant).unwrap();
+        assert_eq!(num, u64::max_value());
+    }
+
+    #[test]
+    fn it_converts_string_array_into_vec() {
+        let arr = Variant::Array(vec![
+            Variant::String("LM_Workstation".into()),
+            Variant::String("LM_Server".into()),
+            Variant::String("NT".into()),
+        ]);
+
+        let roles: Vec<String> = arr.try_into_vec().unwrap();
+
+        assert_eq!(roles, ["LM_Workstation", "LM_Server", "NT"]);
+    }
+
+    #[test]
+    fn it_fails_to_convert_mixed_array_into_vec() {
+        let arr = Variant::Array(vec![
+            Variant::String("a".into()),
+            Variant::I4(1),
+            Variant::String("b".into()),
+            Variant::Null,
+        ]);
+
+        let err = arr.try_into_vec::<String>().unwrap_err();
+
+        assert_eq!(
+            format!("{}", err),
+            "Failed to convert array elements: element 1: Expected a String variant, got I4(1), \
+             element 3: Expected a String variant, got Null"
+        );
+    }
+
+    #[test]
+    fn it_zips_arrays_into_map() {
+        let keys = Variant::Array(vec![
+            Variant::String("Name".into()),
+            Variant::String("Size".into()),
+        ]);
+        let values = Variant::Array(vec![Variant::String("C:".into()), Variant::UI8(512)]);
+
+        let map = Variant::zip_into_map(keys, values).unwrap();
+
+        assert_eq!(map.len(), 2);
+        assert_eq!(map["Name"], Variant::String("C:".into()));
+        assert_eq!(map["Size"], Variant::UI8(512));
+    }
+
+    #[test]
+    fn it_fails_to_zip_mismatched_arrays_into_map() {
+        let keys = Variant::Array(vec![
+            Variant::String("Name".into()),
+            Variant::String("Size".into()),
+        ]);
+        let values = Variant::Array(vec![Variant::String("C:".into())]);
+
+        let err = Variant::zip_into_map(keys, values).unwrap_err();
+
+        assert_eq!(
+            format!("{}", err),
+            "Expected the same number of keys and values, got 2 keys and 1 values"
+        );
+
+        let keys = Variant::Array(vec![Variant::I4(1)]);
+        let values = Variant::Array(vec![Variant::I4(2)]);
+
+        assert!(Variant::zip_into_map(keys, values).is_err());
+    }
+
+    #[test]
+    fn it_fails_to_convert_non_bytes_into_bytes() {
+        assert!(Variant::Array(vec![Variant::UI1(1), Variant::I4(2)])
+            .into_bytes()
+            .is_none());
+        assert!(Variant::UI1(1).into_bytes().is_none());
+        assert!(Variant::Array(vec![]).into_bytes().unwrap().is_empty());
+    }
+
+    #[test]
+    fn it_converts_an_array_of_ui1_into_bytes_without_borrowing_it() {
+        let arr = Variant::Array(vec![Variant::UI1(0xCA), Variant::UI1(0xFE)]);
+
+        assert_eq!(arr.as_bytes(), None);
+        assert_eq!(arr.into_bytes().unwrap(), [0xCA, 0xFE]);
+
+        let bytes = Variant::Bytes(vec![0xCA, 0xFE]);
+
+        assert_eq!(bytes.as_bytes().unwrap(), [0xCA, 0xFE]);
+        assert_eq!(bytes.into_bytes().unwrap(), [0xCA, 0xFE]);
+    }
+
+    #[test]
+    fn it_converts_bytes_like_an_array_of_ui1() {
+        let bytes = Variant::Bytes(vec![1, 2]);
+
+        assert_eq!(bytes.as_bytes().unwrap(), [1, 2]);
+        assert!(Variant::Array(vec![Variant::UI1(1)]).as_bytes().is_none());
+
+        let items: Vec<u8> = bytes.clone().try_into_vec().unwrap();
+        assert_eq!(items, [1, 2]);
+
+        assert_eq!(bytes.display_array_with(" "), Some("1 2".to_string()));
+
+        let uint8s = CimType::Array(Box::new(CimType::UInt8));
+        assert_eq!(bytes.clone().coerce_to_cim_type(uint8s).unwrap(), bytes);
+
+        let sint32s = CimType::Array(Box::new(CimType::SInt32));
+        assert_eq!(
+            bytes.coerce_to_cim_type(sint32s).unwrap(),
+            Variant::Array(vec![Variant::I4(1), Variant::I4(2)])
+        );
+    }
+
+    #[test]
+    fn it_dedups_variants_in_a_set() {
+        let set: HashSet<Variant> = vec![
+            Variant::String("a".into()),
+            Variant::String("a".into()),
+            Variant::String("b".into()),
+            Variant::I4(1),
+            Variant::I4(1),
+            Variant::I8(1),
+            Variant::Null,
+            Variant::Null,
+        ]
+        .into_iter()
+        .collect();
+
+        assert_eq!(set.len(), 5);
+        assert!(set.contains(&Variant::String("a".into())));
+        assert!(set.contains(&Variant::I4(1)));
+        assert!(set.contains(&Variant::I8(1)));
+    }
+
+    #[test]
+    fn it_compares_floats_by_bits() {
+        assert_eq!(Variant::R8(std::f64::NAN), Variant::R8(std::f64::NAN));
+        assert_ne!(Variant::R8(0.0), Variant::R8(-0.0));
+        assert_ne!(Variant::R4(1.0), Variant::R8(1.0));
+
+        let set: HashSet<Variant> = vec![
+            Variant::R8(std::f64::NAN),
+            Variant::R8(std::f64::NAN),
+            Variant::R4(0.5),
+            Variant::R4(0.5),
+        ]
+        .into_iter()
+        .collect();
+
+        assert_eq!(set.len(), 2);
+    }
+
+    #[test]
+    fn it_displays_arrays_with_a_separator() {
+        let arr = Variant::Array(vec![
+            Variant::String("C:".into()),
+            Variant::String("D:".into()),
+        ]);
+
+        assert_eq!(arr.display_array_with(", ").unwrap(), "C:, D:");
+        assert_eq!(arr.display_array_with("\n").unwrap(), "C:\nD:");
+        assert_eq!(arr.to_string(), "C:, D:");
+
+        assert_eq!(Variant::Array(vec![]).display_array_with(", ").unwrap(), "");
+        assert_eq!(Variant::String("C:".into()).display_array_with(", "), None);
+        assert_eq!(Variant::UI8(5).to_string(), "5");
+    }
+
+    #[test]
+    fn it_fails_to_convert_non_array_into_vec() {
+        let res = Variant::I4(1).try_into_vec::<i32>();
+
+        assert!(res.is_err());
+    }
+
+    #[test]
+    fn it_converts_to_native_variants_and_back() {
+        let values = vec![
+            Variant::Null,
+            Variant::String("Hello\0World".into()),
+            Variant::I2(-2),
+            Variant::I4(-4),
+            Variant::I8(-8),
+            Variant::R4(0.5),
+            Variant::R8(-1.25),
+            Variant::Bool(true),
+            Variant::Bool(false),
+            Variant::UI1(1),
+            Variant::UI8(8),
+        ];
+
+        for value in values {
+            let mut vt = value.to_variant().unwrap();
+
+            let converted = Variant::from_variant(vt).unwrap();
+
+            unsafe { VariantClear(&mut vt) };
+
+            assert_eq!(converted, value);
+        }
+    }
+
+    #[test]
+    fn it_converts_error_variants() {
+        let mut vt: VARIANT = unsafe { mem::zeroed() };
+
+        unsafe {
+            let n2 = vt.n1.n2_mut();
+            n2.vt = VT_ERROR as VARTYPE;
+            *n2.n3.scode_mut() = WBEM_E_FAILED as i32;
+        }
+
+        let variant = Variant::from_variant(vt).unwrap();
+
+        assert_eq!(variant, Variant::Error(WBEM_E_FAILED as i32));
+        assert_eq!(variant.to_string(), "error 0x80041001");
+        assert!(variant.clone().coerce_to_cim_type(CimType::UInt32).is_err());
+
+        let mut native = variant.to_variant().unwrap();
+
+        assert_eq!(Variant::from_variant(native).unwrap(), variant);
+
+        unsafe { VariantClear(&mut native) };
+    }
+
+    #[test]
+    fn it_converts_byref_variants() {
+        let chars: Vec<u16> = "by-ref".encode_utf16().collect();
+        let mut bstr = unsafe { SysAllocStringLen(chars.as_ptr(), chars.len() as u32) };
+
+        let mut vt: VARIANT = unsafe { mem::zeroed() };
+
+        unsafe {
+            let n2 = vt.n1.n2_mut();
+            n2.vt = (VT_BSTR | VT_BYREF) as VARTYPE;
+            *n2.n3.pbstrVal_mut() = &mut bstr;
+        }
+
+        let variant = Variant::from_variant(vt);
+
+        // The caller owns the referenced value.
+        unsafe { SysFreeString(bstr) };
+
+        assert_eq!(variant.unwrap(), Variant::String("by-ref".into()));
+
+        let mut num: i32 = -7;
+        let mut vt: VARIANT = unsafe { mem::zeroed() };
+
+        unsafe {
+            let n2 = vt.n1.n2_mut();
+            n2.vt = (VT_I4 | VT_BYREF) as VARTYPE;
+            *n2.n3.plVal_mut() = &mut num;
+        }
+
+        assert_eq!(Variant::from_variant(vt).unwrap(), Variant::I4(-7));
+    }
+
+    #[test]
+    fn it_fails_to_convert_unsupported_byref_variants() {
+        let mut date: f64 = 0.0;
+        let mut vt: VARIANT = unsafe { mem::zeroed() };
+
+        unsafe {
+            let n2 = vt.n1.n2_mut();
+            n2.vt = (VT_DATE | VT_BYREF) as VARTYPE;
+            *n2.n3.pdate_mut() = &mut date;
+        }
+
+        let err = Variant::from_variant(vt).unwrap_err();
+
+        assert_eq!(
+            format!("{}", err),
+            "Converting from by-reference variant type 0x4007 is not supported"
+        );
+
+        let mut vt: VARIANT = unsafe { mem::zeroed() };
+
+        unsafe {
+            vt.n1.n2_mut().vt = (VT_BSTR | VT_BYREF) as VARTYPE;
+        }
+
+        assert!(Variant::from_variant(vt).is_err());
+    }
+
+    #[test]
+    fn it_fails_to_convert_array_to_native_variant() {
+        let res = Variant::Array(vec![Variant::I4(1)]).to_variant();
+
+        assert!(res.is_err());
+    }
+
+    #[test]
+    fn it_coerces_strings_to_numbers() {
+        let coerce = |s: &str, cim_type| Variant::String(s.into()).coerce_to_cim_type(cim_type);
+
+        assert_eq!(coerce("42", CimType::UInt64).unwrap(), Variant::UI8(42));
+        assert_eq!(coerce("-42", CimType::SInt64).unwrap(), Variant::I8(-42));
+        assert_eq!(coerce(" 7 ", CimType::UInt8).unwrap(), Variant::UI1(7));
+        assert_eq!(
+            coerce("4294967295", CimType::UInt32).unwrap(),
+            Variant::I8(4294967295)
+        );
+        assert_eq!(coerce("1.5", CimType::Real64).unwrap(), Variant::R8(1.5));
+
+        assert!(coerce("-1", CimType::UInt64).is_err());
+        assert!(coerce("256", CimType::UInt8).is_err());
+        assert!(coerce("abc", CimType::SInt32).is_err());
+        assert!(coerce("1.5", CimType::SInt32).is_err());
+    }
+
+    #[test]
+    fn it_coerces_numbers_to_bools() {
+        assert_eq!(
+            Variant::I4(0).coerce_to_cim_type(CimType::Boolean).unwrap(),
+            Variant::Bool(false)
+        );
+        assert_eq!(
+            Variant::UI1(2)
+                .coerce_to_cim_type(CimType::Boolean)
+                .unwrap(),
+            Variant::Bool(true)
+        );
+        assert_eq!(
+            Variant::String("TRUE".into())
+                .coerce_to_cim_type(CimType::Boolean)
+                .unwrap(),
+            Variant::Bool(true)
+        );
+        assert!(Variant::R8(1.0)
+            .coerce_to_cim_type(CimType::Boolean)
+            .is_err());
+    }
+
+    #[test]
+    fn it_coerces_other_values() {
+        assert_eq!(
+            Variant::I4(-1).coerce_to_cim_type(CimType::UInt32).unwrap(),
+            Variant::I8(u32::MAX as i64)
+        );
+        assert_eq!(
+            Variant::I8(42).coerce_to_cim_type(CimType::String).unwrap(),
+            Variant::String("42".into())
+        );
+        assert_eq!(
+            Variant::Null.coerce_to_cim_type(CimType::UInt64).unwrap(),
+            Variant::Null
+        );
+        assert_eq!(
+            Variant::Array(vec![
+                Variant::String("1".into()),
+                Variant::String("2".into())
+            ])
+            .coerce_to_cim_type(CimType::Array(Box::new(CimType::UInt64)))
+            .unwrap(),
+            Variant::Array(vec![Variant::UI8(1), Variant::UI8(2)])
+        );
+        assert!(Variant::I4(1)
+            .coerce_to_cim_type(CimType::Array(Box::new(CimType::SInt32)))
+            .is_err());
+        assert!(Variant::Array(vec![])
+            .coerce_to_cim_type(CimType::String)
+            .is_err());
+    }
+
+    #[test]
+    fn it_converts_a_raw_variant_reference() {
+        let mut vt = Variant::String("raw".into()).to_variant().unwrap();
+
+        let variant = Variant::try_from(&vt).unwrap();
+
+        unsafe { VariantClear(&mut vt) };
+
+        assert_eq!(variant, Variant::String("raw".into()));
+
+        let mut vt: VARIANT = unsafe { mem::zeroed() };
+
+        unsafe {
+            let n2 = vt.n1.n2_mut();
+            n2.vt = VT_I2 as VARTYPE;
+            *n2.n3.iVal_mut() = -3;
+        }
+
+        assert_eq!(Variant::try_from(&vt).unwrap(), Variant::I2(-3));
+    }
 }
@@ -0,0 +1,235 @@
+//! Derive macros for the [`wmi`](https://docs.rs/wmi) crate.
+//!
+//! Use them through the `derive` feature of `wmi` (as `wmi::WMIClass`),
+//! instead of depending on this crate directly.
+//!
+extern crate proc_macro;
+
+use proc_macro::TokenStream;
+use proc_macro2::Span;
+use quote::quote;
+use syn::{parse_macro_input, Attribute, Data, DeriveInput, Error, Fields, Lit, Meta, NestedMeta};
+
+/// Implement `wmi::WMIClass`, using the same class and property names as `#[derive(Deserialize)]`.
+///
+/// See the `wmi::class` module for the supported attributes.
+///
+#[proc_macro_derive(WMIClass, attributes(wmi))]
+pub fn derive_wmi_class(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+
+    match expand(&input) {
+        Ok(tokens) => tokens.into(),
+        Err(err) => err.to_compile_error().into(),
+    }
+}
+
+fn expand(input: &DeriveInput) -> Result<proc_macro2::TokenStream, Error> {
+    let ident = &input.ident;
+
+    let fields = match &input.data {
+        Data::Struct(data) => match &data.fields {
+            Fields::Named(fields) => &fields.named,
+            _ => {
+                return Err(Error::new_spanned(
+                    ident,
+                    "WMIClass can only be derived for structs with named fields",
+                ))
+            }
+        },
+        _ => {
+            return Err(Error::new_spanned(
+                ident,
+                "WMIClass can only be derived for structs",
+            ))
+        }
+    };
+
+    if !input.generics.params.is_empty() {
+        return Err(Error::new_spanned(
+            &input.generics,
+            "WMIClass can't be derived for generic structs",
+        ));
+    }
+
+    let container = ContainerAttrs::from_attrs(&input.attrs)?;
+
+    let class_name = container
+        .class
+        .clone()
+        .or_else(|| container.rename.clone())
+        .unwrap_or_else(|| ident.to_string());
+
+    let mut property_names = vec![];
+
+    for field in fields {
+        let field_attrs = FieldAttrs::from_attrs(&field.attrs)?;
+
+        if field_attrs.skip {
+            continue;
+        }
+
+        let field_name = field.ident.as_ref().unwrap().to_string();
+        let field_name = field_name.trim_start_matches("r#");
+
+        let property_name = match (field_attrs.rename, container.rename_all_pascal_case) {
+            (Some(rename), _) => rename,
+            (None, true) => to_pascal_case(field_name),
+            (None, false) => field_name.to_owned(),
+        };
+
+        property_names.push(property_name);
+    }
+
+    Ok(quote! {
+        impl ::wmi::class::WMIClass for #ident {
+            fn wmi_class_name() -> &'static str {
+                #class_name
+            }
+
+            fn wmi_property_names() -> &'static [&'static str] {
+                &[#(#property_names),*]
+            }
+        }
+    })
+}
+
+#[derive(Default)]
+struct ContainerAttrs {
+    class: Option<String>,
+    rename: Option<String>,
+    rename_all_pascal_case: bool,
+}
+
+impl ContainerAttrs {
+    fn from_attrs(attrs: &[Attribute]) -> Result<Self, Error> {
+        let mut container = Self::default();
+
+        for (attr_name, meta) in nested_metas(attrs)? {
+            match (attr_name, &meta) {
+                ("wmi", NestedMeta::Meta(Meta::NameValue(nv))) if nv.path.is_ident("class") => {
+                    container.class = Some(lit_str(&nv.lit)?);
+                }
+                ("serde", NestedMeta::Meta(Meta::NameValue(nv))) if nv.path.is_ident("rename") => {
+                    container.rename = Some(lit_str(&nv.lit)?);
+                }
+                ("serde", NestedMeta::Meta(Meta::NameValue(nv)))
+                    if nv.path.is_ident("rename_all") =>
+                {
+                    if lit_str(&nv.lit)? != "PascalCase" {
+                        return Err(Error::new_spanned(
+                            &nv.lit,
+                            "WMIClass only supports `rename_all = \"PascalCase\"`",
+                        ));
+                    }
+
+                    container.rename_all_pascal_case = true;
+                }
+                // Other `serde` attributes (like `deny_unknown_fields`) don't change the names.
+                ("serde", _) => {}
+                (_, meta) => return Err(Error::new_spanned(meta, "Unsupported wmi attribute")),
+            }
+        }
+
+        Ok(container)
+    }
+}
+
+#[derive(Default)]
+struct FieldAttrs {
+    rename: Option<String>,
+    skip: bool,
+}
+
+impl FieldAttrs {
+    fn from_attrs(attrs: &[Attribute]) -> Result<Self, Error> {
+        let mut field = Self::default();
+
+        for (attr_name, meta) in nested_metas(attrs)? {
+            match (attr_name, &meta) {
+                ("serde", NestedMeta::Meta(Meta::NameValue(nv))) if nv.path.is_ident("rename") => {
+                    field.rename = Some(lit_str(&nv.lit)?);
+                }
+                ("serde", NestedMeta::Meta(Meta::Path(path)))
+                    if path.is_ident("skip") || path.is_ident("skip_deserializing") =>
+                {
+                    field.skip = true;
+                }
+                ("serde", NestedMeta::Meta(Meta::Path(path))) if path.is_ident("flatten") => {
+                    return Err(Error::new_spanned(
+                        path,
+                        "WMIClass doesn't support flattened fields",
+                    ));
+                }
+                ("serde", _) => {}
+                (_, meta) => return Err(Error::new_spanned(meta, "Unsupported wmi attribute")),
+            }
+        }
+
+        Ok(field)
+    }
+}
+
+/// The items of every `#[wmi(...)]` and `#[serde(...)]` attribute.
+fn nested_metas(attrs: &[Attribute]) -> Result<Vec<(&'static str, NestedMeta)>, Error> {
+    let mut metas = vec![];
+
+    for attr in attrs {
+        let attr_name = if attr.path.is_ident("wmi") {
+            "wmi"
+        } else if attr.path.is_ident("serde") {
+            "serde"
+        } else {
+            continue;
+        };
+
+        match attr.parse_meta()? {
+            Meta::List(list) => {
+                for nested in list.nested {
+                    metas.push((attr_name, nested));
+                }
+            }
+            other => return Err(Error::new_spanned(other, "Expected a list of attributes")),
+        }
+    }
+
+    Ok(metas)
+}
+
+fn lit_str(lit: &Lit) -> Result<String, Error> {
+    match lit {
+        Lit::Str(s) => Ok(s.value()),
+        _ => Err(Error::new(Span::call_site(), "Expected a string literal")),
+    }
+}
+
+/// Same as serde's `rename_all = "PascalCase"`.
+fn to_pascal_case(field_name: &str) -> String {
+    let mut pascal = String::new();
+    let mut capitalize = true;
+
+    for c in field_name.chars() {
+        if c == '_' {
+            capitalize = true;
+        } else if capitalize {
+            pascal.push(c.to_ascii_uppercase());
+            capitalize = false;
+        } else {
+            pascal.push(c);
+        }
+    }
+
+    pascal
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_converts_field_names_to_pascal_case() {
+        assert_eq!(to_pascal_case("process_id"), "ProcessId");
+        assert_eq!(to_pascal_case("caption"), "Caption");
+        assert_eq!(to_pascal_case("already_Pascal"), "AlreadyPascal");
+    }
+}